@@ -5,21 +5,26 @@ use iced::{
     border::Radius,
     font,
     widget::{
-        Id, button, column, container, operation::snap_to, pick_list, rich_text, row, scrollable,
-        scrollable::RelativeOffset, space, span, text, text::Span,
+        Id, Stack, button, column, container, operation::snap_to, pick_list, rich_text, row,
+        scrollable, scrollable::RelativeOffset, space, span, text, text::Span,
     },
 };
 
 static LOG_SCROLL_ID: std::sync::LazyLock<Id> = std::sync::LazyLock::new(Id::unique);
 
+use crate::{components::user_card::UserCard, config::AppConfig};
 use crate::{
-    components::user_card,
-    pages::{following, timeline, view},
-    utils::styling::{prim_pick_list_style, prim_pick_menu_style, tab_style},
+    components::{composer, publisher, user_card},
+    pages::{following, scheduled, settings, timeline, view},
+    twtxt::{
+        archive::{archive_feed, needs_archiving},
+        mentions::tweet_authors,
+        metadata::Link,
+    },
+    utils::styling::{prim_pick_list_style, prim_pick_menu_style, tab_style, toolbar_button_style},
 };
-use crate::{components::user_card::UserCard, config::AppConfig};
 use crate::{config::ThemeChoice, logging::LogBuffer};
-use tracing::{debug, error};
+use tracing::{debug, error, info};
 
 /// The application state (model) used by `iced`.
 ///
@@ -30,7 +35,11 @@ pub struct TwtxtApp {
     timeline: timeline::TimelinePage,
     view: view::ViewPage,
     following: following::FollowingPage,
+    scheduled: scheduled::ScheduledPage,
+    settings: settings::SettingsPage,
+    composer: composer::Composer,
     user_card: UserCard,
+    publisher: publisher::Publisher,
     log_buffer: LogBuffer,
     log_lines: Vec<String>,
 }
@@ -46,8 +55,14 @@ pub enum Message {
     SwitchToView,
     /// Switch to the following page
     SwitchToFollowing,
+    /// Switch to the scheduled posts page
+    SwitchToScheduled,
+    /// Switch to the settings page
+    SwitchToSettings,
     /// Switch to the logs page
     SwitchToLogs,
+    /// Open or close the composer
+    ToggleComposer,
     Tick,
     /// A message originating from the timeline page (forwarded)
     Timeline(timeline::Message),
@@ -55,10 +70,20 @@ pub enum Message {
     View(view::Message),
     /// A message originating from the following page (forwarded)
     Following(following::Message),
+    /// A message originating from the scheduled posts page (forwarded)
+    Scheduled(scheduled::Message),
+    /// A message originating from the settings page (forwarded)
+    Settings(settings::Message),
+    /// A message originating from the composer (forwarded)
+    Composer(composer::Message),
     /// A message originating from the user card (forwarded)
     UserCard(user_card::Message),
+    /// A message originating from the publisher (forwarded)
+    Publisher(publisher::Message),
     /// The theme has been changed
     ThemeChanged(ThemeChoice),
+    /// A file was dropped onto the window
+    FileDropped(std::path::PathBuf),
 }
 
 /// A simple top-level routing enum for the active page.
@@ -71,6 +96,10 @@ pub enum Page {
     View,
     /// Show the following list.
     Following,
+    /// Show the posts scheduled for later.
+    Scheduled,
+    /// Show the settings.
+    Settings,
     /// Show the logs.
     Logs,
 }
@@ -130,15 +159,8 @@ impl TwtxtApp {
         let config = AppConfig::load().expect("Failed to load config");
         let (timeline, timeline_task) = timeline::TimelinePage::new();
         let (view, view_task) = view::ViewPage::new(&config);
-        let (user_card, user_card_task) = UserCard::new(
-            config
-                .metadata
-                .nick
-                .clone()
-                .unwrap_or("unknown".to_string()),
-            config.metadata.urls.first().cloned(),
-            config.metadata.avatar.clone(),
-        );
+        let (scheduled, scheduled_task) = scheduled::ScheduledPage::new();
+        let (user_card, user_card_task) = UserCard::new(&config);
         (
             Self {
                 page: Page::Timeline,
@@ -146,13 +168,18 @@ impl TwtxtApp {
                 timeline,
                 view,
                 following: following::FollowingPage::default(),
+                scheduled,
+                settings: settings::SettingsPage::new(&config),
+                composer: composer::Composer::new(),
                 user_card,
+                publisher: publisher::Publisher::new(&config),
                 log_buffer,
                 log_lines: Vec::new(),
             },
             Task::batch([
                 timeline_task.map(Message::Timeline),
                 view_task.map(Message::View),
+                scheduled_task.map(Message::Scheduled),
                 user_card_task.map(Message::UserCard),
             ]),
         )
@@ -178,12 +205,29 @@ impl TwtxtApp {
                 Task::none()
             }
 
+            Message::SwitchToScheduled => {
+                self.page = Page::Scheduled;
+                debug!("Switched to scheduled page!");
+                Task::none()
+            }
+
+            Message::SwitchToSettings => {
+                self.page = Page::Settings;
+                debug!("Switched to settings page!");
+                Task::none()
+            }
+
             Message::SwitchToLogs => {
                 self.page = Page::Logs;
                 debug!("Switched to logs page!");
                 Task::none()
             }
 
+            Message::ToggleComposer => {
+                let authors = self.loaded_authors();
+                self.composer.toggle(authors).map(Message::Composer)
+            }
+
             Message::Tick => {
                 let prev_len = self.log_lines.len();
                 if let Ok(mut buf) = self.log_buffer.lock() {
@@ -211,10 +255,91 @@ impl TwtxtApp {
                 }
             }
 
-            Message::Timeline(msg) => self
-                .timeline
+            Message::Timeline(timeline::Message::Reply { subject, prefix })
+            | Message::View(view::Message::Reply { subject, prefix }) => {
+                let authors = self.loaded_authors();
+                self.composer
+                    .reply(&subject, &prefix, authors, &self.config)
+                    .map(Message::Composer)
+            }
+
+            Message::Timeline(timeline::Message::Edit { target, text }) => {
+                let authors = self.loaded_authors();
+                self.composer
+                    .edit(target, &text, authors, &self.config)
+                    .map(Message::Composer)
+            }
+
+            Message::Timeline(timeline::Message::ArchivePressed) => self.archive(),
+
+            Message::FileDropped(path) => {
+                let authors = self.loaded_authors();
+                self.composer.attach(path, authors).map(Message::Composer)
+            }
+
+            Message::Timeline(msg) => {
+                let task = self
+                    .timeline
+                    .update(msg, &self.config)
+                    .map(Message::Timeline);
+                Task::batch([task, self.publish_if_changed()])
+            }
+
+            Message::Composer(composer::Message::Posted { tweet, by_active }) => {
+                // Tweets posted as another identity go to a feed the timeline doesn't show
+                let timeline_task = if by_active {
+                    self.timeline
+                        .insert_tweet((*tweet).clone())
+                        .map(Message::Timeline)
+                } else {
+                    Task::none()
+                };
+                let view_task = self.view.insert_reply(*tweet).map(Message::View);
+                let archive_task = if by_active && needs_archiving(&self.config) {
+                    self.archive()
+                } else {
+                    Task::none()
+                };
+                Task::batch([
+                    timeline_task,
+                    view_task,
+                    archive_task,
+                    self.publish_if_changed(),
+                ])
+            }
+
+            Message::Composer(composer::Message::Edited { hash, tweet }) => {
+                let task = self
+                    .timeline
+                    .replace_tweet(&hash, *tweet)
+                    .map(Message::Timeline);
+                Task::batch([task, self.publish_if_changed()])
+            }
+
+            Message::Composer(composer::Message::Scheduled {
+                text,
+                publish_at,
+                identity,
+            }) => self
+                .scheduled
+                .add(text, publish_at, identity)
+                .map(Message::Scheduled),
+
+            Message::Composer(composer::Message::RedirectToPage(info)) => {
+                self.page = info.page.clone();
+                match self.page {
+                    Page::View => {
+                        debug!("Redirecting to view page!");
+                        self.view.process_redirect_info(info).map(Message::View)
+                    }
+                    _ => Task::none(),
+                }
+            }
+
+            Message::Composer(msg) => self
+                .composer
                 .update(msg, &self.config)
-                .map(Message::Timeline),
+                .map(Message::Composer),
 
             Message::View(view::Message::RedirectToPage(info)) => {
                 self.page = info.page.clone();
@@ -227,13 +352,40 @@ impl TwtxtApp {
                 }
             }
 
-            Message::View(msg) => self.view.update(msg).map(Message::View),
+            Message::View(msg) => self.view.update(msg, &self.config).map(Message::View),
 
             Message::Following(msg) => {
                 self.following.update(msg, &mut self.config);
-                Task::none()
+                self.publish_if_changed()
             }
 
+            Message::Settings(settings::Message::Saved) => self.reload_identity(),
+
+            Message::Settings(msg) => self
+                .settings
+                .update(msg, &mut self.config)
+                .map(Message::Settings),
+
+            Message::Scheduled(scheduled::Message::Published { tweet, by_active }) => {
+                // Tweets posted as another identity go to a feed the timeline doesn't show
+                let task = if by_active {
+                    self.timeline.insert_tweet(*tweet).map(Message::Timeline)
+                } else {
+                    Task::none()
+                };
+                let archive_task = if by_active && needs_archiving(&self.config) {
+                    self.archive()
+                } else {
+                    Task::none()
+                };
+                Task::batch([task, archive_task, self.publish_if_changed()])
+            }
+
+            Message::Scheduled(msg) => self
+                .scheduled
+                .update(msg, &self.config)
+                .map(Message::Scheduled),
+
             Message::UserCard(user_card::Message::RedirectToPage(info)) => {
                 self.page = info.page.clone();
                 match self.page {
@@ -245,8 +397,15 @@ impl TwtxtApp {
                 }
             }
 
+            Message::UserCard(user_card::Message::IdentitySelected(choice)) => match choice.index {
+                Some(index) => self.switch_identity(index),
+                None => Task::none(),
+            },
+
             Message::UserCard(msg) => self.user_card.update(msg).map(Message::UserCard),
 
+            Message::Publisher(msg) => self.publisher.update(msg).map(Message::Publisher),
+
             Message::ThemeChanged(theme) => {
                 self.config.appearance.theme = theme.clone();
                 let err = self.config.save();
@@ -254,17 +413,105 @@ impl TwtxtApp {
                     error!("Failed to save theme: {}", e);
                 }
                 debug!("Theme changed to: {}", theme.to_string());
+                self.publish_if_changed()
+            }
+        }
+    }
+
+    /// Moves old twts out of the user's feed into an archive, then publishes both.
+    fn archive(&mut self) -> Task<Message> {
+        match archive_feed(&mut self.config) {
+            Ok(Some(_)) => {
+                self.timeline.set_archive_error(None);
+                self.publish_if_changed()
+            }
+            Ok(None) => {
+                info!(
+                    "Not archiving, the feed has no more than {} twts",
+                    self.config.archive.keep_twts
+                );
+                self.timeline.set_archive_error(None);
+                Task::none()
+            }
+            Err(e) => {
+                error!("Failed to archive the feed: {}", e);
+                self.timeline
+                    .set_archive_error(Some(format!("Failed to archive the feed: {}", e)));
                 Task::none()
             }
         }
     }
 
+    /// Makes another of the user's identities the active one, and reloads everything
+    /// that depends on it.
+    fn switch_identity(&mut self, index: usize) -> Task<Message> {
+        if let Err(e) = self.config.switch_identity(index) {
+            error!("Failed to switch identity: {}", e);
+            return Task::none();
+        }
+        info!(
+            "Switched to identity {}",
+            self.config.active_identity().name()
+        );
+
+        self.following = following::FollowingPage::default();
+        self.settings = settings::SettingsPage::new(&self.config);
+        self.composer.identity_switched(&self.config);
+        self.reload_identity()
+    }
+
+    /// Reloads everything showing the active identity's profile or feed, after it was
+    /// switched or its settings changed.
+    fn reload_identity(&mut self) -> Task<Message> {
+        let (user_card, user_card_task) = UserCard::new(&self.config);
+        self.user_card = user_card;
+
+        Task::batch([
+            user_card_task.map(Message::UserCard),
+            self.timeline
+                .update(timeline::Message::Refresh, &self.config)
+                .map(Message::Timeline),
+            self.publish_if_changed(),
+        ])
+    }
+
+    /// The authors of the tweets loaded on the pages, suggested as mentions in the
+    /// composer.
+    fn loaded_authors(&self) -> Vec<Link> {
+        let mut authors = tweet_authors(self.timeline.tweets());
+        authors.extend(tweet_authors(self.view.tweets()));
+        authors
+    }
+
+    /// Publishes the user's feed if it was changed, by a post or a metadata change.
+    fn publish_if_changed(&mut self) -> Task<Message> {
+        self.publisher
+            .check_for_changes(&self.config)
+            .map(Message::Publisher)
+    }
+
     pub fn subscription(&self) -> iced::Subscription<Message> {
-        if self.page == Page::Logs {
+        let logs = if self.page == Page::Logs {
             iced::time::every(std::time::Duration::from_millis(200)).map(|_| Message::Tick)
         } else {
             iced::Subscription::none()
-        }
+        };
+
+        let scheduled = if self.scheduled.has_pending() {
+            iced::time::every(std::time::Duration::from_secs(15))
+                .map(|_| Message::Scheduled(scheduled::Message::Tick))
+        } else {
+            iced::Subscription::none()
+        };
+
+        let dropped_files = iced::event::listen_with(|event, _, _| match event {
+            iced::Event::Window(iced::window::Event::FileDropped(path)) => {
+                Some(Message::FileDropped(path))
+            }
+            _ => None,
+        });
+
+        iced::Subscription::batch([logs, scheduled, dropped_files])
     }
 
     fn view_logs(&self) -> Element<'_, Message> {
@@ -297,6 +544,15 @@ impl TwtxtApp {
         }
 
         let nav = column![
+            button(
+                text("Compose Twt")
+                    .align_x(iced::Alignment::Center)
+                    .width(Length::Fill)
+            )
+            .on_press(Message::ToggleComposer)
+            .padding([8, 16])
+            .style(toolbar_button_style)
+            .width(Length::Fill),
             button("Timeline")
                 .on_press(Message::SwitchToTimeline)
                 .padding([8, 16])
@@ -312,6 +568,16 @@ impl TwtxtApp {
                 .padding([8, 16])
                 .style(tab_style(self.page == Page::Following))
                 .width(Length::Fill),
+            button("Scheduled")
+                .on_press(Message::SwitchToScheduled)
+                .padding([8, 16])
+                .style(tab_style(self.page == Page::Scheduled))
+                .width(Length::Fill),
+            button("Settings")
+                .on_press(Message::SwitchToSettings)
+                .padding([8, 16])
+                .style(tab_style(self.page == Page::Settings))
+                .width(Length::Fill),
             button("Logs")
                 .on_press(Message::SwitchToLogs)
                 .padding([8, 16])
@@ -325,7 +591,8 @@ impl TwtxtApp {
             )
             .style(prim_pick_list_style)
             .menu_style(prim_pick_menu_style),
-            self.user_card.view().map(Message::UserCard),
+            self.publisher.view(&self.theme()).map(Message::Publisher),
+            self.user_card.view(&self.theme()).map(Message::UserCard),
             container(text(env!("BUILD_VERSION")))
                 .padding([8, 16])
                 .width(Length::Fill),
@@ -333,15 +600,37 @@ impl TwtxtApp {
         .spacing(8)
         .width(Length::Fixed(175.0));
 
-        let content = match self.page {
+        let page = match self.page {
             Page::Timeline => self.timeline.view(&self.theme()).map(Message::Timeline),
             Page::View => self.view.view(&self.theme()).map(Message::View),
             Page::Following => self.following.view(&self.config).map(Message::Following),
+            Page::Scheduled => self.scheduled.view(&self.theme()).map(Message::Scheduled),
+            Page::Settings => self.settings.view(&self.theme()).map(Message::Settings),
             Page::Logs => self.view_logs(), // We could make Logs its own separate page struct,
                                             // But it makes more sense to implement this way
                                             // Not like it owns any data and has an update fn we just give data to it
         };
 
+        // The composer is shown over whichever page is open
+        let content: Element<Message> = if self.composer.is_open() {
+            Stack::new()
+                .push(page)
+                .push(
+                    column![
+                        space().height(40),
+                        self.composer
+                            .view(&self.theme(), &self.config)
+                            .map(Message::Composer),
+                        space().height(Length::Fill)
+                    ]
+                    .width(Length::Fill)
+                    .height(Length::Fill),
+                )
+                .into()
+        } else {
+            page
+        };
+
         column![
             space().height(if cfg!(target_os = "macos") { 16 } else { 0 }),
             row![
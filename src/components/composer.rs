@@ -0,0 +1,1140 @@
+//! The composer for new tweets, replies and edits of the user's own tweets.
+//!
+//! It's shared by every page and shown over whichever one is open, so replying from a
+//! feed on the View page works the same way as replying from the timeline. Pages ask
+//! the app to open it, and the app hands posted tweets back to the pages showing them.
+
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use iced::{
+    Alignment, Element, Length, Task, Theme, keyboard,
+    widget::{
+        button, column, container, markdown, pick_list, row, scrollable, space, text, text_editor,
+        text_input,
+    },
+};
+use tracing::{debug, error, info};
+
+use crate::{
+    components::tweet::{collect_image_urls, markdown_settings},
+    config::{AppConfig, IdentityChoice},
+    publish::media::{image_markdown, pasted_image_path, upload_image},
+    twtxt::{
+        Tweet, compose_twtxt_tweet,
+        drafts::Drafts,
+        format_timestamp,
+        mentions::{format_mention, load_cached_feeds, mention_query, suggest},
+        metadata::Link,
+        preview_twtxt_tweet, rewrite_twtxt_tweet,
+        schedule::parse_local_time,
+    },
+    utils::styling::{
+        prim_button_style, prim_pick_list_style, prim_pick_menu_style, sec_button_style,
+        secondary_text, toolbar_button_style, toolbar_minput_style, toolbar_sinput_style,
+    },
+};
+
+/// How long the composer waits after the last change before saving the drafts.
+const DRAFT_SAVE_DELAY: Duration = Duration::from_millis(500);
+
+/// The state of the composer.
+pub struct Composer {
+    open: bool,
+    content: text_editor::Content,
+    /// Whether a tweet is being posted, which includes running the hook scripts.
+    posting: bool,
+    /// Why the last post failed, shown in the composer.
+    post_error: Option<String>,
+    drafts: Drafts,
+    /// The draft the composer is saving to, if anything has been written yet.
+    draft_id: Option<u64>,
+    /// Counts changes to the drafts, so only the save queued by the last one runs.
+    drafts_changes: u64,
+    /// The time entered for scheduling the composed tweet.
+    schedule_time: String,
+    /// The tweet being edited, if any.
+    editing: Option<EditTarget>,
+    /// Whether the composer shows a preview of the tweet next to it.
+    show_preview: bool,
+    /// The tweet as it would be written, rendered in the preview.
+    preview: Option<Tweet>,
+    /// Authors of the tweets loaded on the pages, offered as mentions.
+    authors: Vec<Link>,
+    /// Feeds from the parsed feed cache, offered as mentions.
+    cached_feeds: Vec<Link>,
+    /// The mention being typed, if there are feeds to suggest for it.
+    mention: Option<MentionState>,
+    /// Where the `@` of a mention whose suggestions were dismissed is, so they stay
+    /// hidden while it's being typed.
+    dismissed_mention: Option<text_editor::Position>,
+    /// The image being attached to the composed tweet, if any.
+    attachment: Option<Attachment>,
+    /// The index of the identity in [`AppConfig::identities`] to post as, or `None`
+    /// to post as the active one.
+    post_as: Option<usize>,
+}
+
+/// One of the user's own tweets being edited in the composer.
+#[derive(Debug, Clone)]
+pub struct EditTarget {
+    pub hash: String,
+    /// The timestamp as written in the feed, which the edited tweet keeps.
+    pub timestamp: String,
+    /// How many tweets from other people reply to it, and would lose their parent
+    /// once its hash changes.
+    pub replies_from_others: usize,
+}
+
+/// Suggestions for a mention being typed in the composer.
+struct MentionState {
+    /// The position of the mention's `@`.
+    at: text_editor::Position,
+    suggestions: Vec<Link>,
+    selected: usize,
+}
+
+/// An image being attached to the composed tweet.
+struct Attachment {
+    path: String,
+    alt: String,
+    uploading: bool,
+    /// Why the last upload failed.
+    error: Option<String>,
+}
+
+/// Messages used to update the composer.
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// The composer text was edited.
+    ComposerEdit(text_editor::Action),
+    /// Close the composer, keeping what was written as a draft.
+    CancelCompose,
+    /// Close the composer and delete its draft.
+    DiscardDraft,
+    /// Load a saved draft into the composer.
+    ResumeDraft(u64),
+    /// Delete a saved draft.
+    DeleteDraft(u64),
+    /// Save the drafts, if they haven't changed again since the save was queued.
+    SaveDrafts(u64),
+    /// The drafts were saved, or saving them failed.
+    DraftsSaved(Result<(), String>),
+    /// Move the highlighted mention suggestion up or down.
+    MentionMoved(isize),
+    /// Replace the mention being typed with the suggestion at the given index.
+    MentionPicked(usize),
+    /// Hide the mention suggestions.
+    MentionDismissed,
+    /// The feeds in the parsed feed cache were read.
+    CachedFeedsLoaded(Vec<Link>),
+    /// Show or hide the preview next to the composer.
+    TogglePreview,
+    /// A link in the preview was clicked.
+    LinkClicked(String),
+    /// Start attaching an image to the composed tweet.
+    AttachPressed,
+    /// The path of the image being attached changed.
+    AttachPathChanged(String),
+    /// The alt text of the image being attached changed.
+    AttachAltChanged(String),
+    /// Upload the image being attached.
+    UploadPressed,
+    /// The image was uploaded to the given URL, or uploading it failed.
+    ImageUploaded(Result<String, String>),
+    /// Stop attaching the image.
+    CancelAttach,
+    /// The user picked the identity to post as.
+    PostAsSelected(IdentityChoice),
+    /// Post the composed tweet.
+    PostPressed,
+    /// The composed tweet was written to the feed, or posting it failed.
+    PostFinished {
+        result: Box<Result<Tweet, String>>,
+        by_active: bool,
+    },
+    /// The edited tweet was rewritten in the feed, or rewriting it failed.
+    RewriteFinished {
+        hash: String,
+        result: Box<Result<Option<Tweet>, String>>,
+    },
+    /// The time to schedule the composed tweet for changed.
+    ScheduleTimeChanged(String),
+    /// Schedule the composed tweet instead of posting it now.
+    SchedulePressed,
+    /// A tweet was posted. Handled by the app to show it on the pages.
+    Posted {
+        tweet: Box<Tweet>,
+        /// Whether it was posted as the active identity, rather than another one.
+        by_active: bool,
+    },
+    /// One of the user's own tweets was edited. Handled by the app to update the
+    /// timeline.
+    Edited {
+        hash: String,
+        tweet: Box<Option<Tweet>>,
+    },
+    /// A tweet was scheduled. Handled by the app, which owns the queue.
+    Scheduled {
+        text: String,
+        publish_at: DateTime<Utc>,
+        /// The feed path of the identity to post as.
+        identity: String,
+    },
+    /// Trigger a navigation to another page.
+    RedirectToPage(crate::app::RedirectInfo),
+}
+
+impl Composer {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            content: text_editor::Content::new(),
+            posting: false,
+            post_error: None,
+            drafts: Drafts::load(),
+            draft_id: None,
+            drafts_changes: 0,
+            schedule_time: String::new(),
+            editing: None,
+            show_preview: false,
+            preview: None,
+            authors: Vec::new(),
+            cached_feeds: Vec::new(),
+            mention: None,
+            dismissed_mention: None,
+            attachment: None,
+            post_as: None,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Opens the composer, or hides it if it's open.
+    ///
+    /// `authors` are the authors of the tweets loaded on the pages, which are suggested
+    /// as mentions along with the followed and cached feeds.
+    pub fn toggle(&mut self, authors: Vec<Link>) -> Task<Message> {
+        if self.open {
+            self.open = false;
+            return Task::none();
+        }
+        self.show(authors)
+    }
+
+    /// Starts a reply in the thread with `subject`, beginning with `prefix`.
+    ///
+    /// Picks up where an earlier reply in the same thread was left off, if there is one.
+    pub fn reply(
+        &mut self,
+        subject: &str,
+        prefix: &str,
+        authors: Vec<Link>,
+        config: &AppConfig,
+    ) -> Task<Message> {
+        if self.editing.is_some() {
+            self.close();
+        }
+
+        let show_task = self.show(authors);
+        match self.drafts.for_reply(subject).map(|draft| draft.id) {
+            Some(id) => self.resume_draft(id, config),
+            None => {
+                self.post_error = None;
+                self.draft_id = None;
+                self.content = text_editor::Content::with_text(prefix);
+                self.update_preview(config);
+            }
+        }
+        show_task
+    }
+
+    /// Starts editing one of the user's own tweets, whose text is `text`.
+    pub fn edit(
+        &mut self,
+        target: EditTarget,
+        text: &str,
+        authors: Vec<Link>,
+        config: &AppConfig,
+    ) -> Task<Message> {
+        let show_task = self.show(authors);
+        self.content = text_editor::Content::with_text(text);
+        self.editing = Some(target);
+        self.draft_id = None;
+        self.post_error = None;
+        self.update_preview(config);
+        show_task
+    }
+
+    /// Goes back to posting as the active identity, after the user switched identities
+    /// and the indices of the others changed.
+    pub fn identity_switched(&mut self, config: &AppConfig) {
+        self.post_as = None;
+        self.update_preview(config);
+    }
+
+    /// Opens the composer to attach the image at `path`, keeping any alt text already
+    /// written for the previous one.
+    pub fn attach(&mut self, path: PathBuf, authors: Vec<Link>) -> Task<Message> {
+        let show_task = self.show(authors);
+        match &mut self.attachment {
+            Some(attachment) if attachment.uploading => {}
+            Some(attachment) => {
+                attachment.path = path.display().to_string();
+                attachment.error = None;
+            }
+            None => {
+                self.attachment = Some(Attachment {
+                    path: path.display().to_string(),
+                    alt: String::new(),
+                    uploading: false,
+                    error: None,
+                });
+            }
+        }
+        show_task
+    }
+
+    pub fn update(&mut self, message: Message, config: &AppConfig) -> Task<Message> {
+        match message {
+            Message::ComposerEdit(action) => {
+                // Pasting a copied image file attaches it instead of pasting its path
+                if let text_editor::Action::Edit(text_editor::Edit::Paste(pasted)) = &action
+                    && let Some(path) = pasted_image_path(pasted)
+                {
+                    return self.attach(path, self.authors.clone());
+                }
+
+                let is_edit = action.is_edit();
+                self.content.perform(action);
+                self.update_mention(config);
+                self.update_preview(config);
+                if is_edit {
+                    self.save_draft()
+                } else {
+                    Task::none()
+                }
+            }
+
+            Message::MentionMoved(delta) => {
+                if let Some(mention) = &mut self.mention {
+                    let len = mention.suggestions.len() as isize;
+                    mention.selected = (mention.selected as isize + delta).rem_euclid(len) as usize;
+                }
+                Task::none()
+            }
+
+            Message::MentionPicked(index) => {
+                let Some(mention) = self.mention.take() else {
+                    return Task::none();
+                };
+                let Some(feed) = mention.suggestions.get(index) else {
+                    return Task::none();
+                };
+
+                // Select from the `@` to the cursor, and paste the mention over it
+                let cursor = self.content.cursor();
+                self.content.move_to(text_editor::Cursor {
+                    position: cursor.position,
+                    selection: Some(mention.at),
+                });
+                self.content
+                    .perform(text_editor::Action::Edit(text_editor::Edit::Paste(
+                        Arc::new(format_mention(feed)),
+                    )));
+
+                self.update_preview(config);
+                self.save_draft()
+            }
+
+            Message::MentionDismissed => {
+                self.dismissed_mention = self.mention.take().map(|m| m.at);
+                Task::none()
+            }
+
+            Message::CachedFeedsLoaded(feeds) => {
+                self.cached_feeds = feeds;
+                Task::none()
+            }
+
+            Message::TogglePreview => {
+                self.show_preview = !self.show_preview;
+                self.update_preview(config);
+                Task::none()
+            }
+
+            Message::LinkClicked(url) => {
+                if url.contains("twtxt") && url.ends_with(".txt") {
+                    Task::done(Message::RedirectToPage(crate::app::RedirectInfo {
+                        page: crate::app::Page::View,
+                        content: url,
+                    }))
+                } else {
+                    debug!("Composer: opening URL: {}", url);
+                    if let Err(err) = webbrowser::open(&url) {
+                        error!("Composer: error opening URL: {}", err);
+                    }
+                    Task::none()
+                }
+            }
+
+            Message::AttachPressed => {
+                if self.attachment.is_none() {
+                    self.attachment = Some(Attachment {
+                        path: String::new(),
+                        alt: String::new(),
+                        uploading: false,
+                        error: None,
+                    });
+                }
+                Task::none()
+            }
+
+            Message::AttachPathChanged(path) => {
+                if let Some(attachment) = &mut self.attachment {
+                    attachment.path = path;
+                }
+                Task::none()
+            }
+
+            Message::AttachAltChanged(alt) => {
+                if let Some(attachment) = &mut self.attachment {
+                    attachment.alt = alt;
+                }
+                Task::none()
+            }
+
+            Message::UploadPressed => {
+                let Some(attachment) = &mut self.attachment else {
+                    return Task::none();
+                };
+                if attachment.uploading || attachment.path.trim().is_empty() {
+                    return Task::none();
+                }
+
+                attachment.uploading = true;
+                attachment.error = None;
+                Task::perform(
+                    upload_image(PathBuf::from(attachment.path.trim()), config.media.clone()),
+                    Message::ImageUploaded,
+                )
+            }
+
+            Message::ImageUploaded(result) => {
+                // The attachment is gone if it was cancelled while uploading
+                let Some(attachment) = &mut self.attachment else {
+                    return Task::none();
+                };
+
+                match result {
+                    Ok(url) => {
+                        let markdown = image_markdown(&attachment.alt, &url);
+                        self.attachment = None;
+                        self.content
+                            .perform(text_editor::Action::Edit(text_editor::Edit::Paste(
+                                Arc::new(markdown),
+                            )));
+                        self.update_preview(config);
+                        self.save_draft()
+                    }
+                    Err(e) => {
+                        error!("Composer: failed to upload image: {}", e);
+                        attachment.uploading = false;
+                        attachment.error = Some(e);
+                        Task::none()
+                    }
+                }
+            }
+
+            Message::CancelAttach => {
+                self.attachment = None;
+                Task::none()
+            }
+
+            Message::CancelCompose => {
+                self.close();
+                Task::none()
+            }
+
+            Message::DiscardDraft => {
+                let save = self.remove_draft();
+                self.close();
+                save
+            }
+
+            Message::ResumeDraft(id) => {
+                self.resume_draft(id, config);
+                Task::none()
+            }
+
+            Message::DeleteDraft(id) => {
+                self.drafts.remove(id);
+                if self.draft_id == Some(id) {
+                    self.draft_id = None;
+                }
+                self.queue_drafts_save()
+            }
+
+            Message::SaveDrafts(change) => {
+                if change != self.drafts_changes {
+                    return Task::none();
+                }
+                Task::perform(self.drafts.save(), Message::DraftsSaved)
+            }
+
+            Message::DraftsSaved(result) => {
+                if let Err(e) = result {
+                    error!("Composer: failed to save drafts: {}", e);
+                }
+                Task::none()
+            }
+
+            Message::PostAsSelected(choice) => {
+                self.post_as = choice.index;
+                self.update_preview(config);
+                Task::none()
+            }
+
+            Message::PostPressed => {
+                self.posting = true;
+                self.post_error = None;
+
+                if let Some(hash) = self.editing.as_ref().map(|e| e.hash.clone()) {
+                    return Task::perform(
+                        rewrite_twtxt_tweet(
+                            hash.clone(),
+                            Some(self.content.text()),
+                            config.clone(),
+                        ),
+                        move |result| Message::RewriteFinished {
+                            hash: hash.clone(),
+                            result: Box::new(result),
+                        },
+                    );
+                }
+
+                let by_active = self.post_as.is_none();
+                Task::perform(
+                    compose_twtxt_tweet(self.content.text(), self.posting_config(config)),
+                    move |result| Message::PostFinished {
+                        result: Box::new(result),
+                        by_active,
+                    },
+                )
+            }
+
+            Message::PostFinished { result, by_active } => {
+                self.posting = false;
+                match *result {
+                    Ok(tweet) => {
+                        let save = self.remove_draft();
+                        self.close();
+                        Task::batch([
+                            save,
+                            Task::done(Message::Posted {
+                                tweet: Box::new(tweet),
+                                by_active,
+                            }),
+                        ])
+                    }
+                    Err(e) => {
+                        error!("Composer: failed to post: {}", e);
+                        self.post_error = Some(e);
+                        Task::none()
+                    }
+                }
+            }
+
+            Message::RewriteFinished { hash, result } => {
+                self.posting = false;
+                match *result {
+                    Ok(tweet) => {
+                        info!("Composer: rewrote #{}", hash);
+                        self.close();
+                        Task::done(Message::Edited {
+                            hash,
+                            tweet: Box::new(tweet),
+                        })
+                    }
+                    Err(e) => {
+                        error!("Composer: failed to rewrite #{}: {}", hash, e);
+                        self.post_error = Some(e);
+                        Task::none()
+                    }
+                }
+            }
+
+            Message::ScheduleTimeChanged(v) => {
+                self.schedule_time = v;
+                Task::none()
+            }
+
+            Message::SchedulePressed => {
+                let text = self.content.text();
+                if text.trim().is_empty() {
+                    self.post_error = Some("Can't schedule an empty twt".to_string());
+                    return Task::none();
+                }
+
+                let publish_at = match parse_local_time(&self.schedule_time) {
+                    Ok(t) if t <= Utc::now() => {
+                        self.post_error = Some("Pick a time in the future".to_string());
+                        return Task::none();
+                    }
+                    Ok(t) => t,
+                    Err(e) => {
+                        self.post_error = Some(e);
+                        return Task::none();
+                    }
+                };
+
+                let identity = self.posting_config(config).paths.twtxt;
+                let save = self.remove_draft();
+                self.close();
+                self.schedule_time.clear();
+                Task::batch([
+                    save,
+                    Task::done(Message::Scheduled {
+                        text,
+                        publish_at,
+                        identity,
+                    }),
+                ])
+            }
+
+            Message::Posted { .. } | Message::Edited { .. } | Message::Scheduled { .. } => {
+                Task::none()
+            }
+
+            Message::RedirectToPage(info) => Task::done(Message::RedirectToPage(info)),
+        }
+    }
+
+    /// Shows the composer, reloading the feeds suggested as mentions.
+    fn show(&mut self, authors: Vec<Link>) -> Task<Message> {
+        self.open = true;
+        self.authors = authors;
+        Task::perform(load_cached_feeds(), Message::CachedFeedsLoaded)
+    }
+
+    /// Closes and clears the composer. Whatever was written stays in its draft.
+    fn close(&mut self) {
+        self.open = false;
+        self.post_error = None;
+        self.content = text_editor::Content::new();
+        self.draft_id = None;
+        self.editing = None;
+        self.preview = None;
+        self.mention = None;
+        self.dismissed_mention = None;
+        self.attachment = None;
+        self.post_as = None;
+    }
+
+    /// The config of the identity the composed tweet is posted as.
+    fn posting_config(&self, config: &AppConfig) -> AppConfig {
+        self.post_as
+            .and_then(|index| config.as_identity(index))
+            .unwrap_or_else(|| config.clone())
+    }
+
+    fn resume_draft(&mut self, id: u64, config: &AppConfig) {
+        if let Some(draft) = self.drafts.get(id) {
+            self.open = true;
+            self.post_error = None;
+            self.editing = None;
+            self.content = text_editor::Content::with_text(&draft.text);
+            self.draft_id = Some(id);
+            self.update_preview(config);
+        }
+    }
+
+    /// Updates the draft with the composer text and queues saving it. Edits of posted
+    /// tweets aren't drafts, since they'd be posted as new ones.
+    fn save_draft(&mut self) -> Task<Message> {
+        if self.editing.is_some() {
+            return Task::none();
+        }
+        self.draft_id = self.drafts.update(self.draft_id, &self.content.text());
+        self.queue_drafts_save()
+    }
+
+    /// Removes the draft of the composed tweet, if it has one.
+    fn remove_draft(&mut self) -> Task<Message> {
+        match self.draft_id.take() {
+            Some(id) => {
+                self.drafts.remove(id);
+                self.queue_drafts_save()
+            }
+            None => Task::none(),
+        }
+    }
+
+    /// Saves the drafts once they haven't changed for [`DRAFT_SAVE_DELAY`], rather than
+    /// rewriting the file on every keystroke.
+    fn queue_drafts_save(&mut self) -> Task<Message> {
+        self.drafts_changes += 1;
+        let change = self.drafts_changes;
+        Task::perform(tokio::time::sleep(DRAFT_SAVE_DELAY), move |_| {
+            Message::SaveDrafts(change)
+        })
+    }
+
+    /// Rebuilds the preview from the composer text, if it's shown.
+    fn update_preview(&mut self, config: &AppConfig) {
+        let text = self.content.text();
+        if !self.show_preview || text.trim().is_empty() {
+            self.preview = None;
+            return;
+        }
+
+        // New tweets get the time they're posted at, so their hash is only a guess
+        let timestamp = match &self.editing {
+            Some(target) => target.timestamp.clone(),
+            None => format_timestamp(Utc::now()),
+        };
+        self.preview = match preview_twtxt_tweet(&text, &timestamp, &self.posting_config(config)) {
+            Ok(tweet) => Some(tweet),
+            Err(e) => {
+                error!("Composer: failed to preview: {}", e);
+                None
+            }
+        };
+    }
+
+    /// Looks for a mention being typed at the cursor, and suggests feeds for it.
+    fn update_mention(&mut self, config: &AppConfig) {
+        let cursor = self.content.cursor();
+        let position = cursor.position;
+
+        let found = self
+            .content
+            .line(position.line)
+            .filter(|_| cursor.selection.is_none())
+            .and_then(|line| {
+                let (at, query) = mention_query(&line.text, position.column)?;
+                Some((at, query.to_string()))
+            });
+        let Some((at, query)) = found else {
+            self.mention = None;
+            self.dismissed_mention = None;
+            return;
+        };
+
+        let at = text_editor::Position {
+            line: position.line,
+            column: at,
+        };
+        if self.dismissed_mention == Some(at) {
+            return;
+        }
+
+        let suggestions: Vec<Link> = suggest(
+            &query,
+            &[&config.metadata.follows, &self.authors, &self.cached_feeds],
+        )
+        .into_iter()
+        .cloned()
+        .collect();
+
+        if suggestions.is_empty() {
+            self.mention = None;
+            return;
+        }
+
+        // Keep the highlight where it was while the same mention is being typed
+        let selected = match &self.mention {
+            Some(mention) if mention.at == at => mention.selected.min(suggestions.len() - 1),
+            _ => 0,
+        };
+        self.mention = Some(MentionState {
+            at,
+            suggestions,
+            selected,
+        });
+    }
+
+    pub fn view(&self, theme: &Theme, config: &AppConfig) -> Element<'_, Message> {
+        let post_error: Element<Message> = match &self.post_error {
+            Some(e) => text(e).color(theme.palette().danger).into(),
+            None => space().into(),
+        };
+
+        container(
+            column![
+                row![
+                    text_editor(&self.content)
+                        .placeholder("What's on your mind?")
+                        .on_action(Message::ComposerEdit)
+                        .key_binding(mention_key_binding(
+                            self.mention.as_ref().map(|m| m.selected)
+                        ))
+                        .height(300)
+                        .padding(8)
+                        .style(toolbar_minput_style),
+                ]
+                .push(self.show_preview.then(|| {
+                    container(scrollable(self.view_preview(theme)))
+                        .width(Length::Fill)
+                        .height(300)
+                        .padding(8)
+                }))
+                .spacing(8),
+                self.view_mentions(theme),
+                self.view_attachment(theme),
+                post_error,
+                self.orphan_warning(theme),
+                row![
+                    text_input("Schedule for YYYY-MM-DD HH:MM", &self.schedule_time)
+                        .on_input(Message::ScheduleTimeChanged)
+                        .on_submit(Message::SchedulePressed)
+                        .width(Length::FillPortion(2))
+                        .padding(8)
+                        .style(toolbar_sinput_style),
+                    button(
+                        text("Schedule")
+                            .align_x(Alignment::Center)
+                            .width(Length::Fill)
+                    )
+                    .on_press_maybe(
+                        (!self.posting
+                            && self.editing.is_none()
+                            && !self.schedule_time.trim().is_empty())
+                        .then_some(Message::SchedulePressed)
+                    )
+                    .width(Length::Fill)
+                    .padding([8, 16])
+                    .style(toolbar_button_style),
+                ]
+                .spacing(8)
+                .width(Length::Fill),
+                self.view_post_as(config),
+                row![
+                    button(
+                        text(match (self.posting, self.editing.is_some()) {
+                            (true, true) => "Saving...",
+                            (true, false) => "Posting...",
+                            (false, true) => "Save",
+                            (false, false) => "Post",
+                        })
+                        .align_x(Alignment::Center)
+                        .width(Length::Fill)
+                    )
+                    .on_press_maybe((!self.posting).then_some(Message::PostPressed))
+                    .width(Length::Fill)
+                    .padding([8, 16])
+                    .style(toolbar_button_style),
+                    button(
+                        text("Cancel")
+                            .align_x(Alignment::Center)
+                            .width(Length::Fill)
+                    )
+                    .on_press(Message::CancelCompose)
+                    .width(Length::Fill)
+                    .padding([8, 16])
+                    .style(toolbar_button_style),
+                    button(
+                        text("Discard")
+                            .align_x(Alignment::Center)
+                            .width(Length::Fill)
+                    )
+                    .on_press_maybe(self.draft_id.map(|_| Message::DiscardDraft))
+                    .width(Length::Fill)
+                    .padding([8, 16])
+                    .style(toolbar_button_style),
+                    button(
+                        text(if self.show_preview {
+                            "Hide preview"
+                        } else {
+                            "Preview"
+                        })
+                        .align_x(Alignment::Center)
+                        .width(Length::Fill)
+                    )
+                    .on_press(Message::TogglePreview)
+                    .width(Length::Fill)
+                    .padding([8, 16])
+                    .style(toolbar_button_style),
+                    button(
+                        text("Attach image")
+                            .align_x(Alignment::Center)
+                            .width(Length::Fill)
+                    )
+                    .on_press_maybe(self.attachment.is_none().then_some(Message::AttachPressed))
+                    .width(Length::Fill)
+                    .padding([8, 16])
+                    .style(toolbar_button_style),
+                ]
+                .spacing(8)
+                .width(Length::Fill),
+                self.view_drafts(theme),
+            ]
+            .spacing(8),
+        )
+        .width(Length::Fill)
+        .height(Length::Shrink)
+        .into()
+    }
+
+    /// The picker for the identity to post as, if the user has more than one. Edited
+    /// tweets stay in the feed they were posted to.
+    fn view_post_as(&self, config: &AppConfig) -> Element<'_, Message> {
+        let choices = config.identity_choices();
+        if choices.len() < 2 || self.editing.is_some() {
+            return space().into();
+        }
+
+        let selected = choices
+            .iter()
+            .find(|choice| choice.index == self.post_as)
+            .cloned();
+        row![
+            text("Post as"),
+            pick_list(choices, selected, Message::PostAsSelected)
+                .width(Length::Fill)
+                .style(prim_pick_list_style)
+                .menu_style(prim_pick_menu_style),
+        ]
+        .spacing(8)
+        .align_y(Alignment::Center)
+        .into()
+    }
+
+    /// The composed tweet rendered like it would be on the timeline, along with the
+    /// hash and subject it would be written with.
+    fn view_preview(&self, theme: &Theme) -> Element<'_, Message> {
+        let Some(tweet) = &self.preview else {
+            return text("Nothing to preview yet")
+                .color(secondary_text(theme))
+                .into();
+        };
+
+        let images = collect_image_urls(&tweet.md_items)
+            .into_iter()
+            .map(|url| {
+                text(format!("Image: {}", url))
+                    .size(12)
+                    .color(secondary_text(theme))
+                    .into()
+            })
+            .collect::<Vec<Element<Message>>>();
+
+        let hash = if self.editing.is_some() {
+            format!("Hash: #{}", tweet.hash)
+        } else {
+            format!("Hash if posted now: #{}", tweet.hash)
+        };
+        let subject = match &tweet.reply_to {
+            Some(reply_to) => format!("Subject: (#{})", reply_to),
+            None => "No subject, starts a new thread".to_string(),
+        };
+
+        column![
+            markdown::view(&tweet.md_items, markdown_settings(theme)).map(Message::LinkClicked),
+            column(images).spacing(2),
+            text(hash).size(12).color(secondary_text(theme)),
+            text(subject).size(12).color(secondary_text(theme)),
+        ]
+        .spacing(8)
+        .into()
+    }
+
+    /// The list of feeds that can be mentioned, shown under the composer.
+    fn view_mentions(&self, theme: &Theme) -> Element<'_, Message> {
+        let Some(mention) = &self.mention else {
+            return space().into();
+        };
+
+        let rows = mention
+            .suggestions
+            .iter()
+            .enumerate()
+            .map(|(i, feed)| {
+                button(
+                    row![
+                        text(&feed.text).font(crate::app::BOLD_FONT),
+                        text(&feed.url).size(12).color(secondary_text(theme)),
+                    ]
+                    .spacing(8)
+                    .align_y(Alignment::Center),
+                )
+                .on_press(Message::MentionPicked(i))
+                .width(Length::Fill)
+                .padding([4, 8])
+                .style(if i == mention.selected {
+                    prim_button_style
+                } else {
+                    sec_button_style
+                })
+                .into()
+            })
+            .collect::<Vec<Element<Message>>>();
+
+        column(rows).spacing(2).into()
+    }
+
+    /// The row for choosing an image to attach and describing it.
+    fn view_attachment(&self, theme: &Theme) -> Element<'_, Message> {
+        let Some(attachment) = &self.attachment else {
+            return space().into();
+        };
+
+        let error: Element<Message> = match &attachment.error {
+            Some(e) => text(e).color(theme.palette().danger).into(),
+            None => space().into(),
+        };
+
+        column![
+            row![
+                text_input(
+                    "Path to a PNG, JPEG or WebP image, or drop one here",
+                    &attachment.path
+                )
+                .on_input(Message::AttachPathChanged)
+                .width(Length::FillPortion(2))
+                .padding(8)
+                .style(toolbar_sinput_style),
+                text_input("Describe the image", &attachment.alt)
+                    .on_input(Message::AttachAltChanged)
+                    .on_submit(Message::UploadPressed)
+                    .width(Length::FillPortion(2))
+                    .padding(8)
+                    .style(toolbar_sinput_style),
+                button(
+                    text(if attachment.uploading {
+                        "Uploading..."
+                    } else {
+                        "Upload"
+                    })
+                    .align_x(Alignment::Center)
+                    .width(Length::Fill)
+                )
+                .on_press_maybe(
+                    (!attachment.uploading && !attachment.path.trim().is_empty())
+                        .then_some(Message::UploadPressed)
+                )
+                .width(Length::Fill)
+                .padding([8, 16])
+                .style(toolbar_button_style),
+                button(
+                    text("Cancel")
+                        .align_x(Alignment::Center)
+                        .width(Length::Fill)
+                )
+                .on_press(Message::CancelAttach)
+                .width(Length::Fill)
+                .padding([8, 16])
+                .style(toolbar_button_style),
+            ]
+            .spacing(8)
+            .width(Length::Fill),
+            error,
+        ]
+        .spacing(8)
+        .into()
+    }
+
+    /// A warning about replies that would be orphaned by changing the edited tweet.
+    fn orphan_warning(&self, theme: &Theme) -> Element<'_, Message> {
+        match self.editing.as_ref().map(|e| e.replies_from_others) {
+            None | Some(0) => space().into(),
+            Some(n) => text(orphan_warning_text(n))
+                .color(theme.extended_palette().warning.base.color)
+                .into(),
+        }
+    }
+
+    /// Lists the saved drafts other than the one being edited.
+    fn view_drafts(&self, theme: &Theme) -> Element<'_, Message> {
+        let drafts: Vec<Element<Message>> = self
+            .drafts
+            .list()
+            .iter()
+            .filter(|draft| Some(draft.id) != self.draft_id)
+            .map(|draft| {
+                let mut details = draft
+                    .updated_at
+                    .with_timezone(&chrono::Local)
+                    .format("%b %-d, %-I:%M %p")
+                    .to_string();
+                if let Some(reply) = &draft.reply {
+                    details = format!("Reply to #{} · {}", reply.hash, details);
+                }
+
+                row![
+                    column![
+                        text(draft.preview()),
+                        text(details).size(12).color(secondary_text(theme)),
+                    ]
+                    .spacing(2)
+                    .width(Length::Fill),
+                    button("Resume")
+                        .on_press(Message::ResumeDraft(draft.id))
+                        .padding([4, 8])
+                        .style(prim_button_style),
+                    button("Delete")
+                        .on_press(Message::DeleteDraft(draft.id))
+                        .padding([4, 8])
+                        .style(prim_button_style),
+                ]
+                .spacing(8)
+                .align_y(Alignment::Center)
+                .into()
+            })
+            .collect();
+
+        if drafts.is_empty() {
+            return space().into();
+        }
+
+        column![
+            text("Drafts").font(crate::app::BOLD_FONT),
+            container(scrollable(column(drafts).spacing(6))).max_height(160),
+        ]
+        .spacing(4)
+        .into()
+    }
+}
+
+/// Explains that `n` replies from other people would no longer be threaded under a
+/// tweet once it's edited or deleted.
+pub fn orphan_warning_text(n: usize) -> String {
+    format!(
+        "{} {} from other people point to this twt. Changing it changes its hash, \
+         so {} will no longer be threaded under it.",
+        n,
+        if n == 1 { "reply" } else { "replies" },
+        if n == 1 { "it" } else { "they" },
+    )
+}
+
+/// Lets the mention suggestions be picked with the keyboard while they're shown.
+///
+/// `selected` is the highlighted suggestion, or `None` when there are no suggestions,
+/// in which case every key does what it normally does.
+fn mention_key_binding(
+    selected: Option<usize>,
+) -> impl Fn(text_editor::KeyPress) -> Option<text_editor::Binding<Message>> {
+    move |key_press| {
+        use keyboard::key::Named;
+
+        // Other editors get key presses too, but only the focused one should react
+        let focused = matches!(key_press.status, text_editor::Status::Focused { .. });
+        let binding = match (selected.filter(|_| focused), key_press.key.as_ref()) {
+            (Some(_), keyboard::Key::Named(Named::ArrowUp)) => Some(Message::MentionMoved(-1)),
+            (Some(_), keyboard::Key::Named(Named::ArrowDown)) => Some(Message::MentionMoved(1)),
+            (Some(i), keyboard::Key::Named(Named::Enter | Named::Tab)) => {
+                Some(Message::MentionPicked(i))
+            }
+            (Some(_), keyboard::Key::Named(Named::Escape)) => Some(Message::MentionDismissed),
+            _ => None,
+        };
+
+        match binding {
+            Some(message) => Some(text_editor::Binding::Custom(message)),
+            None => text_editor::Binding::from_key_press(key_press),
+        }
+    }
+}
@@ -1,4 +1,6 @@
+pub mod composer;
 pub mod og_embed;
+pub mod publisher;
 pub mod threaded_feed;
 pub mod tweet;
 pub mod user_card;
@@ -1,17 +1,19 @@
 //! OpenGraph embed card component.
 
 use crate::utils::{
-    download::download_binary,
+    media::{DecodedImage, MediaError, load_thumbnail, too_large_placeholder},
     styling::{prim_button_style, secondary_text},
 };
-use bytes::Bytes;
 use iced::{
     Element, Length, Task, Theme,
-    widget::{Image, column, container, image::Handle, row, text},
+    widget::{Image, column, container, row, text},
 };
 use opengraph::Object;
 use reqwest::Url;
-use tracing::error;
+use tracing::{error, warn};
+
+/// The size of the box preview images are shrunk to fit.
+const IMAGE_MAX_SIZE: u32 = 200;
 
 /// Messages emitted by the embed card component.
 #[derive(Debug, Clone)]
@@ -19,7 +21,7 @@ pub enum Message {
     /// The embed was clicked.
     Clicked(String),
     /// The preview image finished downloading.
-    ImageLoaded(Box<Result<Bytes, String>>),
+    ImageLoaded(Box<Result<DecodedImage, MediaError>>),
 }
 
 #[derive(Clone)]
@@ -29,11 +31,18 @@ pub struct OgEmbedComponent {
     site_name: String,
     url: String,
     image_url: Option<String>,
-    image_handle: Option<(Handle, u32, u32)>,
+    image: Option<DecodedImage>,
+    image_requested: bool,
+    /// Whether the preview image was over the size limits.
+    image_too_large: bool,
 }
 
 impl OgEmbedComponent {
-    pub fn new(obj: &Object, url: &str) -> (Self, Task<Message>) {
+    /// Creates the embed card.
+    ///
+    /// The preview image isn't downloaded until [`Self::load_image`] is called, so the
+    /// caller can decide whether it may be loaded.
+    pub fn new(obj: &Object, url: &str) -> Self {
         let obj_url = if obj.url.is_empty() {
             url.to_string()
         } else {
@@ -51,33 +60,46 @@ impl OgEmbedComponent {
             }
         });
 
-        let task = match &image_url {
-            Some(url) => {
-                let url = url.clone();
-                Task::perform(download_binary(url), |res| {
-                    Message::ImageLoaded(Box::new(res))
-                })
+        Self {
+            title: obj.title.clone(),
+            description: obj.description.clone().unwrap_or_default(),
+            site_name: obj.site_name.clone().unwrap_or_else(|| {
+                // Fall back to hostname from URL
+                Url::parse(&obj_url)
+                    .ok()
+                    .and_then(|u| u.host_str().map(|h| h.to_string()))
+                    .unwrap_or_default()
+            }),
+            url: url.to_string(),
+            image_url,
+            image: None,
+            image_requested: false,
+            image_too_large: false,
+        }
+    }
+
+    /// Starts downloading the preview image, if there is one and it hasn't been yet.
+    pub fn load_image(&mut self) -> Task<Message> {
+        match &self.image_url {
+            Some(url) if !self.image_requested => {
+                self.image_requested = true;
+                Task::perform(
+                    load_thumbnail(url.clone(), IMAGE_MAX_SIZE, IMAGE_MAX_SIZE),
+                    |res| Message::ImageLoaded(Box::new(res)),
+                )
             }
-            None => Task::none(),
-        };
+            _ => Task::none(),
+        }
+    }
 
-        (
-            Self {
-                title: obj.title.clone(),
-                description: obj.description.clone().unwrap_or_default(),
-                site_name: obj.site_name.clone().unwrap_or_else(|| {
-                    // Fall back to hostname from URL
-                    Url::parse(&obj_url)
-                        .ok()
-                        .and_then(|u| u.host_str().map(|h| h.to_string()))
-                        .unwrap_or_default()
-                }),
-                url: url.to_string(),
-                image_url,
-                image_handle: None,
-            },
-            task,
-        )
+    /// Returns whether the embed has a preview image that hasn't been requested yet.
+    pub fn image_pending(&self) -> bool {
+        self.image_url.is_some() && !self.image_requested
+    }
+
+    /// The URL of the preview image, if any.
+    pub fn image_url(&self) -> Option<&str> {
+        self.image_url.as_deref()
     }
 
     pub fn update(&mut self, message: Message) -> Task<Message> {
@@ -85,14 +107,14 @@ impl OgEmbedComponent {
             Message::Clicked(url) => Task::done(Message::Clicked(url)),
             Message::ImageLoaded(result) => {
                 match *result {
-                    Ok(bytes) => {
-                        let dims = image::load_from_memory(&bytes)
-                            .ok()
-                            .map(|img| (img.width(), img.height()));
-                        self.image_handle = Some(match dims {
-                            Some((w, h)) => (Handle::from_bytes(bytes), w, h),
-                            None => (Handle::from_bytes(bytes), 0, 0),
-                        });
+                    Ok(image) => self.image = Some(image),
+                    Err(e @ MediaError::TooLarge(_)) => {
+                        warn!(
+                            "OgEmbed: not showing image {}: {}",
+                            self.image_url.as_deref().unwrap_or("?"),
+                            e
+                        );
+                        self.image_too_large = true;
                     }
                     Err(e) => {
                         error!(
@@ -108,13 +130,13 @@ impl OgEmbedComponent {
     }
 
     pub fn view(&self, theme: &Theme) -> Element<'_, Message> {
-        const MAX_WIDTH: f32 = 200.0;
-        const MAX_HEIGHT: f32 = 200.0;
+        const MAX_WIDTH: f32 = IMAGE_MAX_SIZE as f32;
+        const MAX_HEIGHT: f32 = IMAGE_MAX_SIZE as f32;
 
-        let image: Option<Element<Message>> = self.image_handle.as_ref().map(|handle| {
-            let (handle, img_w, img_h) = handle;
-            if *img_w > 0 && *img_h > 0 {
-                let aspect = *img_h as f32 / *img_w as f32;
+        let image: Option<Element<Message>> = self.image.as_ref().map(|image| {
+            let (handle, img_w, img_h) = (&image.handle, image.width, image.height);
+            if img_w > 0 && img_h > 0 {
+                let aspect = img_h as f32 / img_w as f32;
                 let render_h = (MAX_WIDTH * aspect).min(MAX_HEIGHT);
                 Image::new(handle)
                     .width(Length::Fixed(MAX_WIDTH))
@@ -132,6 +154,11 @@ impl OgEmbedComponent {
             }
         });
 
+        let image = image.or_else(|| {
+            self.image_too_large
+                .then(|| too_large_placeholder(Length::Fixed(MAX_WIDTH)))
+        });
+
         let site = text(&self.site_name);
         let title = text(&self.title).font(crate::app::BOLD_FONT);
         let desc = text(&self.description).color(secondary_text(theme));
@@ -0,0 +1,315 @@
+//! Publishes the user's feeds whenever they change, and shows how each upload went.
+//! Displayed on the sidebar.
+//!
+//! Every identity's feed is watched, not only the active one's, so posting as another
+//! identity publishes its feed too.
+
+use std::{collections::HashMap, path::Path, time::SystemTime};
+
+use chrono::{DateTime, Local};
+use iced::{
+    Alignment, Element, Length, Task, Theme,
+    widget::{button, column, row, space, text},
+};
+use tracing::error;
+
+use crate::{
+    config::{AppConfig, Identity, PublishTarget},
+    publish::publish,
+    twtxt::metadata::Link,
+    utils::{
+        hash::hash_sha256_str,
+        styling::{prim_button_style, secondary_text},
+    },
+};
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// An upload finished, after any automatic retries.
+    Finished {
+        feed: String,
+        name: String,
+        result: Result<(), String>,
+    },
+    /// The user asked to retry a failed upload.
+    RetryPressed { feed: String, name: String },
+}
+
+#[derive(Debug, Clone)]
+enum Status {
+    /// Nothing has been uploaded since the app started.
+    Idle,
+    Uploading,
+    Published(DateTime<Local>),
+    Failed(String),
+}
+
+struct TargetState {
+    target: PublishTarget,
+    /// The path of the feed uploaded to the target.
+    feed: String,
+    status: Status,
+    /// Whether the feed changed again while it was being uploaded.
+    changed_while_uploading: bool,
+    /// A new archive feed that hasn't been uploaded to this target yet.
+    pending_archive: Option<String>,
+}
+
+/// What the feed file looked like the last time it was checked.
+#[derive(PartialEq)]
+struct FileState {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+/// What one of the user's feeds looked like when it was last checked.
+struct FeedState {
+    file: Option<FileState>,
+    content_hash: Option<String>,
+    /// The feed's `# prev` link, to notice new archives.
+    prev: Option<Link>,
+}
+
+impl FeedState {
+    fn new(identity: &Identity) -> Self {
+        Self {
+            file: file_state(&identity.paths.twtxt),
+            content_hash: std::fs::read_to_string(&identity.paths.twtxt)
+                .ok()
+                .map(|s| hash_sha256_str(&s)),
+            prev: identity.metadata.prev.clone(),
+        }
+    }
+}
+
+/// Uploads the user's feeds to the configured publish targets.
+pub struct Publisher {
+    targets: Vec<TargetState>,
+    /// The state of each feed, keyed by its path.
+    feeds: HashMap<String, FeedState>,
+}
+
+impl Publisher {
+    pub fn new(config: &AppConfig) -> Self {
+        let mut publisher = Self {
+            targets: Vec::new(),
+            feeds: identities(config)
+                .map(|identity| (identity.paths.twtxt.clone(), FeedState::new(&identity)))
+                .collect(),
+        };
+        publisher.sync_targets(config);
+        publisher
+    }
+
+    /// Publishes the feeds that changed since they were last checked, for example after
+    /// a post or a metadata change.
+    pub fn check_for_changes(&mut self, config: &AppConfig) -> Task<Message> {
+        self.sync_targets(config);
+
+        let mut tasks = Vec::new();
+        for identity in identities(config) {
+            let feed = identity.paths.twtxt.clone();
+            if self.feed_changed(&identity) {
+                for i in 0..self.targets.len() {
+                    if self.targets[i].feed == feed {
+                        tasks.push(self.start_upload(i));
+                    }
+                }
+            }
+        }
+        Task::batch(tasks)
+    }
+
+    /// Checks whether a feed changed, and queues up any archive split off from it.
+    fn feed_changed(&mut self, identity: &Identity) -> bool {
+        let path = &identity.paths.twtxt;
+        let Some(state) = self.feeds.get_mut(path) else {
+            self.feeds.insert(path.clone(), FeedState::new(identity));
+            return false;
+        };
+
+        if identity.metadata.prev != state.prev {
+            state.prev = identity.metadata.prev.clone();
+            // Only archives written next to the feed by twtGUI are uploaded
+            if let Some(prev) = &state.prev
+                && !prev.url.contains('/')
+            {
+                let archive = Path::new(path)
+                    .with_file_name(&prev.url)
+                    .to_string_lossy()
+                    .into_owned();
+                for target in self.targets.iter_mut().filter(|t| t.feed == *path) {
+                    target.pending_archive = Some(archive.clone());
+                }
+            }
+        }
+
+        let file = file_state(path);
+        if file == state.file {
+            return false;
+        }
+        state.file = file;
+
+        // Saving the config rewrites the file even when nothing changed
+        let content_hash = std::fs::read_to_string(path)
+            .ok()
+            .map(|s| hash_sha256_str(&s));
+        if content_hash == state.content_hash {
+            return false;
+        }
+        state.content_hash = content_hash;
+        true
+    }
+
+    pub fn update(&mut self, message: Message) -> Task<Message> {
+        match message {
+            Message::Finished { feed, name, result } => {
+                let Some(i) = self.position(&feed, &name) else {
+                    return Task::none();
+                };
+
+                let state = &mut self.targets[i];
+                state.status = match result {
+                    Ok(()) => {
+                        state.pending_archive = None;
+                        Status::Published(Local::now())
+                    }
+                    Err(e) => {
+                        error!("Failed to publish to {}: {}", name, e);
+                        Status::Failed(e)
+                    }
+                };
+
+                if std::mem::take(&mut state.changed_while_uploading) {
+                    self.start_upload(i)
+                } else {
+                    Task::none()
+                }
+            }
+
+            Message::RetryPressed { feed, name } => match self.position(&feed, &name) {
+                Some(i) => self.start_upload(i),
+                None => Task::none(),
+            },
+        }
+    }
+
+    fn position(&self, feed: &str, name: &str) -> Option<usize> {
+        self.targets
+            .iter()
+            .position(|t| t.feed == feed && t.target.name == name)
+    }
+
+    /// Keeps the targets in line with the config, keeping the status of existing ones.
+    fn sync_targets(&mut self, config: &AppConfig) {
+        let mut old = std::mem::take(&mut self.targets);
+        self.targets = identities(config)
+            .flat_map(|identity| {
+                let feed = identity.paths.twtxt;
+                identity
+                    .publish
+                    .into_iter()
+                    .map(move |target| (feed.clone(), target))
+            })
+            .map(|(feed, target)| {
+                match old
+                    .iter()
+                    .position(|s| s.feed == feed && s.target.name == target.name)
+                {
+                    Some(i) => {
+                        let mut state = old.swap_remove(i);
+                        state.target = target;
+                        state
+                    }
+                    None => TargetState {
+                        target,
+                        feed,
+                        status: Status::Idle,
+                        changed_while_uploading: false,
+                        pending_archive: None,
+                    },
+                }
+            })
+            .collect();
+    }
+
+    fn start_upload(&mut self, i: usize) -> Task<Message> {
+        let state = &mut self.targets[i];
+
+        // Uploads to the same target must not overlap, or an older copy could win
+        if matches!(state.status, Status::Uploading) {
+            state.changed_while_uploading = true;
+            return Task::none();
+        }
+
+        state.status = Status::Uploading;
+        let feed = state.feed.clone();
+        let name = state.target.name.clone();
+        Task::perform(
+            publish(
+                state.target.clone(),
+                state.feed.clone(),
+                state.pending_archive.clone(),
+            ),
+            move |result| Message::Finished {
+                feed: feed.clone(),
+                name: name.clone(),
+                result,
+            },
+        )
+    }
+
+    pub fn view(&self, theme: &Theme) -> Element<'_, Message> {
+        if self.targets.is_empty() {
+            return space().into();
+        }
+
+        let rows = self.targets.iter().map(|state| {
+            let status: Element<Message> = match &state.status {
+                Status::Idle => text("Not published yet")
+                    .color(secondary_text(theme))
+                    .into(),
+                Status::Uploading => text("Publishing...").color(secondary_text(theme)).into(),
+                Status::Published(at) => text(format!("Published {}", at.format("%-I:%M %p")))
+                    .color(secondary_text(theme))
+                    .into(),
+                Status::Failed(e) => column![
+                    row![
+                        text("Failed").color(theme.palette().danger),
+                        space().width(Length::Fill),
+                        button("Retry")
+                            .on_press(Message::RetryPressed {
+                                feed: state.feed.clone(),
+                                name: state.target.name.clone(),
+                            })
+                            .padding([2, 8])
+                            .style(prim_button_style),
+                    ]
+                    .align_y(Alignment::Center),
+                    text(e).size(10).color(secondary_text(theme)),
+                ]
+                .spacing(2)
+                .into(),
+            };
+
+            column![text(&state.target.name).font(crate::app::BOLD_FONT), status]
+                .spacing(2)
+                .into()
+        });
+
+        column(rows).spacing(6).padding([0, 16]).into()
+    }
+}
+
+/// Every identity whose feed is published, the active one first.
+fn identities(config: &AppConfig) -> impl Iterator<Item = Identity> + '_ {
+    std::iter::once(config.active_identity()).chain(config.identities.iter().cloned())
+}
+
+fn file_state(path: &str) -> Option<FileState> {
+    let meta = std::fs::metadata(path).ok()?;
+    Some(FileState {
+        modified: meta.modified().ok(),
+        len: meta.len(),
+    })
+}
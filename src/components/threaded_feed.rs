@@ -1,7 +1,10 @@
 //! A lazily-rendered threaded feed component.
 
 use crate::components::tweet::{self, TweetComponent};
+use crate::twtxt::feed_id::FeedId;
 use crate::twtxt::{Tweet, TweetNode};
+use crate::utils::media::{DecodedImage, MediaError, avatar_thumbnail};
+use crate::utils::privacy::MediaFilter;
 use crate::utils::styling::sec_button_style;
 use iced::widget::container;
 use iced::widget::rule::horizontal;
@@ -10,6 +13,7 @@ use iced::{
     widget::{Column, Id, button, column, image::Handle, row, scrollable, space},
 };
 use std::collections::HashMap;
+use std::sync::Arc;
 use tracing::{debug, error};
 
 /// How many additional threads to load when reaching the bottom of the scroll.
@@ -48,6 +52,12 @@ pub enum Message {
     LinkClicked(String),
     /// A reply button inside a tweet was clicked.
     ReplyClicked(usize),
+    /// The edit button of one of the user's own tweets was clicked.
+    EditClicked(usize),
+    /// The delete button of one of the user's own tweets was clicked.
+    DeleteClicked(usize),
+    /// A feed's avatar, loaded after the user asked for it, finished downloading.
+    AvatarLoaded(FeedId, Box<Result<DecodedImage, MediaError>>),
     /// Request to navigate to another page.
     RedirectToPage(crate::app::RedirectInfo),
     /// A message coming from a specific tweet component.
@@ -72,12 +82,21 @@ pub struct LazyThreadedFeed {
     /// every message.
     node_index: HashMap<usize, Vec<usize>>,
     thread_stack: Vec<StackEntry>,
-    pub avatars: HashMap<String, Handle>,
+    pub avatars: HashMap<FeedId, Handle>,
+    /// URLs of avatars the media filter kept from loading, by feed. Set by the owning
+    /// page, and loaded here when the user loads the hidden media of one of the feed's
+    /// tweets.
+    pub hidden_avatars: HashMap<FeedId, String>,
+    /// Decides which media tweets may load without asking, set by the owning page.
+    pub media_filter: Arc<MediaFilter>,
+    /// The user's own feed, whose tweets can be edited and deleted. Set by the owning page.
+    pub own_feed: Option<FeedId>,
 }
 
 impl LazyThreadedFeed {
     pub fn new(threads: &[TweetNode], tweets: &[Tweet]) -> (Self, Task<Message>) {
-        let (built, task) = build_nodes(threads, tweets);
+        let media_filter = Arc::new(MediaFilter::default());
+        let (built, task) = build_nodes(threads, tweets, &media_filter);
         let node_index = build_index(&built);
         let total = built.len();
         (
@@ -89,6 +108,9 @@ impl LazyThreadedFeed {
                 node_index,
                 thread_stack: Vec::new(),
                 avatars: HashMap::new(),
+                hidden_avatars: HashMap::new(),
+                media_filter,
+                own_feed: None,
             },
             task,
         )
@@ -100,7 +122,11 @@ impl LazyThreadedFeed {
         self.visible_threads_count = INITIAL_LOAD.min(threads.len());
         self.thread_stack.clear();
 
-        let (built, task) = build_nodes(&self.source_threads[..self.visible_threads_count], tweets);
+        let (built, task) = build_nodes(
+            &self.source_threads[..self.visible_threads_count],
+            tweets,
+            &self.media_filter,
+        );
         self.node_index = build_index(&built);
         self.built_threads = built;
         task
@@ -134,6 +160,7 @@ impl LazyThreadedFeed {
                         let (new_nodes, task) = build_nodes(
                             &self.source_threads[old_count..self.visible_threads_count],
                             tweets,
+                            &self.media_filter,
                         );
                         self.built_threads.extend(new_nodes);
                         self.node_index = build_index(&self.built_threads);
@@ -159,6 +186,19 @@ impl LazyThreadedFeed {
             Message::RedirectToPage(info) => Task::done(Message::RedirectToPage(info)),
 
             Message::ReplyClicked(index) => Task::done(Message::ReplyClicked(index)),
+            Message::EditClicked(index) => Task::done(Message::EditClicked(index)),
+            Message::DeleteClicked(index) => Task::done(Message::DeleteClicked(index)),
+            Message::AvatarLoaded(feed_id, result) => {
+                match *result {
+                    Ok(image) => {
+                        self.avatars.insert(feed_id, image.handle);
+                    }
+                    Err(e) => {
+                        error!("ThreadedFeed: error loading avatar for {}: {}", feed_id, e);
+                    }
+                }
+                Task::none()
+            }
 
             Message::LinkClicked(url) => {
                 if url.contains("twtxt") && url.ends_with(".txt") {
@@ -179,6 +219,23 @@ impl LazyThreadedFeed {
                 Task::done(Message::ReplyClicked(index))
             }
 
+            Message::Tweet(_, tweet::Message::EditClicked(index)) => {
+                Task::done(Message::EditClicked(index))
+            }
+
+            Message::Tweet(_, tweet::Message::DeleteClicked(index)) => {
+                Task::done(Message::DeleteClicked(index))
+            }
+
+            Message::Tweet(_, tweet::Message::LoadAvatarClicked(feed_id)) => {
+                let Some(url) = self.hidden_avatars.remove(&feed_id) else {
+                    return Task::none();
+                };
+                Task::perform(avatar_thumbnail(url), move |result| {
+                    Message::AvatarLoaded(feed_id, Box::new(result))
+                })
+            }
+
             Message::Tweet(_, tweet::Message::LinkClicked(url)) => {
                 Task::done(Message::LinkClicked(url))
             }
@@ -200,6 +257,42 @@ impl LazyThreadedFeed {
         }
     }
 
+    /// Shows a reply that was just added to `tweets` at `index` under the tweet it
+    /// replies to, in the threads being shown as well as those on the stack, so it
+    /// appears without leaving the thread being looked at.
+    pub fn insert_reply(&mut self, index: usize, tweets: &[Tweet]) -> Task<Message> {
+        let Some(parent) = tweets[index]
+            .reply_to
+            .as_deref()
+            .and_then(|hash| tweets.iter().position(|t| t.hash == hash))
+        else {
+            return Task::none();
+        };
+
+        let mut tasks = vec![insert_child(
+            &mut self.source_threads,
+            &mut self.built_threads,
+            &mut self.node_index,
+            parent,
+            index,
+            tweets,
+            &self.media_filter,
+        )];
+        for entry in &mut self.thread_stack {
+            tasks.push(insert_child(
+                &mut entry.source_threads,
+                &mut entry.built_threads,
+                &mut entry.node_index,
+                parent,
+                index,
+                tweets,
+                &self.media_filter,
+            ));
+        }
+
+        Task::batch(tasks)
+    }
+
     /// Push the current trees onto the stack, then rebuild from only the
     /// subtree rooted at index.
     fn drill_into_thread(&mut self, index: usize, tweets: &[Tweet]) -> Task<Message> {
@@ -217,7 +310,7 @@ impl LazyThreadedFeed {
             });
 
             let focused_sources = vec![focused_source];
-            let (built, task) = build_nodes(&focused_sources, tweets);
+            let (built, task) = build_nodes(&focused_sources, tweets, &self.media_filter);
             self.node_index = build_index(&built);
             self.source_threads = focused_sources;
             self.built_threads = built;
@@ -250,7 +343,15 @@ impl LazyThreadedFeed {
         for node in visible {
             col = col.push(
                 column![
-                    render_built_node(theme, node, tweets, &self.avatars, reply_available),
+                    render_built_node(
+                        theme,
+                        node,
+                        tweets,
+                        &self.avatars,
+                        &self.hidden_avatars,
+                        reply_available,
+                        self.own_feed.as_ref()
+                    ),
                     horizontal(1),
                 ]
                 .width(Length::Fill)
@@ -327,16 +428,68 @@ fn find_source_node(nodes: &[TweetNode], index: usize) -> Option<TweetNode> {
     None
 }
 
-fn build_nodes(threads: &[TweetNode], tweets: &[Tweet]) -> (Vec<BuiltNode>, Task<Message>) {
-    let (nodes, tasks): (Vec<_>, Vec<_>) =
-        threads.iter().map(|node| build_node(node, tweets)).unzip();
+/// Recursively find a TweetNode by tweet index.
+fn find_source_node_mut(nodes: &mut [TweetNode], index: usize) -> Option<&mut TweetNode> {
+    for node in nodes {
+        if node.index == index {
+            return Some(node);
+        }
+        if let Some(found) = find_source_node_mut(&mut node.children, index) {
+            return Some(found);
+        }
+    }
+    None
+}
+
+/// Adds `child` under `parent` in one level of the thread stack, building it if the
+/// parent has been built.
+fn insert_child(
+    source: &mut [TweetNode],
+    built: &mut [BuiltNode],
+    node_index: &mut HashMap<usize, Vec<usize>>,
+    parent: usize,
+    child: usize,
+    tweets: &[Tweet],
+    media_filter: &Arc<MediaFilter>,
+) -> Task<Message> {
+    let node = TweetNode {
+        index: child,
+        children: Vec::new(),
+    };
+    let Some(source_parent) = find_source_node_mut(source, parent) else {
+        return Task::none();
+    };
+    source_parent.children.push(node.clone());
+
+    let Some(built_parent) = find_node_mut(built, node_index, parent) else {
+        return Task::none();
+    };
+    let (built_child, task) = build_node(&node, tweets, media_filter);
+    built_parent.children.push(built_child);
+    *node_index = build_index(built);
+    task
+}
+
+fn build_nodes(
+    threads: &[TweetNode],
+    tweets: &[Tweet],
+    media_filter: &Arc<MediaFilter>,
+) -> (Vec<BuiltNode>, Task<Message>) {
+    let (nodes, tasks): (Vec<_>, Vec<_>) = threads
+        .iter()
+        .map(|node| build_node(node, tweets, media_filter))
+        .unzip();
 
     (nodes, Task::batch(tasks))
 }
 
-fn build_node(node: &TweetNode, tweets: &[Tweet]) -> (BuiltNode, Task<Message>) {
+fn build_node(
+    node: &TweetNode,
+    tweets: &[Tweet],
+    media_filter: &Arc<MediaFilter>,
+) -> (BuiltNode, Task<Message>) {
     let index = node.index;
-    let (component, task) = TweetComponent::new(index, tweets);
+    let (component, task) = TweetComponent::new(index, tweets, media_filter);
     let task = task.map(move |msg| Message::Tweet(index, msg));
 
     let mut sorted_children = node.children.clone();
@@ -344,7 +497,7 @@ fn build_node(node: &TweetNode, tweets: &[Tweet]) -> (BuiltNode, Task<Message>)
 
     let (children, child_tasks): (Vec<_>, Vec<_>) = sorted_children
         .iter()
-        .map(|child| build_node(child, tweets))
+        .map(|child| build_node(child, tweets, media_filter))
         .unzip();
 
     let all_tasks = Task::batch(std::iter::once(task).chain(child_tasks));
@@ -362,13 +515,23 @@ fn render_built_node<'a>(
     theme: &Theme,
     node: &'a BuiltNode,
     tweets: &'a [Tweet],
-    avatars: &'a HashMap<String, Handle>,
+    avatars: &'a HashMap<FeedId, Handle>,
+    hidden_avatars: &HashMap<FeedId, String>,
     reply_available: bool,
+    own_feed: Option<&FeedId>,
 ) -> Column<'a, Message> {
     let index = node.component.index;
+    let is_own = own_feed.is_some_and(|id| tweets[index].feed_id == *id);
     let tweet_view = node
         .component
-        .view(theme, tweets, avatars, reply_available)
+        .view(
+            theme,
+            tweets,
+            avatars,
+            hidden_avatars,
+            reply_available,
+            is_own,
+        )
         .map(move |msg| Message::Tweet(index, msg));
 
     let mut thread_col = column![tweet_view].spacing(8);
@@ -376,7 +539,15 @@ fn render_built_node<'a>(
     for child in &node.children {
         let indented = row![
             space().width(32),
-            render_built_node(theme, child, tweets, avatars, reply_available)
+            render_built_node(
+                theme,
+                child,
+                tweets,
+                avatars,
+                hidden_avatars,
+                reply_available,
+                own_feed
+            )
         ];
         thread_col = thread_col.push(indented);
     }
@@ -1,23 +1,24 @@
 //! A tweet renderer component, responsible for displaying a single tweet line.
 
 use crate::twtxt::Tweet;
+use crate::twtxt::feed_id::FeedId;
 use crate::twtxt::metadata::Link;
 use crate::twtxt::parsing;
-use crate::utils::download::download_binary;
 use crate::{
     components::og_embed::{self, OgEmbedComponent},
     utils::{
         download::download_opengraph,
+        identicon::identicon,
         is_file_url,
-        styling::{sec_button_style, secondary_text},
+        media::{DecodedImage, MediaError, load_thumbnail, too_large_placeholder},
+        privacy::MediaFilter,
+        styling::{prim_button_style, sec_button_style, secondary_text},
     },
 };
 
-use bytes::Bytes;
 use chrono::Local;
 use iced::{
     Background, Border, Color, ContentFit, Element, Length, Padding, Pixels, Task, Theme,
-    border::Radius,
     widget::{
         Image, button, column, container,
         image::Handle,
@@ -26,9 +27,10 @@ use iced::{
     },
 };
 use opengraph::Object;
-use tracing::error;
+use tracing::{error, warn};
 
 use std::collections::HashMap;
+use std::sync::Arc;
 
 /// Messages emitted by the tweet component.
 #[derive(Debug, Clone)]
@@ -39,60 +41,92 @@ pub enum Message {
     ReplyClicked(usize),
     /// The thread button was clicked.
     ThreadClicked(usize),
+    /// The edit button of one of the user's own tweets was clicked.
+    EditClicked(usize),
+    /// The delete button of one of the user's own tweets was clicked.
+    DeleteClicked(usize),
+    /// The user asked to load the images and link previews hidden by their privacy settings.
+    LoadMediaClicked,
+    /// The user asked to load the avatar of a feed, hidden by their privacy settings.
+    LoadAvatarClicked(FeedId),
     /// An image inside the tweet finished downloading.
-    ImageLoaded(usize, Box<Result<Bytes, String>>), // usize = index into image_urls
+    ImageLoaded(usize, Box<Result<DecodedImage, MediaError>>), // usize = index into image_urls
     /// An object was loaded from a URL's OpenGraph metadata.
     OgObjectLoaded(usize, Box<Result<Object, String>>, String), // usize = index into urls
     OgEmbed(usize, crate::components::og_embed::Message),
 }
 
+/// The size of the box inline images are shrunk to fit.
+const IMAGE_MAX_WIDTH: u32 = 500;
+const IMAGE_MAX_HEIGHT: u32 = 400;
+
+/// The state of an image embedded in a tweet.
+#[derive(Debug, Clone)]
+enum InlineImage {
+    /// The image is still loading, failed to load or hasn't been requested.
+    Pending,
+    Loaded(DecodedImage),
+    /// The image was over the size limits, so a placeholder is shown instead.
+    TooLarge,
+}
+
 /// A widget that renders a single tweet, including inline images and avatar.
 pub struct TweetComponent {
     pub index: usize,
+    /// The feed the tweet came from, used to decide which media may be loaded.
+    feed_id: FeedId,
+    media_filter: Arc<MediaFilter>,
+    /// Whether the user asked to load all media in this tweet.
+    media_unlocked: bool,
     image_urls: Vec<String>,
-    image_handles: Vec<Option<(Handle, u32, u32)>>,
+    images: Vec<InlineImage>,
+    /// Indices into `image_urls` of images that weren't loaded automatically.
+    blocked_images: Vec<usize>,
+    urls: Vec<Link>,
+    /// Indices into `urls` of links whose previews weren't loaded automatically.
+    blocked_urls: Vec<usize>,
     og_objects: Vec<Option<Object>>,
     og_embeds: Vec<Option<OgEmbedComponent>>,
 }
 
 impl TweetComponent {
-    pub fn new(index: usize, tweets: &[Tweet]) -> (Self, Task<Message>) {
+    pub fn new(
+        index: usize,
+        tweets: &[Tweet],
+        media_filter: &Arc<MediaFilter>,
+    ) -> (Self, Task<Message>) {
         let tweet = &tweets[index];
         let image_urls = collect_image_urls(&tweet.md_items);
-        let image_handles = vec![None; image_urls.len()];
+        let images = vec![InlineImage::Pending; image_urls.len()];
         let urls = collect_urls(&tweet.content);
         let og_objects = vec![None; urls.len()];
         let og_embeds = vec![None; urls.len()];
 
-        let img_tasks: Vec<Task<Message>> = image_urls
-            .iter()
-            .enumerate()
-            .map(|(i, url)| {
-                let url = url.clone();
-                Task::perform(download_binary(url), move |res| {
-                    Message::ImageLoaded(i, Box::new(res))
-                })
-            })
-            .collect();
-
-        let url_tasks: Vec<Task<Message>> = urls
-            .iter()
-            .enumerate()
-            .map(|(i, url)| {
-                let url = url.clone();
-                Task::perform(download_opengraph(url.url.clone()), move |res| {
-                    Message::OgObjectLoaded(i, Box::new(res), url.url)
-                })
-            })
-            .collect();
+        let (allowed_images, blocked_images): (Vec<usize>, Vec<usize>) = (0..image_urls.len())
+            .partition(|&i| media_filter.allows(&tweet.feed_id, &image_urls[i]));
+        let (allowed_urls, blocked_urls): (Vec<usize>, Vec<usize>) =
+            (0..urls.len()).partition(|&i| media_filter.allows(&tweet.feed_id, &urls[i].url));
 
-        let tasks = img_tasks.into_iter().chain(url_tasks).collect::<Vec<_>>();
+        let img_tasks = allowed_images
+            .into_iter()
+            .map(|i| load_image_task(i, &image_urls[i]));
+        let url_tasks = allowed_urls
+            .into_iter()
+            .map(|i| load_opengraph_task(i, &urls[i]));
+
+        let tasks = img_tasks.chain(url_tasks).collect::<Vec<_>>();
 
         (
             Self {
                 index,
+                feed_id: tweet.feed_id.clone(),
+                media_filter: media_filter.clone(),
+                media_unlocked: false,
                 image_urls,
-                image_handles,
+                images,
+                blocked_images,
+                urls,
+                blocked_urls,
                 og_objects,
                 og_embeds,
             },
@@ -100,24 +134,77 @@ impl TweetComponent {
         )
     }
 
+    /// The number of images and link previews that are waiting for the user to load
+    /// them, including the author's avatar if `avatar_hidden`.
+    fn hidden_media_count(&self, avatar_hidden: bool) -> usize {
+        let hidden_embed_images = self
+            .og_embeds
+            .iter()
+            .flatten()
+            .filter(|embed| embed.image_pending())
+            .count();
+
+        self.blocked_images.len()
+            + self.blocked_urls.len()
+            + hidden_embed_images
+            + usize::from(avatar_hidden)
+    }
+
     pub fn update(&mut self, message: Message) -> Task<Message> {
         match message {
             Message::LinkClicked(url) => Task::done(Message::LinkClicked(url)),
             Message::ReplyClicked(index) => Task::done(Message::ReplyClicked(index)),
             Message::ThreadClicked(index) => Task::done(Message::ThreadClicked(index)),
+            Message::EditClicked(index) => Task::done(Message::EditClicked(index)),
+            Message::DeleteClicked(index) => Task::done(Message::DeleteClicked(index)),
+            Message::LoadAvatarClicked(feed_id) => Task::done(Message::LoadAvatarClicked(feed_id)),
+            Message::LoadMediaClicked => {
+                self.media_unlocked = true;
+
+                let img_tasks: Vec<Task<Message>> = std::mem::take(&mut self.blocked_images)
+                    .into_iter()
+                    .map(|i| load_image_task(i, &self.image_urls[i]))
+                    .collect();
+
+                let url_tasks: Vec<Task<Message>> = std::mem::take(&mut self.blocked_urls)
+                    .into_iter()
+                    .map(|i| load_opengraph_task(i, &self.urls[i]))
+                    .collect();
+
+                let embed_tasks: Vec<Task<Message>> = self
+                    .og_embeds
+                    .iter_mut()
+                    .enumerate()
+                    .filter_map(|(i, embed)| {
+                        let task = embed.as_mut()?.load_image();
+                        Some(task.map(move |msg| Message::OgEmbed(i, msg)))
+                    })
+                    .collect();
+
+                // The avatar belongs to the feed, so whoever owns the avatars loads it
+                let avatar_task = Task::done(Message::LoadAvatarClicked(self.feed_id.clone()));
+
+                Task::batch(
+                    img_tasks
+                        .into_iter()
+                        .chain(url_tasks)
+                        .chain(embed_tasks)
+                        .chain([avatar_task]),
+                )
+            }
             Message::ImageLoaded(i, result) => {
+                let Some(slot) = self.images.get_mut(i) else {
+                    return Task::none();
+                };
                 match *result {
-                    Ok(bytes) => {
-                        if i < self.image_handles.len() {
-                            let dims = image::load_from_memory(&bytes)
-                                .ok()
-                                .map(|img| (img.width(), img.height()));
-
-                            self.image_handles[i] = Some(match dims {
-                                Some((w, h)) => (Handle::from_bytes(bytes), w, h),
-                                None => (Handle::from_bytes(bytes), 0, 0),
-                            });
-                        }
+                    Ok(image) => *slot = InlineImage::Loaded(image),
+                    Err(e @ MediaError::TooLarge(_)) => {
+                        warn!(
+                            "Tweet: not showing image {}: {}",
+                            self.image_urls.get(i).map(String::as_str).unwrap_or("?"),
+                            e
+                        );
+                        *slot = InlineImage::TooLarge;
                     }
                     Err(e) => {
                         error!(
@@ -136,7 +223,16 @@ impl TweetComponent {
                             self.og_objects[i] = Some(obj.clone());
                         }
                         if i < self.og_embeds.len() {
-                            let (embed, task) = OgEmbedComponent::new(&obj, &url);
+                            let mut embed = OgEmbedComponent::new(&obj, &url);
+                            let load_image = self.media_unlocked
+                                || embed.image_url().is_some_and(|image_url| {
+                                    self.media_filter.allows(&self.feed_id, image_url)
+                                });
+                            let task = if load_image {
+                                embed.load_image()
+                            } else {
+                                Task::none()
+                            };
                             self.og_embeds[i] = Some(embed);
                             return task.map(move |msg| Message::OgEmbed(i, msg));
                         }
@@ -166,60 +262,25 @@ impl TweetComponent {
         &'a self,
         theme: &Theme,
         tweets: &'a [Tweet],
-        avatars: &'a HashMap<String, Handle>,
+        avatars: &'a HashMap<FeedId, Handle>,
+        hidden_avatars: &HashMap<FeedId, String>,
         reply_available: bool,
+        is_own: bool,
     ) -> Element<'a, Message> {
         let tweet = &tweets[self.index];
 
-        let code_bg = Color::from_rgba(0.0, 0.0, 0.0, 0.55);
-
-        let content = markdown::view(
-            &tweet.md_items,
-            markdown::Settings::with_text_size(
-                Pixels(12.0),
-                markdown::Style {
-                    font: crate::app::REGULAR_FONT,
-                    link_color: theme.palette().primary,
-                    inline_code_font: crate::app::MONOSPACE_FONT,
-                    inline_code_color: Color::from_rgb(0.85, 0.85, 0.85),
-                    inline_code_highlight: Highlight {
-                        background: Background::Color(code_bg),
-                        border: Border::default(),
-                    },
-                    inline_code_padding: Padding::from(2.0),
-                    code_block_font: crate::app::MONOSPACE_FONT,
-                },
-            ),
-        )
-        .map(Message::LinkClicked);
-
-        let handle = avatars.get(&tweet.feed_hash);
-        let avatar_img: Element<Message> = if let Some(avatar) = handle {
-            Image::new(avatar)
-                .width(Length::Fixed(48.0))
-                .height(Length::Fixed(48.0))
-                .border_radius(24)
-                .filter_method(iced::widget::image::FilterMethod::Linear)
-                .into()
-        } else {
-            container(text("?").size(24))
-                .width(Length::Fixed(48.0))
-                .height(Length::Fixed(48.0))
-                .center_x(Length::Fixed(48.0))
-                .center_y(Length::Fixed(48.0))
-                .style(|theme: &Theme| {
-                    let ext = theme.extended_palette();
-                    container::Style {
-                        background: Some(Background::Color(ext.background.strong.color)),
-                        border: Border {
-                            radius: Radius::from(24.0),
-                            ..Default::default()
-                        },
-                        ..Default::default()
-                    }
-                })
-                .into()
-        };
+        let content =
+            markdown::view(&tweet.md_items, markdown_settings(theme)).map(Message::LinkClicked);
+
+        let avatar = avatars
+            .get(&tweet.feed_id)
+            .cloned()
+            .unwrap_or_else(|| identicon(tweet.feed_id.as_str(), theme));
+        let avatar_img = Image::new(avatar)
+            .width(Length::Fixed(48.0))
+            .height(Length::Fixed(48.0))
+            .border_radius(24)
+            .filter_method(iced::widget::image::FilterMethod::Linear);
 
         let formatted_time = tweet
             .timestamp
@@ -235,27 +296,33 @@ impl TweetComponent {
         ]
         .on_link_click(Message::LinkClicked);
 
-        const MAX_WIDTH: f32 = 500.0;
-        const MAX_HEIGHT: f32 = 400.0;
+        const MAX_WIDTH: f32 = IMAGE_MAX_WIDTH as f32;
+        const MAX_HEIGHT: f32 = IMAGE_MAX_HEIGHT as f32;
 
         let mut images_col = column![].spacing(4);
-        for entry in self.image_handles.iter().flatten() {
-            let (handle, img_w, img_h) = entry;
-            let image = if *img_w > 0 && *img_h > 0 {
-                let aspect = *img_h as f32 / *img_w as f32;
-                let render_h = (MAX_WIDTH * aspect).min(MAX_HEIGHT);
-                Image::new(handle)
-                    .width(Length::Fixed(MAX_WIDTH))
-                    .height(Length::Fixed(render_h))
-                    .content_fit(ContentFit::Contain)
-                    .filter_method(iced::widget::image::FilterMethod::Linear)
-            } else {
-                // fallback!
-                Image::new(handle)
-                    .width(Length::Fill)
-                    .height(Length::Shrink)
-                    .content_fit(ContentFit::Contain)
-                    .filter_method(iced::widget::image::FilterMethod::Linear)
+        for entry in &self.images {
+            let image: Element<Message> = match entry {
+                InlineImage::Pending => continue,
+                InlineImage::TooLarge => too_large_placeholder(Length::Fixed(MAX_WIDTH)),
+                InlineImage::Loaded(image) if image.width > 0 && image.height > 0 => {
+                    let aspect = image.height as f32 / image.width as f32;
+                    let render_h = (MAX_WIDTH * aspect).min(MAX_HEIGHT);
+                    Image::new(&image.handle)
+                        .width(Length::Fixed(MAX_WIDTH))
+                        .height(Length::Fixed(render_h))
+                        .content_fit(ContentFit::Contain)
+                        .filter_method(iced::widget::image::FilterMethod::Linear)
+                        .into()
+                }
+                InlineImage::Loaded(image) => {
+                    // fallback!
+                    Image::new(&image.handle)
+                        .width(Length::Fill)
+                        .height(Length::Shrink)
+                        .content_fit(ContentFit::Contain)
+                        .filter_method(iced::widget::image::FilterMethod::Linear)
+                        .into()
+                }
             };
             images_col = images_col.push(image);
         }
@@ -268,23 +335,59 @@ impl TweetComponent {
             }
         }
 
-        let reply_button: Element<Message> = if reply_available {
-            button("Reply")
-                .style(sec_button_style)
-                .padding([8.0, 16.0])
-                .on_press(Message::ReplyClicked(self.index))
-                .into()
+        let hidden_media = self.hidden_media_count(hidden_avatars.contains_key(&tweet.feed_id));
+        let load_media_button: Element<Message> = if hidden_media > 0 {
+            button(text(if hidden_media == 1 {
+                "Load 1 hidden image or preview".to_string()
+            } else {
+                format!("Load {} hidden images or previews", hidden_media)
+            }))
+            .on_press(Message::LoadMediaClicked)
+            .padding([4.0, 8.0])
+            .style(prim_button_style)
+            .into()
         } else {
             space().into()
         };
 
+        let mut actions = row![].spacing(8);
+        if reply_available {
+            actions = actions.push(
+                button("Reply")
+                    .style(sec_button_style)
+                    .padding([8.0, 16.0])
+                    .on_press(Message::ReplyClicked(self.index)),
+            );
+        }
+        if is_own {
+            actions = actions
+                .push(
+                    button("Edit")
+                        .style(sec_button_style)
+                        .padding([8.0, 16.0])
+                        .on_press(Message::EditClicked(self.index)),
+                )
+                .push(
+                    button("Delete")
+                        .style(sec_button_style)
+                        .padding([8.0, 16.0])
+                        .on_press(Message::DeleteClicked(self.index)),
+                );
+        }
+
         column![
             button(
                 row![
                     avatar_img,
-                    column![header, container(content), images_col, og_embeds_col]
-                        .padding([6.0, 0.0])
-                        .spacing(4)
+                    column![
+                        header,
+                        container(content),
+                        images_col,
+                        og_embeds_col,
+                        load_media_button
+                    ]
+                    .padding([6.0, 0.0])
+                    .spacing(4)
                 ]
                 .spacing(12),
             )
@@ -292,7 +395,7 @@ impl TweetComponent {
             .width(Length::Fill)
             .padding(16)
             .style(sec_button_style),
-            reply_button
+            actions
         ]
         .spacing(8)
         .width(Length::Fill)
@@ -300,8 +403,43 @@ impl TweetComponent {
     }
 }
 
+/// The settings tweets are rendered with, shared with the composer's preview.
+pub fn markdown_settings(theme: &Theme) -> markdown::Settings {
+    let code_bg = Color::from_rgba(0.0, 0.0, 0.0, 0.55);
+
+    markdown::Settings::with_text_size(
+        Pixels(12.0),
+        markdown::Style {
+            font: crate::app::REGULAR_FONT,
+            link_color: theme.palette().primary,
+            inline_code_font: crate::app::MONOSPACE_FONT,
+            inline_code_color: Color::from_rgb(0.85, 0.85, 0.85),
+            inline_code_highlight: Highlight {
+                background: Background::Color(code_bg),
+                border: Border::default(),
+            },
+            inline_code_padding: Padding::from(2.0),
+            code_block_font: crate::app::MONOSPACE_FONT,
+        },
+    )
+}
+
+fn load_image_task(i: usize, url: &str) -> Task<Message> {
+    Task::perform(
+        load_thumbnail(url.to_string(), IMAGE_MAX_WIDTH, IMAGE_MAX_HEIGHT),
+        move |res| Message::ImageLoaded(i, Box::new(res)),
+    )
+}
+
+fn load_opengraph_task(i: usize, link: &Link) -> Task<Message> {
+    let url = link.url.clone();
+    Task::perform(download_opengraph(url.clone()), move |res| {
+        Message::OgObjectLoaded(i, Box::new(res), url)
+    })
+}
+
 /// Recursively collects image URLs from markdown items.
-fn collect_image_urls(items: &[markdown::Item]) -> Vec<String> {
+pub fn collect_image_urls(items: &[markdown::Item]) -> Vec<String> {
     let mut urls = Vec::new();
     collect_image_urls_into(items, &mut urls);
     urls
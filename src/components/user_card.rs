@@ -1,12 +1,16 @@
-//! A card displaying a user's profile, with their avatar and name.
-//! Displayed on the sidebar.
+//! A card displaying a user's profile, with their avatar and name, and a picker to
+//! switch to their other identities. Displayed on the sidebar.
 
-use crate::utils::{download::download_binary, styling::prim_button_style};
-use bytes::Bytes;
+use crate::config::{AppConfig, IdentityChoice};
+use crate::twtxt::feed_id::FeedId;
+use crate::utils::{
+    identicon::identicon,
+    media::{DecodedImage, MediaError, avatar_thumbnail},
+    styling::{prim_button_style, prim_pick_list_style, prim_pick_menu_style},
+};
 use iced::{
-    Background, Border, Element, Length, Task, Theme,
-    border::Radius,
-    widget::{button, container, image::Handle, row, text},
+    Element, Length, Task, Theme,
+    widget::{button, column, container, image::Handle, pick_list, row, text},
 };
 use tracing::error;
 
@@ -15,9 +19,11 @@ pub enum Message {
     /// The user has clicked on the card.
     UserClicked,
     /// An avatar image has finished downloading.
-    AvatarLoaded(Box<Result<Bytes, String>>),
+    AvatarLoaded(Box<Result<DecodedImage, MediaError>>),
     /// Navigate to another page.
     RedirectToPage(crate::app::RedirectInfo),
+    /// The user picked an identity to switch to. Handled by the app.
+    IdentitySelected(IdentityChoice),
 }
 
 /// Card displaying a user's profile, with their avatar and name.
@@ -25,16 +31,22 @@ pub struct UserCard {
     user: String,
     user_url: Option<String>,
     avatar: Option<Handle>,
+    /// Every identity the user has, the active one first.
+    identities: Vec<IdentityChoice>,
 }
 
 impl UserCard {
-    pub fn new(
-        user: String,
-        user_url: Option<String>,
-        avatar_url: Option<String>,
-    ) -> (Self, Task<Message>) {
-        let task = if let Some(url) = avatar_url {
-            Task::perform(download_binary(url.clone()), move |res| {
+    /// Creates the card for the config's active identity.
+    pub fn new(config: &AppConfig) -> (Self, Task<Message>) {
+        let user = config
+            .metadata
+            .nick
+            .clone()
+            .unwrap_or("unknown".to_string());
+        let user_url = config.metadata.urls.first().cloned();
+
+        let task = if let Some(url) = config.metadata.avatar.clone() {
+            Task::perform(avatar_thumbnail(url.clone()), move |res| {
                 Message::AvatarLoaded(Box::new(res))
             })
         } else {
@@ -45,6 +57,7 @@ impl UserCard {
                 user,
                 user_url,
                 avatar: None,
+                identities: config.identity_choices(),
             },
             task,
         )
@@ -53,9 +66,9 @@ impl UserCard {
     pub fn update(&mut self, msg: Message) -> Task<Message> {
         match msg {
             Message::AvatarLoaded(result) => match *result {
-                Ok(bytes) => {
+                Ok(image) => {
                     if self.avatar.is_none() {
-                        self.avatar = Some(Handle::from_bytes(bytes));
+                        self.avatar = Some(image.handle);
                     }
                     Task::none()
                 }
@@ -77,39 +90,25 @@ impl UserCard {
             }
 
             Message::RedirectToPage(info) => Task::done(Message::RedirectToPage(info)),
+
+            Message::IdentitySelected(choice) => Task::done(Message::IdentitySelected(choice)),
         }
     }
 
-    pub fn view(&self) -> Element<'_, Message> {
-        let avatar: Element<'_, Message> = match &self.avatar {
-            Some(handle) => iced::widget::image(handle)
-                .width(32)
-                .height(32)
-                .border_radius(16)
-                .filter_method(iced::widget::image::FilterMethod::Linear)
-                .into(),
-            None => container(text("?").size(16))
-                .width(Length::Fixed(32.0))
-                .height(Length::Fixed(32.0))
-                .center_x(Length::Fixed(32.0))
-                .center_y(Length::Fixed(32.0))
-                .style(|theme: &Theme| {
-                    let ext = theme.extended_palette();
-                    container::Style {
-                        background: Some(Background::Color(ext.background.strong.color)),
-                        border: Border {
-                            radius: Radius::from(16.0),
-                            ..Default::default()
-                        },
-                        ..Default::default()
-                    }
-                })
-                .into(),
-        };
+    pub fn view(&self, theme: &Theme) -> Element<'_, Message> {
+        let avatar = self.avatar.clone().unwrap_or_else(|| {
+            let seed = self.user_url.as_deref().unwrap_or(&self.user);
+            identicon(FeedId::from_url(seed).as_str(), theme)
+        });
+        let avatar = iced::widget::image(avatar)
+            .width(32)
+            .height(32)
+            .border_radius(16)
+            .filter_method(iced::widget::image::FilterMethod::Linear);
 
         let username = text(self.user.clone()).font(crate::app::BOLD_FONT);
 
-        if self.user_url.is_none() {
+        let card: Element<'_, Message> = if self.user_url.is_none() {
             container(
                 row![avatar, username]
                     .spacing(8)
@@ -129,6 +128,21 @@ impl UserCard {
             .padding([8, 16])
             .width(Length::Fill)
             .into()
+        };
+
+        if self.identities.len() < 2 {
+            return card;
         }
+
+        let switcher = pick_list(
+            self.identities.as_slice(),
+            self.identities.first().cloned(),
+            Message::IdentitySelected,
+        )
+        .width(Length::Fill)
+        .style(prim_pick_list_style)
+        .menu_style(prim_pick_menu_style);
+
+        column![card, switcher].spacing(4).into()
     }
 }
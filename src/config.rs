@@ -13,18 +13,27 @@ use crate::twtxt::metadata::Metadata;
 use std::collections::{HashMap, HashSet};
 use std::fmt;
 
-/// Returns the canonical path where the application's config file is stored.
+pub mod secrets;
+
+/// Returns the platform-specific directory where configuration files are stored.
 ///
-/// Ensures the configuration directory exists and returns a `config.toml` file path
-/// inside the platform-specific config directory.
-fn config_path() -> Result<PathBuf, Box<dyn Error>> {
+/// Ensures the directory exists.
+fn config_dir() -> Result<PathBuf, Box<dyn Error>> {
     let proj = ProjectDirs::from("com", "taxevaiden", "twtGUI")
         .ok_or("Could not determine config directory")?;
 
     let dir = proj.config_dir();
     fs::create_dir_all(dir)?;
 
-    Ok(dir.join("config.toml"))
+    Ok(dir.to_path_buf())
+}
+
+/// Returns the canonical path where the application's config file is stored.
+///
+/// Ensures the configuration directory exists and returns a `config.toml` file path
+/// inside the platform-specific config directory.
+fn config_path() -> Result<PathBuf, Box<dyn Error>> {
+    Ok(config_dir()?.join("config.toml"))
 }
 
 macro_rules! theme_choices {
@@ -74,6 +83,246 @@ pub struct Appearance {
     pub theme: ThemeChoice,
 }
 
+macro_rules! media_policies {
+    ($($variant:ident => $label:expr),* $(,)?) => {
+        /// Which remote media (avatars, inline images and link previews) is loaded
+        /// automatically. Anything else has to be loaded by clicking on it.
+        #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+        #[serde(rename_all = "snake_case")]
+        pub enum MediaPolicy {
+            $($variant),*
+        }
+
+        impl MediaPolicy {
+            pub const ALL: &'static [MediaPolicy] = &[$(MediaPolicy::$variant),*];
+        }
+
+        impl fmt::Display for MediaPolicy {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                    $(MediaPolicy::$variant => write!(f, $label)),*
+                }
+            }
+        }
+    };
+}
+
+media_policies! {
+    LoadAll => "Load all media",
+    FollowedDomains => "Only from followed domains",
+    ClickToLoad => "Click to load",
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for MediaPolicy {
+    fn default() -> Self {
+        MediaPolicy::LoadAll
+    }
+}
+
+/// Privacy settings for loading remote content.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Privacy {
+    /// The media policy used for feeds without an override.
+    pub media: MediaPolicy,
+    /// Per-feed media policies, keyed by the URL of the followed feed.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub media_overrides: HashMap<String, MediaPolicy>,
+}
+
+/// When old twts are moved out of the user's feed into archive feeds.
+///
+/// Archiving only happens automatically once one of the limits is set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Archiving {
+    /// Archive once the feed has more than this many twts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_twts: Option<usize>,
+    /// Archive once the feed is larger than this many bytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<u64>,
+    /// How many of the newest twts stay in the feed when it's archived.
+    pub keep_twts: usize,
+}
+
+impl Default for Archiving {
+    fn default() -> Self {
+        Self {
+            max_twts: None,
+            max_bytes: None,
+            keep_twts: 100,
+        }
+    }
+}
+
+/// Where images attached in the composer are uploaded.
+///
+/// Credentials for the HTTP based backends are kept in `secrets.toml`, under `media`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MediaBackend {
+    /// Upload with a plain HTTP `PUT` or `POST` of the image. If `url` ends with a `/`,
+    /// the image's file name is appended to it.
+    Http {
+        url: String,
+        #[serde(default)]
+        method: HttpMethod,
+        /// The URL the uploaded images are served under. If not set, the endpoint is
+        /// expected to respond with the URL of the image.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        public_url: Option<String>,
+    },
+    /// Upload into a WebDAV collection, creating it if it's missing.
+    WebDav {
+        url: String,
+        /// The URL the collection is served under, if not the one it's uploaded to.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        public_url: Option<String>,
+    },
+    /// Copy into a local directory, such as one next to `twtxt.txt` that's published
+    /// along with it.
+    Directory {
+        path: String,
+        /// The URL the directory is served under.
+        public_url: String,
+    },
+}
+
+/// How images attached in the composer are uploaded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MediaUploads {
+    /// Where images are uploaded. Attaching images is disabled until this is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backend: Option<MediaBackend>,
+    /// Re-encode images before uploading them, which drops EXIF data such as the
+    /// location a photo was taken at.
+    pub strip_metadata: bool,
+    /// Downscale images so neither side is longer than this many pixels.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_dimension: Option<u32>,
+}
+
+impl Default for MediaUploads {
+    fn default() -> Self {
+        Self {
+            backend: None,
+            strip_metadata: true,
+            max_dimension: None,
+        }
+    }
+}
+
+/// The HTTP method used by the generic HTTP publishing backend.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum HttpMethod {
+    #[default]
+    Put,
+    Post,
+}
+
+/// Where and how the user's `twtxt.txt` is uploaded.
+///
+/// Credentials for the HTTP based backends are kept in `secrets.toml`, under the name
+/// of the target. SFTP and git use the user's own SSH and git setup.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PublishBackend {
+    /// Upload with a WebDAV `PUT`, creating missing parent collections.
+    WebDav { url: String },
+    /// Upload with a plain HTTP `PUT` or `POST` of the feed contents.
+    Http {
+        url: String,
+        #[serde(default)]
+        method: HttpMethod,
+    },
+    /// Upload over SFTP using the system's `sftp` client.
+    Sftp {
+        host: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        port: Option<u16>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        user: Option<String>,
+        /// The path of the feed on the server.
+        path: String,
+        /// The private key to log in with, if not the default one.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        identity_file: Option<String>,
+    },
+    /// Commit the feed in a local clone of a git repository and push it.
+    Git {
+        /// The path of the local clone.
+        repository: String,
+        /// The path of the feed inside the repository.
+        #[serde(default = "default_git_path")]
+        path: String,
+        #[serde(default = "default_git_remote")]
+        remote: String,
+        /// The branch to push to, the one checked out if not set.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        branch: Option<String>,
+    },
+}
+
+fn default_git_path() -> String {
+    "twtxt.txt".to_string()
+}
+
+fn default_git_remote() -> String {
+    "origin".to_string()
+}
+
+/// A server the user's `twtxt.txt` is published to whenever it changes.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PublishTarget {
+    /// A name for the target, shown in the UI and used to look up its credentials.
+    pub name: String,
+    #[serde(flatten)]
+    pub backend: PublishBackend,
+}
+
+/// A feed the user can post as, other than the active one.
+///
+/// The active identity is the config's own `metadata`, `paths` and `publish`, so
+/// everything reading those acts as it. Switching swaps it with one of these.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Identity {
+    pub metadata: Metadata,
+    pub paths: AppFilePaths,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub publish: Vec<PublishTarget>,
+}
+
+impl Identity {
+    /// The name the identity is shown by: its nick, or its URL or feed path if it
+    /// doesn't have one.
+    pub fn name(&self) -> String {
+        self.metadata
+            .nick
+            .clone()
+            .or_else(|| self.metadata.urls.first().cloned())
+            .unwrap_or_else(|| self.paths.twtxt.clone())
+    }
+}
+
+/// One of the user's identities, as offered in the identity pickers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdentityChoice {
+    /// The index of the identity in [`AppConfig::identities`], or `None` for the
+    /// active one.
+    pub index: Option<usize>,
+    pub name: String,
+}
+
+impl fmt::Display for IdentityChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
 /// Top-level application configuration stored in `config.toml`.
 ///
 /// This includes a cached set of metadata from the user's feed as well as
@@ -89,6 +338,23 @@ pub struct AppConfig {
 
     /// The appearance settings for the application.
     pub appearance: Appearance,
+
+    /// The privacy settings for the application.
+    pub privacy: Privacy,
+
+    /// When the user's feed is archived.
+    pub archive: Archiving,
+
+    /// The servers the user's feed is published to.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub publish: Vec<PublishTarget>,
+
+    /// Where and how images attached in the composer are uploaded.
+    pub media: MediaUploads,
+
+    /// The user's other feeds, which can be switched to or posted as.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub identities: Vec<Identity>,
 }
 
 /// Paths to files that are used or created by the application.
@@ -96,12 +362,16 @@ pub struct AppConfig {
 pub struct AppFilePaths {
     /// Path to the user's local `twtxt.txt` file.
     pub twtxt: String,
-    /// Path to a script file that is run before a tweet is posted.
+    /// Path to a script file that is run before a tweet is posted. If it exits with a
+    /// non-zero status, the tweet isn't posted.
+    ///
+    /// All scripts receive details about the tweet in `TWTGUI_*` environment variables,
+    /// see [`HookEnv`](crate::twtxt::hooks::HookEnv).
     #[serde(skip_serializing_if = "Option::is_none")]
     pub pre_tweet_script: Option<String>,
     /// Path to a script file that is run when a tweet is posted. When this is set, the tweet
     /// is not automatically appended to `twtxt.txt` by twtGUI and is instead passed to the script as an argument.
-    /// The timestamp is NOT passed to the script, so the script must handle timestamp formatting itself.
+    /// The timestamp is NOT passed as an argument, but is available as `TWTGUI_TWT_TIMESTAMP`.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tweet_script: Option<String>,
     /// Path to a script file that is run after a tweet is posted.
@@ -133,6 +403,11 @@ impl Default for AppConfig {
                 tweet_script: None,
                 post_tweet_script: None,
             },
+            privacy: Privacy::default(),
+            archive: Archiving::default(),
+            publish: Vec::new(),
+            media: MediaUploads::default(),
+            identities: Vec::new(),
         }
     }
 }
@@ -163,6 +438,76 @@ impl AppConfig {
         Ok(config)
     }
 
+    /// Returns the active identity.
+    pub fn active_identity(&self) -> Identity {
+        Identity {
+            metadata: self.metadata.clone(),
+            paths: self.paths.clone(),
+            publish: self.publish.clone(),
+        }
+    }
+
+    /// Lists every identity, the active one first and the others by name.
+    pub fn identity_choices(&self) -> Vec<IdentityChoice> {
+        let mut others: Vec<IdentityChoice> = self
+            .identities
+            .iter()
+            .enumerate()
+            .map(|(i, identity)| IdentityChoice {
+                index: Some(i),
+                name: identity.name(),
+            })
+            .collect();
+        others.sort_by_key(|choice| choice.name.to_lowercase());
+
+        let active = IdentityChoice {
+            index: None,
+            name: self.active_identity().name(),
+        };
+        std::iter::once(active).chain(others).collect()
+    }
+
+    /// Returns a copy of the config acting as the identity at `index`, for posting as
+    /// it without switching to it.
+    pub fn as_identity(&self, index: usize) -> Option<Self> {
+        let mut config = self.clone();
+        config.swap_identity(index)?;
+        Some(config)
+    }
+
+    /// Makes the identity at `index` the active one, and saves the config.
+    ///
+    /// The previously active identity takes its place in the list.
+    pub fn switch_identity(&mut self, index: usize) -> Result<(), Box<dyn Error>> {
+        self.swap_identity(index)
+            .ok_or_else(|| format!("There's no identity {}", index))?;
+        self.save()
+    }
+
+    /// Returns a copy of the config acting as the identity whose feed is at
+    /// `twtxt_path`, whether it's the active one or not.
+    ///
+    /// Unlike indices into [`AppConfig::identities`], feed paths stay the same when
+    /// the user switches identities.
+    pub fn for_feed(&self, twtxt_path: &str) -> Option<Self> {
+        if self.paths.twtxt == twtxt_path {
+            return Some(self.clone());
+        }
+        let index = self
+            .identities
+            .iter()
+            .position(|identity| identity.paths.twtxt == twtxt_path)?;
+        self.as_identity(index)
+    }
+
+    fn swap_identity(&mut self, index: usize) -> Option<()> {
+        let identity = self.identities.get_mut(index)?;
+        std::mem::swap(&mut self.metadata, &mut identity.metadata);
+        std::mem::swap(&mut self.paths, &mut identity.paths);
+        std::mem::swap(&mut self.publish, &mut identity.publish);
+        Some(())
+    }
+
     /// Saves the configuration to disk.
     ///
     /// This also ensures the saved metadata is kept in sync with the linked
@@ -351,3 +696,116 @@ impl AppConfig {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity(nick: Option<&str>, twtxt: &str) -> Identity {
+        Identity {
+            metadata: Metadata {
+                nick: nick.map(str::to_string),
+                ..Metadata::default()
+            },
+            paths: AppFilePaths {
+                twtxt: twtxt.to_string(),
+                ..AppConfig::default().paths
+            },
+            publish: Vec::new(),
+        }
+    }
+
+    /// A config acting as `main`, with `bob` and `alice` as its other identities.
+    fn config() -> AppConfig {
+        let mut config = AppConfig::default();
+        config.metadata.nick = Some("main".to_string());
+        config.paths.twtxt = "/feeds/main.txt".to_string();
+        config.identities = vec![
+            identity(Some("bob"), "/feeds/bob.txt"),
+            identity(None, "/feeds/Alice.txt"),
+        ];
+        config
+    }
+
+    #[test]
+    fn swapping_identities_trades_places() {
+        let mut config = config();
+        config.swap_identity(0).unwrap();
+        assert_eq!(config.metadata.nick.as_deref(), Some("bob"));
+        assert_eq!(config.paths.twtxt, "/feeds/bob.txt");
+        assert_eq!(config.identities[0].name(), "main");
+        assert_eq!(config.identities[0].paths.twtxt, "/feeds/main.txt");
+
+        config.swap_identity(0).unwrap();
+        assert_eq!(config.paths.twtxt, "/feeds/main.txt");
+        assert!(config.swap_identity(2).is_none());
+    }
+
+    #[test]
+    fn acting_as_an_identity_leaves_the_config_alone() {
+        let config = config();
+        let bob = config.as_identity(0).unwrap();
+        assert_eq!(bob.paths.twtxt, "/feeds/bob.txt");
+        assert_eq!(config.paths.twtxt, "/feeds/main.txt");
+        assert!(config.as_identity(2).is_none());
+    }
+
+    #[test]
+    fn finds_identities_by_feed() {
+        let config = config();
+        assert_eq!(
+            config.for_feed("/feeds/main.txt").unwrap().paths.twtxt,
+            "/feeds/main.txt"
+        );
+        assert_eq!(
+            config.for_feed("/feeds/Alice.txt").unwrap().paths.twtxt,
+            "/feeds/Alice.txt"
+        );
+        assert!(config.for_feed("/feeds/gone.txt").is_none());
+
+        // Switching identities moves them around the list, but not to other feeds
+        let mut switched = config.clone();
+        switched.swap_identity(1).unwrap();
+        assert_eq!(
+            switched
+                .for_feed("/feeds/bob.txt")
+                .unwrap()
+                .metadata
+                .nick
+                .as_deref(),
+            Some("bob")
+        );
+        assert_eq!(
+            switched
+                .for_feed("/feeds/main.txt")
+                .unwrap()
+                .metadata
+                .nick
+                .as_deref(),
+            Some("main")
+        );
+    }
+
+    #[test]
+    fn identity_choices_list_the_active_one_first() {
+        let choices = config().identity_choices();
+        assert_eq!(
+            choices,
+            [
+                IdentityChoice {
+                    index: None,
+                    name: "main".to_string(),
+                },
+                // Named by their feed path without a nick, sorted ignoring case
+                IdentityChoice {
+                    index: Some(1),
+                    name: "/feeds/Alice.txt".to_string(),
+                },
+                IdentityChoice {
+                    index: Some(0),
+                    name: "bob".to_string(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,288 @@
+//! Credentials for private feeds.
+//!
+//! These are kept in their own `secrets.toml` next to `config.toml`, so the config
+//! can be shared or synced without leaking passwords. On Unix the file is only
+//! readable by the current user.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{LazyLock, RwLock},
+};
+
+use reqwest::Url;
+use tracing::error;
+
+use crate::config::config_dir;
+use crate::twtxt::feed_id::FeedId;
+
+/// The credentials in use, loaded from disk the first time they're needed.
+static SECRETS: LazyLock<RwLock<Secrets>> = LazyLock::new(|| {
+    RwLock::new(Secrets::load().unwrap_or_else(|e| {
+        error!("Failed to load secrets: {}", e);
+        Secrets::default()
+    }))
+});
+
+/// Returns the path where credentials are stored.
+fn secrets_path() -> Result<PathBuf, Box<dyn Error>> {
+    Ok(config_dir()?.join("secrets.toml"))
+}
+
+/// Credentials used to access a private feed over HTTP.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Credential {
+    /// HTTP Basic authentication.
+    Basic { username: String, password: String },
+    /// A bearer token sent in the `Authorization` header.
+    Bearer { token: String },
+}
+
+/// All stored credentials.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Secrets {
+    /// Credentials keyed by the URL of the feed they belong to.
+    pub feeds: BTreeMap<String, Credential>,
+    /// Credentials for publishing, keyed by the name of the publish target.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub publish: BTreeMap<String, Credential>,
+    /// The credential for uploading images to the media backend.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub media: Option<Credential>,
+}
+
+impl Secrets {
+    /// Loads the credentials from disk, or returns an empty set if there are none.
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        let path = secrets_path()?;
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        warn_if_readable_by_others(&path);
+
+        let contents = fs::read_to_string(&path)?;
+        Ok(toml::from_str(&contents)?)
+    }
+
+    /// Saves the credentials to disk, readable only by the current user.
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let path = secrets_path()?;
+        let toml_string = toml::to_string_pretty(self)?;
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let mut file = options.open(&path)?;
+
+        // The mode above only applies when the file is created
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        }
+
+        file.write_all(toml_string.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Returns the credential stored for a feed.
+pub fn feed_credential(feed_url: &str) -> Option<Credential> {
+    SECRETS.read().ok()?.feeds.get(feed_url).cloned()
+}
+
+/// Stores (or with `None`, removes) the credential for a feed and saves it to disk.
+pub fn set_feed_credential(
+    feed_url: &str,
+    credential: Option<Credential>,
+) -> Result<(), Box<dyn Error>> {
+    let mut secrets = SECRETS.write().map_err(|e| e.to_string())?;
+
+    let changed = match credential {
+        Some(credential) => {
+            secrets
+                .feeds
+                .insert(feed_url.to_string(), credential.clone())
+                != Some(credential)
+        }
+        None => secrets.feeds.remove(feed_url).is_some(),
+    };
+
+    if changed {
+        secrets.save()?;
+    }
+    Ok(())
+}
+
+/// Returns the credential used to publish to the target named `name`.
+pub fn publish_credential(name: &str) -> Option<Credential> {
+    SECRETS.read().ok()?.publish.get(name).cloned()
+}
+
+/// Returns the credential used to upload images to the media backend.
+pub fn media_credential() -> Option<Credential> {
+    SECRETS.read().ok()?.media.clone()
+}
+
+/// Returns the credential to send with a request to `url`, if any.
+///
+/// Credentials are only ever sent to the same origin (scheme, host and port) as the
+/// feed they belong to. See [`select_credential`] for which one is picked.
+pub fn credential_for_request(url: &str) -> Option<Credential> {
+    let secrets = SECRETS.read().ok()?;
+    select_credential(&secrets.feeds, url).cloned()
+}
+
+/// Picks the credential from `feeds` to send with a request to `url`.
+///
+/// The feed's own credential is preferred. Otherwise, when several feeds on the same
+/// origin have credentials, the one whose directory is the longest prefix of the
+/// request's path is used, and none is sent if that doesn't settle it.
+fn select_credential<'a>(
+    feeds: &'a BTreeMap<String, Credential>,
+    url: &str,
+) -> Option<&'a Credential> {
+    if feeds.is_empty() {
+        return None;
+    }
+
+    let feed_id = FeedId::from_url(url);
+    if let Some((_, credential)) = feeds
+        .iter()
+        .find(|(feed_url, _)| FeedId::from_url(feed_url) == feed_id)
+    {
+        return Some(credential);
+    }
+
+    let request = Url::parse(url).ok()?;
+    let origin = request.origin();
+    if !origin.is_tuple() {
+        return None;
+    }
+
+    let same_origin: Vec<(Url, &Credential)> = feeds
+        .iter()
+        .filter_map(|(feed_url, credential)| Some((Url::parse(feed_url).ok()?, credential)))
+        .filter(|(feed_url, _)| feed_url.origin() == origin)
+        .collect();
+    if let [(_, credential)] = same_origin.as_slice() {
+        return Some(credential);
+    }
+
+    let mut matches: Vec<(usize, &Credential)> = same_origin
+        .iter()
+        .filter_map(|(feed_url, credential)| {
+            let dir = &feed_url.path()[..=feed_url.path().rfind('/')?];
+            request
+                .path()
+                .starts_with(dir)
+                .then_some((dir.len(), *credential))
+        })
+        .collect();
+    matches.sort_by_key(|(len, _)| std::cmp::Reverse(*len));
+
+    match matches.as_slice() {
+        [(_, credential)] => Some(credential),
+        [(longest, credential), (next, _), ..] if longest > next => Some(credential),
+        _ => None,
+    }
+}
+
+/// Warns when the secrets file can be read by other users.
+#[cfg(unix)]
+fn warn_if_readable_by_others(path: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+    if let Ok(meta) = fs::metadata(path)
+        && meta.permissions().mode() & 0o077 != 0
+    {
+        tracing::warn!(
+            "{} is accessible by other users, consider running `chmod 600` on it",
+            path.display()
+        );
+    }
+}
+
+#[cfg(not(unix))]
+fn warn_if_readable_by_others(_path: &Path) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bearer(token: &str) -> Credential {
+        Credential::Bearer {
+            token: token.to_string(),
+        }
+    }
+
+    #[test]
+    fn prefers_the_feeds_own_credential() {
+        let feeds = BTreeMap::from([
+            ("https://example.com/a/twtxt.txt".to_string(), bearer("a")),
+            ("https://example.com/twtxt.txt".to_string(), bearer("root")),
+        ]);
+        assert_eq!(
+            select_credential(&feeds, "https://EXAMPLE.com/twtxt.txt"),
+            Some(&bearer("root"))
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_longest_path_prefix_on_the_origin() {
+        let feeds = BTreeMap::from([
+            ("https://example.com/a/twtxt.txt".to_string(), bearer("a")),
+            ("https://example.com/twtxt.txt".to_string(), bearer("root")),
+        ]);
+        assert_eq!(
+            select_credential(&feeds, "https://example.com/a/avatar.png"),
+            Some(&bearer("a"))
+        );
+        assert_eq!(
+            select_credential(&feeds, "https://example.com/b/avatar.png"),
+            Some(&bearer("root"))
+        );
+        assert_eq!(
+            select_credential(&feeds, "https://example.org/a/avatar.png"),
+            None
+        );
+    }
+
+    #[test]
+    fn sends_nothing_when_the_fallback_is_ambiguous() {
+        let feeds = BTreeMap::from([
+            ("https://example.com/a/twtxt.txt".to_string(), bearer("a")),
+            ("https://example.com/a/archive.txt".to_string(), bearer("b")),
+            ("https://example.com/c/twtxt.txt".to_string(), bearer("c")),
+        ]);
+        assert_eq!(
+            select_credential(&feeds, "https://example.com/a/avatar.png"),
+            None
+        );
+        assert_eq!(
+            select_credential(&feeds, "https://example.com/avatar.png"),
+            None
+        );
+    }
+
+    #[test]
+    fn a_single_credential_on_the_origin_is_used() {
+        let feeds = BTreeMap::from([("https://example.com/a/twtxt.txt".to_string(), bearer("a"))]);
+        assert_eq!(
+            select_credential(&feeds, "https://example.com/media/avatar.png"),
+            Some(&bearer("a"))
+        );
+    }
+}
@@ -7,6 +7,7 @@ mod components;
 mod config;
 mod logging;
 mod pages;
+mod publish;
 mod twtxt;
 mod utils;
 
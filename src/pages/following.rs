@@ -1,17 +1,70 @@
 //! A page for managing the list of followed feeds.
 
+use std::fmt;
+
 use iced::{
     Alignment, Element, Length,
-    widget::{button, column, row, scrollable, text, text_input},
+    widget::{Row, button, column, pick_list, row, scrollable, text, text_input},
 };
+use tracing::error;
 
 use crate::{
-    config::AppConfig,
-    utils::styling::{toolbar_button_style, toolbar_sinput_style},
+    config::{
+        AppConfig, MediaPolicy,
+        secrets::{Credential, feed_credential, set_feed_credential},
+    },
+    utils::styling::{
+        sec_pick_list_style, sec_pick_menu_style, secondary_text, toolbar_button_style,
+        toolbar_sinput_style,
+    },
 };
 
 use crate::twtxt::metadata::Link;
 
+/// The kind of authentication used for a followed feed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AuthKind {
+    #[default]
+    None,
+    Basic,
+    Bearer,
+}
+
+impl AuthKind {
+    pub const ALL: &'static [AuthKind] = &[AuthKind::None, AuthKind::Basic, AuthKind::Bearer];
+}
+
+impl fmt::Display for AuthKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthKind::None => write!(f, "No authentication"),
+            AuthKind::Basic => write!(f, "Basic (username/password)"),
+            AuthKind::Bearer => write!(f, "Bearer token"),
+        }
+    }
+}
+
+/// A per-feed media policy, where `None` means the global policy is used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MediaOverride(pub Option<MediaPolicy>);
+
+impl MediaOverride {
+    fn options() -> Vec<MediaOverride> {
+        std::iter::once(MediaOverride(None))
+            .chain(MediaPolicy::ALL.iter().map(|p| MediaOverride(Some(*p))))
+            .collect()
+    }
+}
+
+impl fmt::Display for MediaOverride {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(policy) => write!(f, "{}", policy),
+            None => write!(f, "Default media policy"),
+        }
+    }
+}
+
 /// State for the following page.
 ///
 /// Holds the current editing state and the in-progress form fields.
@@ -23,6 +76,15 @@ pub struct FollowingPage {
     pub editing: Option<String>,
     pub edit_name: String,
     pub edit_url: String,
+
+    /// Credentials for the feed being edited, stored separately from the config.
+    pub edit_auth: AuthKind,
+    pub edit_username: String,
+    pub edit_password: String,
+    pub edit_token: String,
+
+    /// The media policy override for the feed being edited.
+    pub edit_media: MediaOverride,
 }
 
 /// Messages used to update the following page.
@@ -43,6 +105,18 @@ pub enum Message {
     EditNameChanged(String),
     /// Change the edit URL field.
     EditUrlChanged(String),
+    /// Change the authentication kind of the edited follow item.
+    EditAuthChanged(AuthKind),
+    /// Change the edit username field.
+    EditUsernameChanged(String),
+    /// Change the edit password field.
+    EditPasswordChanged(String),
+    /// Change the edit token field.
+    EditTokenChanged(String),
+    /// Change the media policy override of the edited follow item.
+    EditMediaChanged(MediaOverride),
+    /// Change the global media policy.
+    MediaPolicyChanged(MediaPolicy),
     /// Save the edited follow item.
     SaveEdit,
     /// Cancel the current edit.
@@ -69,6 +143,14 @@ impl FollowingPage {
             }
 
             Message::RemovePressed(name) => {
+                if let Some(link) = config.metadata.follows.iter().find(|l| l.text == name)
+                    && let Err(e) = set_feed_credential(&link.url, None)
+                {
+                    error!("Following: failed to remove credentials: {}", e);
+                }
+                if let Some(link) = config.metadata.follows.iter().find(|l| l.text == name) {
+                    config.privacy.media_overrides.remove(&link.url);
+                }
                 config.metadata.follows.retain(|l| l.text != name);
                 let _ = config.save();
             }
@@ -78,11 +160,40 @@ impl FollowingPage {
                     self.editing = Some(name.clone());
                     self.edit_name = link.text.clone();
                     self.edit_url = link.url.clone();
+
+                    self.edit_username.clear();
+                    self.edit_password.clear();
+                    self.edit_token.clear();
+                    self.edit_auth = match feed_credential(&link.url) {
+                        Some(Credential::Basic { username, password }) => {
+                            self.edit_username = username;
+                            self.edit_password = password;
+                            AuthKind::Basic
+                        }
+                        Some(Credential::Bearer { token }) => {
+                            self.edit_token = token;
+                            AuthKind::Bearer
+                        }
+                        None => AuthKind::None,
+                    };
+
+                    self.edit_media =
+                        MediaOverride(config.privacy.media_overrides.get(&link.url).copied());
                 }
             }
 
             Message::EditNameChanged(v) => self.edit_name = v,
             Message::EditUrlChanged(v) => self.edit_url = v,
+            Message::EditAuthChanged(v) => self.edit_auth = v,
+            Message::EditUsernameChanged(v) => self.edit_username = v,
+            Message::EditPasswordChanged(v) => self.edit_password = v,
+            Message::EditTokenChanged(v) => self.edit_token = v,
+            Message::EditMediaChanged(v) => self.edit_media = v,
+
+            Message::MediaPolicyChanged(policy) => {
+                config.privacy.media = policy;
+                let _ = config.save();
+            }
 
             Message::SaveEdit => {
                 if let Some(old_name) = self.editing.take()
@@ -92,6 +203,23 @@ impl FollowingPage {
                         .iter_mut()
                         .find(|l| l.text == old_name)
                 {
+                    if link.url != self.edit_url
+                        && let Err(e) = set_feed_credential(&link.url, None)
+                    {
+                        error!("Following: failed to remove credentials: {}", e);
+                    }
+                    if let Err(e) = set_feed_credential(&self.edit_url, self.edited_credential()) {
+                        error!("Following: failed to save credentials: {}", e);
+                    }
+
+                    config.privacy.media_overrides.remove(&link.url);
+                    if let MediaOverride(Some(policy)) = self.edit_media {
+                        config
+                            .privacy
+                            .media_overrides
+                            .insert(self.edit_url.clone(), policy);
+                    }
+
                     link.text = self.edit_name.clone();
                     link.url = self.edit_url.clone();
                     let _ = config.save();
@@ -104,6 +232,83 @@ impl FollowingPage {
         }
     }
 
+    /// Builds the credential entered in the edit fields.
+    fn edited_credential(&self) -> Option<Credential> {
+        match self.edit_auth {
+            AuthKind::None => None,
+            AuthKind::Basic => Some(Credential::Basic {
+                username: self.edit_username.clone(),
+                password: self.edit_password.clone(),
+            }),
+            AuthKind::Bearer => Some(Credential::Bearer {
+                token: self.edit_token.clone(),
+            }),
+        }
+    }
+
+    /// The authentication and media fields shown while editing a follow item.
+    fn extra_edit_fields(&self) -> Row<'_, Message> {
+        let fields = row![
+            pick_list(
+                AuthKind::ALL,
+                Some(self.edit_auth),
+                Message::EditAuthChanged
+            )
+            .width(Length::Fill)
+            .padding(8)
+            .style(sec_pick_list_style)
+            .menu_style(sec_pick_menu_style),
+        ]
+        .spacing(8)
+        .align_y(Alignment::Center);
+
+        let fields = match self.edit_auth {
+            AuthKind::None => fields.push(
+                text("Credentials are stored outside config.toml")
+                    .width(Length::FillPortion(2))
+                    .style(|theme| text::Style {
+                        color: Some(secondary_text(theme)),
+                    }),
+            ),
+            AuthKind::Basic => fields
+                .push(
+                    text_input("Username", &self.edit_username)
+                        .on_input(Message::EditUsernameChanged)
+                        .width(Length::Fill)
+                        .padding(8)
+                        .style(toolbar_sinput_style),
+                )
+                .push(
+                    text_input("Password", &self.edit_password)
+                        .on_input(Message::EditPasswordChanged)
+                        .secure(true)
+                        .width(Length::Fill)
+                        .padding(8)
+                        .style(toolbar_sinput_style),
+                ),
+            AuthKind::Bearer => fields.push(
+                text_input("Token", &self.edit_token)
+                    .on_input(Message::EditTokenChanged)
+                    .secure(true)
+                    .width(Length::FillPortion(2))
+                    .padding(8)
+                    .style(toolbar_sinput_style),
+            ),
+        };
+
+        fields.push(
+            pick_list(
+                MediaOverride::options(),
+                Some(self.edit_media),
+                Message::EditMediaChanged,
+            )
+            .width(Length::Fixed(175.0))
+            .padding(8)
+            .style(sec_pick_list_style)
+            .menu_style(sec_pick_menu_style),
+        )
+    }
+
     pub fn view(&self, config: &AppConfig) -> Element<'_, Message> {
         let mut list = column!().spacing(8);
 
@@ -114,38 +319,42 @@ impl FollowingPage {
             if self.editing.as_deref() == Some(name) {
                 // Editing mode
                 list = list.push(
-                    row![
-                        text_input("Name", &self.edit_name)
-                            .on_input(Message::EditNameChanged)
-                            .width(Length::Fill)
-                            .padding(8)
-                            .style(toolbar_sinput_style),
-                        text_input("URL", &self.edit_url)
-                            .on_input(Message::EditUrlChanged)
-                            .width(Length::FillPortion(2))
-                            .padding(8)
-                            .style(toolbar_sinput_style),
+                    column![
                         row![
-                            button(text("Save").align_x(Alignment::Center).width(Length::Fill))
-                                .on_press(Message::SaveEdit)
+                            text_input("Name", &self.edit_name)
+                                .on_input(Message::EditNameChanged)
+                                .width(Length::Fill)
+                                .padding(8)
+                                .style(toolbar_sinput_style),
+                            text_input("URL", &self.edit_url)
+                                .on_input(Message::EditUrlChanged)
+                                .width(Length::FillPortion(2))
+                                .padding(8)
+                                .style(toolbar_sinput_style),
+                            row![
+                                button(text("Save").align_x(Alignment::Center).width(Length::Fill))
+                                    .on_press(Message::SaveEdit)
+                                    .width(Length::Fill)
+                                    .padding([8, 16])
+                                    .style(toolbar_button_style),
+                                button(
+                                    text("Cancel")
+                                        .align_x(Alignment::Center)
+                                        .width(Length::Fill)
+                                )
+                                .on_press(Message::CancelEdit)
                                 .width(Length::Fill)
                                 .padding([8, 16])
                                 .style(toolbar_button_style),
-                            button(
-                                text("Cancel")
-                                    .align_x(Alignment::Center)
-                                    .width(Length::Fill)
-                            )
-                            .on_press(Message::CancelEdit)
-                            .width(Length::Fill)
-                            .padding([8, 16])
-                            .style(toolbar_button_style),
+                            ]
+                            .width(Length::Fixed(175.0))
+                            .spacing(8)
                         ]
-                        .width(Length::Fixed(175.0))
                         .spacing(8)
+                        .align_y(Alignment::Center),
+                        self.extra_edit_fields(),
                     ]
-                    .spacing(8)
-                    .align_y(Alignment::Center),
+                    .spacing(8),
                 );
             } else {
                 // Normal mode
@@ -201,7 +410,23 @@ impl FollowingPage {
         ]
         .spacing(8);
 
+        let media_section = row![
+            text("Remote media (avatars, images and link previews)").width(Length::Fill),
+            pick_list(
+                MediaPolicy::ALL,
+                Some(config.privacy.media),
+                Message::MediaPolicyChanged
+            )
+            .width(Length::Fixed(175.0))
+            .padding(8)
+            .style(sec_pick_list_style)
+            .menu_style(sec_pick_menu_style),
+        ]
+        .spacing(8)
+        .align_y(Alignment::Center);
+
         column![
+            media_section,
             add_section,
             scrollable(list).height(Length::Fill).spacing(8)
         ]
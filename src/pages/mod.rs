@@ -1,5 +1,7 @@
 //! Page components for twtGUI.
 
 pub mod following;
+pub mod scheduled;
+pub mod settings;
 pub mod timeline;
 pub mod view;
@@ -0,0 +1,327 @@
+//! A page listing the posts scheduled for later, and posting them when they're due.
+
+use chrono::{DateTime, Utc};
+use iced::{
+    Alignment, Element, Length, Task, Theme,
+    widget::{button, column, row, scrollable, space, text, text_editor, text_input},
+};
+use tracing::{error, info};
+
+use crate::{
+    config::AppConfig,
+    twtxt::{
+        Tweet, compose_twtxt_tweet,
+        schedule::{Schedule, format_local_time, parse_local_time},
+    },
+    utils::styling::{
+        secondary_text, toolbar_button_style, toolbar_minput_style, toolbar_sinput_style,
+    },
+};
+
+/// The state for the scheduled posts page.
+pub struct ScheduledPage {
+    schedule: Schedule,
+    /// The post currently being published, if any.
+    posting: Option<u64>,
+
+    /// The post being edited, if any.
+    editing: Option<u64>,
+    edit_text: text_editor::Content,
+    edit_time: String,
+    edit_error: Option<String>,
+}
+
+/// Messages used to update the scheduled posts page.
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// Post anything that has come due.
+    Tick,
+    /// A scheduled post was published, or publishing it failed.
+    PostFinished {
+        id: u64,
+        result: Box<Result<Tweet, String>>,
+        by_active: bool,
+    },
+    /// A scheduled post was published, as the active identity if `by_active`. Handled
+    /// by the app to show it on the timeline.
+    Published { tweet: Box<Tweet>, by_active: bool },
+
+    /// Start editing a queued post.
+    EditPressed(u64),
+    /// The text of the edited post changed.
+    EditTextChanged(text_editor::Action),
+    /// The time of the edited post changed.
+    EditTimeChanged(String),
+    /// Save the edited post.
+    SaveEdit,
+    /// Stop editing without saving.
+    CancelEdit,
+    /// Remove a post from the queue.
+    CancelPost(u64),
+}
+
+impl ScheduledPage {
+    /// Creates the page, catching up on any posts that came due while the app was closed.
+    pub fn new() -> (Self, Task<Message>) {
+        (
+            Self {
+                schedule: Schedule::load(),
+                posting: None,
+                editing: None,
+                edit_text: text_editor::Content::new(),
+                edit_time: String::new(),
+                edit_error: None,
+            },
+            Task::done(Message::Tick),
+        )
+    }
+
+    /// Whether anything is waiting to be posted, so the app knows to keep checking.
+    pub fn has_pending(&self) -> bool {
+        self.schedule.list().iter().any(|p| p.error.is_none())
+    }
+
+    /// Queues a post from the composer, to be posted as the identity whose feed is at
+    /// `identity`.
+    pub fn add(
+        &mut self,
+        text: String,
+        publish_at: DateTime<Utc>,
+        identity: String,
+    ) -> Task<Message> {
+        let id = self.schedule.add(text, publish_at, identity);
+        info!(
+            "Scheduled post {} for {}",
+            id,
+            format_local_time(publish_at)
+        );
+        Task::done(Message::Tick)
+    }
+
+    pub fn update(&mut self, message: Message, config: &AppConfig) -> Task<Message> {
+        match message {
+            Message::Tick => self.post_next_due(config),
+
+            Message::PostFinished {
+                id,
+                result,
+                by_active,
+            } => {
+                self.posting = None;
+                let published = match *result {
+                    Ok(tweet) => {
+                        info!("Published scheduled post {}", id);
+                        self.schedule.remove(id);
+                        Task::done(Message::Published {
+                            tweet: Box::new(tweet),
+                            by_active,
+                        })
+                    }
+                    Err(e) => {
+                        error!("Failed to publish scheduled post {}: {}", id, e);
+                        self.schedule.set_error(id, e);
+                        Task::none()
+                    }
+                };
+
+                // Posts missed while the app was closed are published one after another
+                Task::batch([published, self.post_next_due(config)])
+            }
+
+            Message::Published { .. } => Task::none(),
+
+            Message::EditPressed(id) => {
+                if let Some(post) = self.schedule.get(id) {
+                    self.editing = Some(id);
+                    self.edit_text = text_editor::Content::with_text(&post.text);
+                    self.edit_time = format_local_time(post.publish_at);
+                    self.edit_error = None;
+                }
+                Task::none()
+            }
+
+            Message::EditTextChanged(action) => {
+                self.edit_text.perform(action);
+                Task::none()
+            }
+
+            Message::EditTimeChanged(v) => {
+                self.edit_time = v;
+                Task::none()
+            }
+
+            Message::SaveEdit => {
+                let Some(id) = self.editing else {
+                    return Task::none();
+                };
+
+                match parse_local_time(&self.edit_time) {
+                    Ok(publish_at) => {
+                        self.schedule.update(id, self.edit_text.text(), publish_at);
+                        self.editing = None;
+                        Task::done(Message::Tick)
+                    }
+                    Err(e) => {
+                        self.edit_error = Some(e);
+                        Task::none()
+                    }
+                }
+            }
+
+            Message::CancelEdit => {
+                self.editing = None;
+                Task::none()
+            }
+
+            Message::CancelPost(id) => {
+                self.schedule.remove(id);
+                if self.editing == Some(id) {
+                    self.editing = None;
+                }
+                Task::none()
+            }
+        }
+    }
+
+    /// Starts publishing the earliest due post, unless one is already being published.
+    ///
+    /// The post goes through the same path as one from the composer, so it's timestamped
+    /// with the time it's actually published and the hook scripts run as usual.
+    fn post_next_due(&mut self, config: &AppConfig) -> Task<Message> {
+        if self.posting.is_some() {
+            return Task::none();
+        }
+
+        // The post being edited waits until it's saved, but the ones after it don't
+        let Some(post) = self.schedule.next_due(Utc::now(), self.editing) else {
+            return Task::none();
+        };
+
+        let id = post.id;
+        let posting_config = match &post.identity {
+            Some(identity) => config.for_feed(identity),
+            None => Some(config.clone()),
+        };
+        let Some(posting_config) = posting_config else {
+            let e = "The identity this was scheduled as no longer exists".to_string();
+            error!("Failed to publish scheduled post {}: {}", id, e);
+            self.schedule.set_error(id, e);
+            return self.post_next_due(config);
+        };
+
+        let by_active = posting_config.paths.twtxt == config.paths.twtxt;
+        self.posting = Some(id);
+        Task::perform(
+            compose_twtxt_tweet(post.text.clone(), posting_config),
+            move |result| Message::PostFinished {
+                id,
+                result: Box::new(result),
+                by_active,
+            },
+        )
+    }
+
+    pub fn view(&self, theme: &Theme) -> Element<'_, Message> {
+        if self.schedule.list().is_empty() {
+            return text("No scheduled posts. Use \"Schedule\" in the composer to add one.")
+                .color(secondary_text(theme))
+                .into();
+        }
+
+        let mut list = column!().spacing(8);
+
+        for post in self.schedule.list() {
+            if self.editing == Some(post.id) {
+                let edit_error: Element<Message> = match &self.edit_error {
+                    Some(e) => text(e).color(theme.palette().danger).into(),
+                    None => space().into(),
+                };
+
+                list = list.push(
+                    column![
+                        text_editor(&self.edit_text)
+                            .on_action(Message::EditTextChanged)
+                            .height(150)
+                            .padding(8)
+                            .style(toolbar_minput_style),
+                        row![
+                            text_input("YYYY-MM-DD HH:MM", &self.edit_time)
+                                .on_input(Message::EditTimeChanged)
+                                .on_submit(Message::SaveEdit)
+                                .width(Length::Fill)
+                                .padding(8)
+                                .style(toolbar_sinput_style),
+                            row![
+                                button(text("Save").align_x(Alignment::Center).width(Length::Fill))
+                                    .on_press(Message::SaveEdit)
+                                    .width(Length::Fill)
+                                    .padding([8, 16])
+                                    .style(toolbar_button_style),
+                                button(
+                                    text("Cancel")
+                                        .align_x(Alignment::Center)
+                                        .width(Length::Fill)
+                                )
+                                .on_press(Message::CancelEdit)
+                                .width(Length::Fill)
+                                .padding([8, 16])
+                                .style(toolbar_button_style),
+                            ]
+                            .width(Length::Fixed(175.0))
+                            .spacing(8)
+                        ]
+                        .spacing(8)
+                        .align_y(Alignment::Center),
+                        edit_error,
+                    ]
+                    .spacing(8),
+                );
+                continue;
+            }
+
+            let is_posting = self.posting == Some(post.id);
+            let status: Element<Message> = if is_posting {
+                text("Posting...").color(secondary_text(theme)).into()
+            } else if let Some(e) = &post.error {
+                text(format!("Failed: {}", e))
+                    .color(theme.palette().danger)
+                    .into()
+            } else {
+                text(format_local_time(post.publish_at))
+                    .color(secondary_text(theme))
+                    .into()
+            };
+
+            list = list.push(
+                row![
+                    column![text(&post.text), status]
+                        .spacing(4)
+                        .padding(8)
+                        .width(Length::Fill),
+                    row![
+                        button(text("Edit").align_x(Alignment::Center).width(Length::Fill))
+                            .on_press_maybe((!is_posting).then_some(Message::EditPressed(post.id)))
+                            .width(Length::Fill)
+                            .padding([8, 16])
+                            .style(toolbar_button_style),
+                        button(
+                            text("Cancel")
+                                .align_x(Alignment::Center)
+                                .width(Length::Fill)
+                        )
+                        .on_press_maybe((!is_posting).then_some(Message::CancelPost(post.id)))
+                        .width(Length::Fill)
+                        .padding([8, 16])
+                        .style(toolbar_button_style),
+                    ]
+                    .width(Length::Fixed(175.0))
+                    .spacing(8)
+                ]
+                .spacing(8)
+                .align_y(Alignment::Center),
+            );
+        }
+
+        scrollable(list).height(Length::Fill).spacing(8).into()
+    }
+}
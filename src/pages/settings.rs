@@ -0,0 +1,546 @@
+//! A page for editing the user's profile and the paths in `config.toml`.
+//!
+//! Changes are checked before they're saved, and anything wrong with them is shown
+//! next to the field it's about.
+
+use std::{collections::HashMap, path::Path};
+
+use iced::{
+    Alignment, Element, Length, Task, Theme,
+    widget::{button, column, row, scrollable, space, text, text_editor, text_input},
+};
+use reqwest::Url;
+use tracing::{error, info};
+
+use crate::{
+    config::AppConfig,
+    twtxt::metadata::Link,
+    utils::styling::{
+        secondary_text, toolbar_button_style, toolbar_minput_style, toolbar_sinput_style,
+    },
+};
+
+/// The settings that can be edited on the page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Field {
+    Nick,
+    Urls,
+    Avatar,
+    Description,
+    Kind,
+    Links,
+    Twtxt,
+    PreTweetScript,
+    TweetScript,
+    PostTweetScript,
+}
+
+/// State for the settings page.
+///
+/// Holds the settings as they're being edited, which only reach the config once they
+/// are saved.
+pub struct SettingsPage {
+    nick: String,
+    /// The feed's URLs, one per line.
+    urls: text_editor::Content,
+    avatar: String,
+    description: String,
+    kind: String,
+    /// The profile links, one `Name URL` per line.
+    links: text_editor::Content,
+    twtxt: String,
+    pre_tweet_script: String,
+    tweet_script: String,
+    post_tweet_script: String,
+
+    /// What's wrong with each field, from the last time saving was attempted.
+    errors: HashMap<Field, String>,
+    /// Why the config couldn't be written, if it couldn't.
+    save_error: Option<String>,
+    /// Whether the settings were saved and haven't been edited since.
+    saved: bool,
+}
+
+/// Messages used to update the settings page.
+#[derive(Debug, Clone)]
+pub enum Message {
+    NickChanged(String),
+    UrlsEdited(text_editor::Action),
+    AvatarChanged(String),
+    DescriptionChanged(String),
+    KindChanged(String),
+    LinksEdited(text_editor::Action),
+    TwtxtChanged(String),
+    PreTweetScriptChanged(String),
+    TweetScriptChanged(String),
+    PostTweetScriptChanged(String),
+    /// Check the settings and save them.
+    SavePressed,
+    /// Throw away the edits and show the saved settings again.
+    ResetPressed,
+    /// The settings were saved. Handled by the app to reload what depends on them.
+    Saved,
+}
+
+impl SettingsPage {
+    /// Creates the page showing the config's current settings.
+    pub fn new(config: &AppConfig) -> Self {
+        let metadata = &config.metadata;
+        let paths = &config.paths;
+        let links = metadata
+            .links
+            .iter()
+            .map(|link| format!("{} {}", link.text, link.url))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Self {
+            nick: metadata.nick.clone().unwrap_or_default(),
+            urls: text_editor::Content::with_text(&metadata.urls.join("\n")),
+            avatar: metadata.avatar.clone().unwrap_or_default(),
+            description: metadata.description.clone().unwrap_or_default(),
+            kind: metadata.kind.clone().unwrap_or_default(),
+            links: text_editor::Content::with_text(&links),
+            twtxt: paths.twtxt.clone(),
+            pre_tweet_script: paths.pre_tweet_script.clone().unwrap_or_default(),
+            tweet_script: paths.tweet_script.clone().unwrap_or_default(),
+            post_tweet_script: paths.post_tweet_script.clone().unwrap_or_default(),
+            errors: HashMap::new(),
+            save_error: None,
+            saved: false,
+        }
+    }
+
+    pub fn update(&mut self, message: Message, config: &mut AppConfig) -> Task<Message> {
+        match message {
+            Message::SavePressed => return self.save(config),
+            Message::Saved => return Task::none(),
+            Message::ResetPressed => *self = Self::new(config),
+
+            Message::NickChanged(v) => self.nick = v,
+            Message::UrlsEdited(action) => self.urls.perform(action),
+            Message::AvatarChanged(v) => self.avatar = v,
+            Message::DescriptionChanged(v) => self.description = v,
+            Message::KindChanged(v) => self.kind = v,
+            Message::LinksEdited(action) => self.links.perform(action),
+            Message::TwtxtChanged(v) => self.twtxt = v,
+            Message::PreTweetScriptChanged(v) => self.pre_tweet_script = v,
+            Message::TweetScriptChanged(v) => self.tweet_script = v,
+            Message::PostTweetScriptChanged(v) => self.post_tweet_script = v,
+        }
+
+        self.saved = false;
+        Task::none()
+    }
+
+    /// Checks the edited settings and, if they're all valid, saves them to the config.
+    fn save(&mut self, config: &mut AppConfig) -> Task<Message> {
+        self.errors.clear();
+        self.save_error = None;
+        self.saved = false;
+
+        let urls: Vec<String> = non_empty_lines(&self.urls.text())
+            .map(str::to_string)
+            .collect();
+        let links = check_links(&self.links.text());
+
+        self.check(Field::Nick, check_nick(&self.nick));
+        for url in &urls {
+            self.check(Field::Urls, check_url(url));
+        }
+        if let Some(avatar) = non_empty(&self.avatar) {
+            self.check(Field::Avatar, check_url(&avatar));
+        }
+        if let Some(description) = non_empty(&self.description) {
+            self.check(Field::Description, check_single_line(&description));
+        }
+        self.check(Field::Twtxt, check_feed_path(&self.twtxt));
+        for (field, script) in [
+            (Field::PreTweetScript, non_empty(&self.pre_tweet_script)),
+            (Field::TweetScript, non_empty(&self.tweet_script)),
+            (Field::PostTweetScript, non_empty(&self.post_tweet_script)),
+        ] {
+            if let Some(script) = script {
+                self.check(field, check_script(&script));
+            }
+        }
+
+        let links = match links {
+            Ok(links) if self.errors.is_empty() => links,
+            Ok(_) => return Task::none(),
+            Err(e) => {
+                self.errors.insert(Field::Links, e);
+                return Task::none();
+            }
+        };
+
+        // The edits are only kept if the config could be written
+        let mut updated = config.clone();
+        updated.metadata.nick = non_empty(&self.nick);
+        updated.metadata.urls = urls;
+        updated.metadata.avatar = non_empty(&self.avatar);
+        updated.metadata.description = non_empty(&self.description);
+        updated.metadata.kind = non_empty(&self.kind);
+        updated.metadata.links = links;
+        updated.paths.twtxt = self.twtxt.trim().to_string();
+        updated.paths.pre_tweet_script = non_empty(&self.pre_tweet_script);
+        updated.paths.tweet_script = non_empty(&self.tweet_script);
+        updated.paths.post_tweet_script = non_empty(&self.post_tweet_script);
+
+        match updated.save() {
+            Ok(()) => {
+                info!("Settings: saved");
+                *config = updated;
+                self.saved = true;
+                Task::done(Message::Saved)
+            }
+            Err(e) => {
+                error!("Settings: failed to save: {}", e);
+                self.save_error = Some(format!("Failed to save config.toml: {}", e));
+                Task::none()
+            }
+        }
+    }
+
+    /// Records the first problem found with a field.
+    fn check(&mut self, field: Field, result: Result<(), String>) {
+        if let Err(e) = result {
+            self.errors.entry(field).or_insert(e);
+        }
+    }
+
+    pub fn view(&self, theme: &Theme) -> Element<'_, Message> {
+        let profile = column![
+            text("Profile").font(crate::app::BOLD_FONT),
+            self.field(
+                "Nick",
+                Field::Nick,
+                input("yournick", &self.nick, Message::NickChanged),
+                theme,
+            ),
+            self.field(
+                "URLs, one per line",
+                Field::Urls,
+                text_editor(&self.urls)
+                    .placeholder("https://example.com/twtxt.txt")
+                    .on_action(Message::UrlsEdited)
+                    .height(70)
+                    .padding(8)
+                    .style(toolbar_minput_style)
+                    .into(),
+                theme,
+            ),
+            self.field(
+                "Avatar",
+                Field::Avatar,
+                input(
+                    "https://example.com/avatar.png",
+                    &self.avatar,
+                    Message::AvatarChanged
+                ),
+                theme,
+            ),
+            self.field(
+                "Description",
+                Field::Description,
+                input(
+                    "A short bio",
+                    &self.description,
+                    Message::DescriptionChanged
+                ),
+                theme,
+            ),
+            self.field(
+                "Kind",
+                Field::Kind,
+                input("bot, rss, or empty", &self.kind, Message::KindChanged),
+                theme,
+            ),
+            self.field(
+                "Links, one Name URL per line",
+                Field::Links,
+                text_editor(&self.links)
+                    .placeholder("Website https://example.com")
+                    .on_action(Message::LinksEdited)
+                    .height(100)
+                    .padding(8)
+                    .style(toolbar_minput_style)
+                    .into(),
+                theme,
+            ),
+        ]
+        .spacing(8);
+
+        let paths = column![
+            text("Files").font(crate::app::BOLD_FONT),
+            self.field(
+                "twtxt.txt",
+                Field::Twtxt,
+                input("/path/to/twtxt.txt", &self.twtxt, Message::TwtxtChanged),
+                theme,
+            ),
+            self.field(
+                "Pre-tweet script",
+                Field::PreTweetScript,
+                input(
+                    "Run before a twt is written",
+                    &self.pre_tweet_script,
+                    Message::PreTweetScriptChanged,
+                ),
+                theme,
+            ),
+            self.field(
+                "Tweet script",
+                Field::TweetScript,
+                input(
+                    "Run with the twt as written",
+                    &self.tweet_script,
+                    Message::TweetScriptChanged,
+                ),
+                theme,
+            ),
+            self.field(
+                "Post-tweet script",
+                Field::PostTweetScript,
+                input(
+                    "Run after a twt is written",
+                    &self.post_tweet_script,
+                    Message::PostTweetScriptChanged,
+                ),
+                theme,
+            ),
+        ]
+        .spacing(8);
+
+        let status: Element<Message> = if let Some(e) = &self.save_error {
+            text(e).color(theme.palette().danger).into()
+        } else if !self.errors.is_empty() {
+            text("Fix the settings marked above to save them")
+                .color(theme.palette().danger)
+                .into()
+        } else if self.saved {
+            text("Saved").color(secondary_text(theme)).into()
+        } else {
+            space().into()
+        };
+
+        let buttons = row![
+            status,
+            space().width(Length::Fill),
+            button(text("Reset").align_x(Alignment::Center).width(Length::Fill))
+                .on_press(Message::ResetPressed)
+                .width(Length::Fixed(175.0))
+                .padding([8, 16])
+                .style(toolbar_button_style),
+            button(text("Save").align_x(Alignment::Center).width(Length::Fill))
+                .on_press(Message::SavePressed)
+                .width(Length::Fixed(175.0))
+                .padding([8, 16])
+                .style(toolbar_button_style),
+        ]
+        .spacing(8)
+        .align_y(Alignment::Center);
+
+        column![
+            scrollable(column![profile, paths].spacing(24).padding([0, 16])).height(Length::Fill),
+            buttons,
+        ]
+        .spacing(8)
+        .into()
+    }
+
+    /// A labelled setting, with what's wrong with it underneath.
+    fn field<'a>(
+        &'a self,
+        label: &'a str,
+        field: Field,
+        input: Element<'a, Message>,
+        theme: &Theme,
+    ) -> Element<'a, Message> {
+        let error: Element<Message> = match self.errors.get(&field) {
+            Some(e) => text(e).size(12).color(theme.palette().danger).into(),
+            None => space().into(),
+        };
+
+        row![
+            text(label).width(Length::Fixed(175.0)),
+            column![input, error].spacing(4).width(Length::Fill),
+        ]
+        .spacing(8)
+        .align_y(Alignment::Start)
+        .into()
+    }
+}
+
+fn input<'a>(
+    placeholder: &'a str,
+    value: &'a str,
+    on_input: fn(String) -> Message,
+) -> Element<'a, Message> {
+    text_input(placeholder, value)
+        .on_input(on_input)
+        .padding(8)
+        .style(toolbar_sinput_style)
+        .into()
+}
+
+/// Returns the trimmed value, or `None` if nothing was entered.
+fn non_empty(value: &str) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+fn non_empty_lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines().map(str::trim).filter(|line| !line.is_empty())
+}
+
+/// Nicks are used in mentions, which end at whitespace.
+fn check_nick(nick: &str) -> Result<(), String> {
+    if nick.trim().contains(char::is_whitespace) {
+        return Err("Nicks can't contain spaces".to_string());
+    }
+    Ok(())
+}
+
+fn check_url(url: &str) -> Result<(), String> {
+    let parsed = Url::parse(url).map_err(|e| format!("Invalid URL {}: {}", url, e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!("{} isn't an http or https URL", url));
+    }
+    Ok(())
+}
+
+/// Reads the profile links, written as `Name URL` on each line.
+fn check_links(text: &str) -> Result<Vec<Link>, String> {
+    non_empty_lines(text)
+        .enumerate()
+        .map(|(i, line)| {
+            let (name, url) = line
+                .rsplit_once(char::is_whitespace)
+                .ok_or_else(|| format!("Line {}: write links as Name URL", i + 1))?;
+            Url::parse(url).map_err(|e| format!("Line {}: invalid URL {}: {}", i + 1, url, e))?;
+            Ok(Link {
+                text: name.trim().to_string(),
+                url: url.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Metadata is written as one line of the feed's header.
+fn check_single_line(value: &str) -> Result<(), String> {
+    if value.contains('\n') {
+        return Err("This has to fit on one line".to_string());
+    }
+    Ok(())
+}
+
+/// The feed doesn't have to exist yet, since posting creates it, but its folder does.
+fn check_feed_path(path: &str) -> Result<(), String> {
+    let path = path.trim();
+    if path.is_empty() {
+        return Err("Set the path to your twtxt.txt".to_string());
+    }
+
+    let path = Path::new(path);
+    if path.is_dir() {
+        return Err(format!("{} is a folder", path.display()));
+    }
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+        && !parent.is_dir()
+    {
+        return Err(format!("The folder {} doesn't exist", parent.display()));
+    }
+    Ok(())
+}
+
+/// Hook scripts are run through the shell, so they only need to be readable files.
+fn check_script(path: &str) -> Result<(), String> {
+    let path = Path::new(path);
+    if !path.is_file() {
+        return Err(format!("There's no script at {}", path.display()));
+    }
+    std::fs::File::open(path)
+        .map(|_| ())
+        .map_err(|e| format!("Can't read {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_server::temp_dir;
+
+    #[test]
+    fn nicks_are_one_word() {
+        assert!(check_nick("alice").is_ok());
+        assert!(check_nick(" alice ").is_ok());
+        assert!(check_nick("alice smith").is_err());
+    }
+
+    #[test]
+    fn urls_are_http() {
+        assert!(check_url("https://example.com/twtxt.txt").is_ok());
+        assert!(check_url("http://example.com/avatar.png").is_ok());
+        assert!(check_url("gemini://example.com/twtxt.txt").is_err());
+        assert!(check_url("example.com/twtxt.txt").is_err());
+    }
+
+    #[test]
+    fn single_lines() {
+        assert!(check_single_line("Just me, posting").is_ok());
+        assert!(check_single_line("Two\nlines").is_err());
+    }
+
+    #[test]
+    fn links_are_a_name_and_a_url() {
+        let links =
+            check_links("\n  My blog  https://example.com/blog\nGit https://example.com/git\n")
+                .unwrap();
+        assert_eq!(
+            links,
+            [
+                Link {
+                    text: "My blog".to_string(),
+                    url: "https://example.com/blog".to_string(),
+                },
+                Link {
+                    text: "Git".to_string(),
+                    url: "https://example.com/git".to_string(),
+                },
+            ]
+        );
+
+        assert_eq!(
+            check_links("https://example.com/blog"),
+            Err("Line 1: write links as Name URL".to_string())
+        );
+        assert!(
+            check_links("Blog https://example.com\nGit example.com/git")
+                .unwrap_err()
+                .starts_with("Line 2: invalid URL example.com/git")
+        );
+    }
+
+    #[test]
+    fn feed_path_needs_an_existing_folder() {
+        let dir = temp_dir("settings-feed-path");
+
+        assert!(check_feed_path(&dir.join("twtxt.txt").to_string_lossy()).is_ok());
+        assert!(check_feed_path("twtxt.txt").is_ok());
+        assert!(check_feed_path("  ").is_err());
+        assert!(check_feed_path(&dir.to_string_lossy()).is_err());
+        assert!(check_feed_path(&dir.join("missing/twtxt.txt").to_string_lossy()).is_err());
+    }
+
+    #[test]
+    fn scripts_have_to_exist() {
+        let dir = temp_dir("settings-script");
+        let script = dir.join("post.sh");
+        std::fs::write(&script, "#!/bin/sh\n").unwrap();
+
+        assert!(check_script(&script.to_string_lossy()).is_ok());
+        assert!(check_script(&dir.to_string_lossy()).is_err());
+        assert!(check_script(&dir.join("missing.sh").to_string_lossy()).is_err());
+    }
+}
@@ -1,49 +1,74 @@
-//! A page that displays the user's timeline and allows composing new tweets.
+//! A page that displays the user's timeline, with their own feed and every feed they follow.
+
+use std::{collections::HashMap, sync::Arc};
 
-use bytes::Bytes;
 use iced::{
     Alignment, Element, Length, Task, Theme,
-    widget::{Stack, button, column, container, image::Handle, row, space, text, text_editor},
+    widget::{button, column, container, row, space, text},
 };
 
 use tracing::{error, info};
 
+use crate::components::composer::{EditTarget, orphan_warning_text};
+use crate::twtxt::feed_id::FeedId;
+use crate::twtxt::reply::{reply_prefix, subject_of, thread_members};
 use crate::twtxt::threading::build_threads;
 use crate::twtxt::{
-    Tweet, TweetNode, compose_twtxt_tweet, download_and_parse_twtxt, load_local_twtxt_feed,
+    Tweet, TweetNode, download_and_parse_twtxt, load_local_twtxt_feed, rewrite::read_twt,
+    rewrite_twtxt_tweet,
 };
-use crate::utils::download::{ParsedCache, download_binary};
+use crate::utils::download::ParsedCache;
+use crate::utils::media::{DecodedImage, MediaError, avatar_thumbnail};
+use crate::utils::privacy::MediaFilter;
 use crate::{
     components::threaded_feed::{self, LazyThreadedFeed},
-    utils::styling::toolbar_minput_style,
+    config::AppConfig,
+    utils::styling::toolbar_button_style,
 };
-use crate::{config::AppConfig, utils::styling::toolbar_button_style};
 
 /// The state for the timeline page.
 ///
 /// This page is responsible for showing a combined timeline from the user's
-/// own feed and any followed feeds, as well as providing a composer for new tweets.
+/// own feed and any followed feeds. New tweets are written in the shared composer.
 pub struct TimelinePage {
-    show_composer: bool,
-    composer: text_editor::Content,
     tweets: Vec<Tweet>,
     thread_tree: Vec<TweetNode>,
     pending_downloads: usize,
     feed: LazyThreadedFeed,
-    local_hash: Option<String>,
+    /// Whether a tweet is being deleted, which includes running the hook scripts.
+    deleting: bool,
+    /// Why the last deletion failed, shown in the delete banner.
+    delete_error: Option<String>,
+    /// Why archiving the feed last failed, shown until it's dismissed or archiving works.
+    archive_error: Option<String>,
+    /// The hash of the tweet waiting for the user to confirm its deletion.
+    confirm_delete: Option<String>,
+    /// The nicks loaded feeds declare for themselves, which replies mention them by.
+    nicks: HashMap<FeedId, String>,
 }
 
 /// Messages used to update the timeline page.
 #[derive(Debug, Clone)]
 pub enum Message {
-    /// The composer text was edited.
-    ComposerEdit(text_editor::Action),
-    /// Open/close the composer panel.
-    ToggleComposer,
-    /// Cancel the current composition.
-    CancelCompose,
-    /// Post the composed tweet.
-    PostPressed,
+    /// Start a reply in the thread with `subject`. Handled by the app, which owns the
+    /// composer.
+    Reply { subject: String, prefix: String },
+    /// Edit one of the user's own tweets. Handled by the app, which owns the composer.
+    Edit { target: EditTarget, text: String },
+    /// Confirm deleting the tweet that was asked to be deleted.
+    ConfirmDelete,
+    /// Keep the tweet that was asked to be deleted.
+    CancelDelete,
+    /// One of the user's own tweets was deleted from the local feed.
+    Deleted {
+        hash: String,
+        result: Box<Result<Option<Tweet>, String>>,
+    },
+    /// Move old twts out of the user's feed into an archive. Handled by the app, since
+    /// archiving updates the config.
+    ArchivePressed,
+    /// Hide the error from the last attempt at archiving.
+    DismissArchiveError,
     /// Refresh all feeds.
     Refresh,
     /// A feed finished loading (either local or remote).
@@ -55,8 +80,8 @@ pub enum Message {
     /// An avatar image has finished downloading.
     AvatarLoaded {
         url: String, // The URL of the feed these tweets belong to
-        result: Box<Result<Bytes, String>>,
-        hash: String,
+        result: Box<Result<DecodedImage, MediaError>>,
+        feed_id: FeedId,
     },
     /// Trigger a navigation to another page.
     RedirectToPage(crate::app::RedirectInfo),
@@ -69,13 +94,15 @@ impl TimelinePage {
         let (feed, feed_task) = LazyThreadedFeed::new(&[], &[]);
         (
             Self {
-                show_composer: false,
-                composer: text_editor::Content::new(),
                 tweets: Vec::new(),
                 thread_tree: Vec::new(),
                 pending_downloads: 0,
-                local_hash: None,
                 feed,
+                deleting: false,
+                delete_error: None,
+                archive_error: None,
+                confirm_delete: None,
+                nicks: HashMap::new(),
             },
             feed_task.map(Message::Feed),
         )
@@ -83,38 +110,66 @@ impl TimelinePage {
 
     pub fn update(&mut self, message: Message, config: &AppConfig) -> Task<Message> {
         match message {
-            Message::ComposerEdit(action) => {
-                self.composer.perform(action);
+            Message::Reply { .. } | Message::Edit { .. } => Task::none(),
+
+            Message::ArchivePressed => Task::none(),
+
+            Message::DismissArchiveError => {
+                self.archive_error = None;
                 Task::none()
             }
 
-            Message::ToggleComposer => {
-                self.show_composer = !self.show_composer;
-                Task::none()
+            Message::ConfirmDelete => {
+                let Some(hash) = self.confirm_delete.clone() else {
+                    return Task::none();
+                };
+                self.deleting = true;
+                self.delete_error = None;
+                Task::perform(
+                    rewrite_twtxt_tweet(hash.clone(), None, config.clone()),
+                    move |result| Message::Deleted {
+                        hash: hash.clone(),
+                        result: Box::new(result),
+                    },
+                )
             }
 
-            Message::CancelCompose => {
-                self.show_composer = false;
-                self.composer = text_editor::Content::new();
+            Message::CancelDelete => {
+                self.confirm_delete = None;
+                self.delete_error = None;
                 Task::none()
             }
 
-            Message::PostPressed => {
-                self.show_composer = false;
-                self.post_composed_tweet(config)
+            Message::Deleted { hash, result } => {
+                self.deleting = false;
+                match *result {
+                    Ok(_) => {
+                        info!("Timeline: deleted #{}", hash);
+                        self.confirm_delete = None;
+                        self.replace_tweet(&hash, None)
+                    }
+                    Err(e) => {
+                        error!("Timeline: failed to delete #{}: {}", hash, e);
+                        self.delete_error = Some(e);
+                        Task::none()
+                    }
+                }
             }
 
             Message::Refresh => {
                 self.tweets.clear();
                 self.thread_tree.clear();
+                self.nicks.clear();
                 self.feed.avatars.clear();
+                self.feed.hidden_avatars.clear();
+                self.feed.media_filter = Arc::new(MediaFilter::new(config));
+                self.feed.own_feed = config.metadata.urls.first().map(|u| FeedId::from_url(u));
                 let reset_task = self.feed.reset(&[], &[]).map(Message::Feed);
 
                 let mut tasks = Vec::new();
 
                 // handle local file
                 if let Some((nick, url, parsed)) = load_local_twtxt_feed(config) {
-                    self.local_hash = Some(parsed.content_hash.clone());
                     tasks.push(Task::done(Message::FeedLoaded {
                         nick,
                         url,
@@ -157,23 +212,35 @@ impl TimelinePage {
 
                 info!("Timeline: feed successfully loaded for {} @ {}", nick, url);
 
-                let content_hash = parsed.content_hash.clone();
+                let feed_id = parsed.feed_id.clone();
                 let avatar_url = parsed
                     .bundle
                     .metadata
                     .as_ref()
-                    .and_then(|m| m.avatar.clone());
-
+                    .and_then(|m| m.avatar.clone())
+                    .filter(|avatar_url| {
+                        let allowed = self.feed.media_filter.allows(&feed_id, avatar_url);
+                        if !allowed {
+                            self.feed
+                                .hidden_avatars
+                                .insert(feed_id.clone(), avatar_url.clone());
+                        }
+                        allowed
+                    });
+
+                if let Some(nick) = parsed.bundle.metadata.as_ref().and_then(|m| m.nick.clone()) {
+                    self.nicks.insert(feed_id.clone(), nick);
+                }
                 self.tweets.extend(parsed.bundle.tweets);
 
                 let avatar_task = avatar_url
                     .map(|avatar_url| {
                         self.pending_downloads += 1;
-                        Task::perform(download_binary(avatar_url), move |res| {
+                        Task::perform(avatar_thumbnail(avatar_url), move |res| {
                             Message::AvatarLoaded {
                                 url: url.clone(),
                                 result: Box::new(res),
-                                hash: content_hash.clone(),
+                                feed_id: feed_id.clone(),
                             }
                         })
                     })
@@ -182,11 +249,15 @@ impl TimelinePage {
                 Task::batch([self.decrement_pending(), avatar_task])
             }
 
-            Message::AvatarLoaded { url, result, hash } => {
+            Message::AvatarLoaded {
+                url,
+                result,
+                feed_id,
+            } => {
                 match *result {
-                    Ok(bytes) => {
+                    Ok(image) => {
                         info!("Timeline: avatar successfully loaded for {}", url);
-                        self.feed.avatars.insert(hash, Handle::from_bytes(bytes));
+                        self.feed.avatars.insert(feed_id, image.handle);
                     }
                     Err(e) => {
                         error!("Timeline: error loading avatar for {}: {}", url, e);
@@ -200,17 +271,52 @@ impl TimelinePage {
             }
 
             Message::Feed(threaded_feed::Message::ReplyClicked(index)) => {
-                if let Some(tweet) = self.tweets.get(index).cloned() {
-                    let hash = tweet.hash;
-                    let author = tweet.author;
-                    let source = tweet.url;
-
-                    self.show_composer = true;
-                    self.composer = text_editor::Content::with_text(
-                        format!("(#{}) @<{} {}> ", hash, author, source).as_str(),
-                    );
+                let Some(tweet) = self.tweets.get(index) else {
+                    return Task::none();
+                };
+
+                let thread = thread_members(&self.tweets, &self.thread_tree, index);
+                let prefix = reply_prefix(
+                    &self.tweets,
+                    index,
+                    &thread,
+                    self.feed.own_feed.as_ref(),
+                    &self.nicks,
+                );
+                Task::done(Message::Reply {
+                    subject: subject_of(tweet).to_string(),
+                    prefix,
+                })
+            }
+
+            Message::Feed(threaded_feed::Message::EditClicked(index)) => {
+                let Some(tweet) = self.tweets.get(index) else {
+                    return Task::none();
+                };
+
+                // The raw line is needed, since the tweet only keeps its rendered content
+                let url = config.metadata.urls.first().cloned().unwrap_or_default();
+                match read_twt(&config.paths.twtxt, &url, &tweet.hash) {
+                    Ok(twt) => Task::done(Message::Edit {
+                        text: twt.editable_text(),
+                        target: EditTarget {
+                            hash: tweet.hash.clone(),
+                            timestamp: twt.timestamp,
+                            replies_from_others: self.replies_from_others(&tweet.hash),
+                        },
+                    }),
+                    Err(e) => {
+                        error!("Timeline: can't edit #{}: {}", tweet.hash, e);
+                        Task::none()
+                    }
                 }
+            }
 
+            Message::Feed(threaded_feed::Message::DeleteClicked(index)) => {
+                if let Some(tweet) = self.tweets.get(index) {
+                    self.confirm_delete = Some(tweet.hash.clone());
+                    self.delete_error = None;
+                }
                 Task::none()
             }
 
@@ -220,11 +326,47 @@ impl TimelinePage {
         }
     }
 
+    /// Shows why archiving the feed failed, or with `None` clears the last error.
+    pub fn set_archive_error(&mut self, error: Option<String>) {
+        self.archive_error = error;
+    }
+
+    /// The tweets loaded on the timeline.
+    pub fn tweets(&self) -> &[Tweet] {
+        &self.tweets
+    }
+
+    /// Adds a tweet that was just posted to the timeline.
+    pub fn insert_tweet(&mut self, tweet: Tweet) -> Task<Message> {
+        self.tweets.insert(0, tweet);
+        self.sort_and_refresh()
+    }
+
+    /// Replaces one of the user's own tweets after it was edited, or removes it after
+    /// it was deleted.
+    pub fn replace_tweet(&mut self, hash: &str, tweet: Option<Tweet>) -> Task<Message> {
+        self.tweets.retain(|t| t.hash != hash);
+        match tweet {
+            Some(tweet) => self.insert_tweet(tweet),
+            None => self.sort_and_refresh(),
+        }
+    }
+
+    /// The number of other people's tweets replying to the tweet with `hash`, which
+    /// would lose their parent if it was edited or deleted.
+    fn replies_from_others(&self, hash: &str) -> usize {
+        self.tweets
+            .iter()
+            .filter(|t| t.reply_to.as_deref() == Some(hash))
+            .filter(|t| self.feed.own_feed.as_ref() != Some(&t.feed_id))
+            .count()
+    }
+
     fn sort_and_refresh(&mut self) -> Task<Message> {
         self.tweets.sort_by_key(|b| std::cmp::Reverse(b.timestamp));
-        let thread_tree = build_threads(&self.tweets);
+        self.thread_tree = build_threads(&self.tweets);
         self.feed
-            .reset(&thread_tree, &self.tweets)
+            .reset(&self.thread_tree, &self.tweets)
             .map(Message::Feed)
     }
 
@@ -239,95 +381,95 @@ impl TimelinePage {
         Task::none()
     }
 
-    fn post_composed_tweet(&mut self, config: &AppConfig) -> Task<Message> {
-        let composer_text = self.composer.text();
-
-        if let Some(tweet) = compose_twtxt_tweet(&composer_text, config, self.local_hash.clone()) {
-            self.tweets.insert(0, tweet);
-            self.composer = text_editor::Content::new();
-            self.sort_and_refresh()
-        } else {
-            Task::none()
-        }
-    }
-
     pub fn view(&self, theme: &Theme) -> Element<'_, Message> {
-        let compose_button = button(
-            text("Compose Twt")
+        let refresh_button = button(
+            text("Refresh")
                 .align_x(Alignment::Center)
                 .width(Length::Fill),
         )
-        .on_press(Message::ToggleComposer)
+        .on_press_maybe(if self.pending_downloads == 0 {
+            Some(Message::Refresh)
+        } else {
+            None
+        })
         .width(Length::Fill)
         .padding([8, 16])
         .style(toolbar_button_style);
 
-        let refresh_button = button("Refresh")
-            .on_press_maybe(if self.pending_downloads == 0 {
-                Some(Message::Refresh)
-            } else {
-                None
-            })
+        let archive_button = button("Archive")
+            .on_press(Message::ArchivePressed)
             .padding([8, 16])
             .style(toolbar_button_style);
 
-        let toolbar = row![compose_button, refresh_button].spacing(8);
+        let toolbar = row![refresh_button, archive_button].spacing(8);
 
         let feed = self.feed.view(theme, &self.tweets, true).map(Message::Feed);
 
-        let base = column![toolbar, feed]
-            .spacing(8)
-            .width(Length::Fill)
-            .height(Length::Fill);
-
-        let stack = Stack::new().push(base);
-
-        if self.show_composer {
-            let composer_sheet = container(
+        let delete_banner: Element<Message> = match &self.confirm_delete {
+            Some(hash) => container(
                 column![
-                    text_editor(&self.composer)
-                        .placeholder("What's on your mind?")
-                        .on_action(Message::ComposerEdit)
-                        .height(300)
-                        .padding(8)
-                        .style(toolbar_minput_style),
+                    text("Delete this twt from your feed? This can't be undone."),
+                    match self.replies_from_others(hash) {
+                        0 => space().into(),
+                        n => Element::from(
+                            text(orphan_warning_text(n))
+                                .color(theme.extended_palette().warning.base.color),
+                        ),
+                    },
+                    match &self.delete_error {
+                        Some(e) => Element::from(text(e).color(theme.palette().danger)),
+                        None => space().into(),
+                    },
                     row![
-                        button(text("Post").align_x(Alignment::Center).width(Length::Fill))
-                            .on_press(Message::PostPressed)
-                            .width(Length::Fill)
-                            .padding([8, 16])
-                            .style(toolbar_button_style),
                         button(
-                            text("Cancel")
-                                .align_x(Alignment::Center)
-                                .width(Length::Fill)
+                            text(if self.deleting {
+                                "Deleting..."
+                            } else {
+                                "Delete"
+                            })
+                            .align_x(Alignment::Center)
+                            .width(Length::Fill)
                         )
-                        .on_press(Message::CancelCompose)
+                        .on_press_maybe((!self.deleting).then_some(Message::ConfirmDelete))
                         .width(Length::Fill)
                         .padding([8, 16])
                         .style(toolbar_button_style),
+                        button(text("Keep").align_x(Alignment::Center).width(Length::Fill))
+                            .on_press(Message::CancelDelete)
+                            .width(Length::Fill)
+                            .padding([8, 16])
+                            .style(toolbar_button_style),
                     ]
-                    .spacing(8)
-                    .width(Length::Fill),
+                    .spacing(8),
                 ]
                 .spacing(8),
             )
+            .padding(8)
+            .into(),
+            None => space().into(),
+        };
+
+        let archive_banner: Element<Message> = match &self.archive_error {
+            Some(e) => container(
+                row![
+                    text(e).color(theme.palette().danger).width(Length::Fill),
+                    button("Dismiss")
+                        .on_press(Message::DismissArchiveError)
+                        .padding([8, 16])
+                        .style(toolbar_button_style),
+                ]
+                .spacing(8)
+                .align_y(Alignment::Center),
+            )
+            .padding(8)
+            .into(),
+            None => space().into(),
+        };
+
+        column![toolbar, archive_banner, delete_banner, feed]
+            .spacing(8)
             .width(Length::Fill)
-            .height(Length::Shrink);
-
-            stack
-                .push(
-                    column![
-                        space().height(40),
-                        composer_sheet,
-                        space().height(Length::Fill)
-                    ]
-                    .width(Length::Fill)
-                    .height(Length::Fill),
-                )
-                .into()
-        } else {
-            stack.into()
-        }
+            .height(Length::Fill)
+            .into()
     }
 }
@@ -1,24 +1,27 @@
 //! A page that renders a single twtxt feed and its metadata.
 
-use bytes::Bytes;
+use std::{collections::HashMap, sync::Arc};
+
 use iced::{
-    Alignment, Background, Border, Color, Element, Length, Task, Theme,
-    border::Radius,
+    Alignment, Color, Element, Length, Task, Theme,
     widget::{
-        button, column, container,
-        image::{self, Handle},
-        pick_list, rich_text, row,
-        row::Row,
-        space, span, text, text_input,
+        button, column, container, image, pick_list, rich_text, row, row::Row, space, span, text,
+        text_input,
     },
 };
 use tracing::{error, info};
 
 use crate::components::threaded_feed::LazyThreadedFeed;
+use crate::twtxt::feed_id::FeedId;
 use crate::twtxt::metadata::Metadata;
+use crate::twtxt::reply::{reply_prefix, subject_of, thread_members};
 use crate::twtxt::threading::build_threads;
 use crate::twtxt::{Tweet, TweetNode, download_and_parse_twtxt};
-use crate::utils::download::{ParsedCache, download_binary};
+use crate::utils::download::{ParsedCache, local_feed_path};
+use crate::utils::identicon::identicon;
+use crate::utils::media::{DecodedImage, MediaError, avatar_thumbnail};
+use crate::utils::privacy::MediaFilter;
+use crate::utils::resolve_feed_link;
 use crate::utils::styling::{
     sec_button_style, sec_pick_list_style, sec_pick_menu_style, secondary_text,
     toolbar_button_style, toolbar_sinput_style,
@@ -35,7 +38,7 @@ pub struct ViewPage {
     metadata: Option<Metadata>,
     pending_downloads: usize,
     feed: LazyThreadedFeed,
-    feed_hash: String,
+    feed_id: FeedId,
     selected_follow: Option<String>,
     info_expanded: bool,
     loading_archive: bool,
@@ -58,8 +61,8 @@ pub enum Message {
     /// An avatar image has finished downloading.
     AvatarLoaded {
         url: String,
-        result: Box<Result<Bytes, String>>,
-        hash: String,
+        result: Box<Result<DecodedImage, MediaError>>,
+        feed_id: FeedId,
     },
     /// An archived feed has finished loading.
     ArchiveLoaded {
@@ -71,6 +74,9 @@ pub enum Message {
     LinkClicked(String),
     /// Messages forwarded from the threaded feed component.
     Feed(threaded_feed::Message),
+    /// Start a reply in the thread with `subject`. Handled by the app, which owns the
+    /// composer.
+    Reply { subject: String, prefix: String },
     /// A feed was selected from the following dropdown.
     FollowSelected(String),
     /// The user pressed the "Expand" button.
@@ -90,7 +96,7 @@ impl ViewPage {
                 metadata: None,
                 pending_downloads: 0,
                 feed,
-                feed_hash: String::new(),
+                feed_id: FeedId::default(),
                 selected_follow: None,
                 info_expanded: true,
                 loading_archive: false,
@@ -99,7 +105,7 @@ impl ViewPage {
         )
     }
 
-    pub fn update(&mut self, message: Message) -> Task<Message> {
+    pub fn update(&mut self, message: Message, config: &AppConfig) -> Task<Message> {
         match message {
             Message::ComposerChanged(value) => {
                 self.composer = value;
@@ -110,8 +116,10 @@ impl ViewPage {
                 self.tweets.clear();
                 self.thread_tree.clear();
                 self.feed.avatars.clear();
+                self.feed.hidden_avatars.clear();
+                self.feed.media_filter = Arc::new(MediaFilter::new(config));
                 self.metadata = None;
-                self.feed_hash = String::new();
+                self.feed_id = FeedId::default();
                 self.pending_downloads = 1;
                 let reset_task = self.feed.reset(&[], &[]).map(Message::Feed);
 
@@ -141,24 +149,33 @@ impl ViewPage {
                 self.metadata = parsed.bundle.metadata.clone();
                 self.tweets = parsed.bundle.tweets;
                 self.tweets.sort_by_key(|b| std::cmp::Reverse(b.timestamp));
-                self.feed_hash = parsed.content_hash.clone();
-                let thread_tree = build_threads(&self.tweets);
+                self.feed_id = parsed.feed_id.clone();
+                self.thread_tree = build_threads(&self.tweets);
                 let feed_task = self
                     .feed
-                    .reset(&thread_tree, &self.tweets)
+                    .reset(&self.thread_tree, &self.tweets)
                     .map(Message::Feed);
 
                 let avatar_task = parsed
                     .bundle
                     .metadata
                     .and_then(|meta| meta.avatar)
+                    .filter(|avatar_url| {
+                        let allowed = self.feed.media_filter.allows(&parsed.feed_id, avatar_url);
+                        if !allowed {
+                            self.feed
+                                .hidden_avatars
+                                .insert(parsed.feed_id.clone(), avatar_url.clone());
+                        }
+                        allowed
+                    })
                     .map(|avatar_url| {
                         self.pending_downloads += 1;
-                        Task::perform(download_binary(avatar_url), move |res| {
+                        Task::perform(avatar_thumbnail(avatar_url), move |res| {
                             Message::AvatarLoaded {
                                 url: url.clone(),
                                 result: Box::new(res),
-                                hash: parsed.content_hash.clone(),
+                                feed_id: parsed.feed_id.clone(),
                             }
                         })
                     })
@@ -167,13 +184,19 @@ impl ViewPage {
                 Task::batch([feed_task, avatar_task])
             }
 
-            Message::AvatarLoaded { url, result, hash } => {
-                if let Ok(bytes) = *result {
-                    info!("View: avatar successfully loaded for {}", url);
-                    let handle = Handle::from_bytes(bytes);
-                    self.feed.avatars.insert(hash, handle.clone());
-                } else if let Err(e) = *result {
-                    error!("View: error loading avatar for {}: {}", url, e);
+            Message::AvatarLoaded {
+                url,
+                result,
+                feed_id,
+            } => {
+                match *result {
+                    Ok(image) => {
+                        info!("View: avatar successfully loaded for {}", url);
+                        self.feed.avatars.insert(feed_id, image.handle);
+                    }
+                    Err(e) => {
+                        error!("View: error loading avatar for {}: {}", url, e);
+                    }
                 }
 
                 self.pending_downloads -= 1;
@@ -187,20 +210,13 @@ impl ViewPage {
                     self.loading_archive = true;
                     self.pending_downloads += 1;
 
-                    let url = if let Ok(base) = reqwest::Url::parse(&self.composer) {
-                        match base.join(&prev.url) {
-                            Ok(joined) => joined.to_string(),
-                            Err(_) => prev.url.clone(),
-                        }
-                    } else {
-                        prev.url.clone()
-                    };
+                    let url = resolve_feed_link(&self.composer, &prev.url);
 
                     return Task::perform(
                         download_and_parse_twtxt(
                             "archive".into(),
                             url,
-                            Some(self.composer.clone()),
+                            Some(self.hash_url()),
                             false,
                         ),
                         |result| Message::ArchiveLoaded {
@@ -216,15 +232,11 @@ impl ViewPage {
                 self.pending_downloads -= 1;
                 self.loading_archive = false;
 
-                let Ok(mut parsed) = *result else {
+                let Ok(parsed) = *result else {
                     error!("View: failed to load archive feed");
                     return Task::none();
                 };
 
-                for tweet in &mut parsed.bundle.tweets {
-                    tweet.feed_hash = self.feed_hash.clone();
-                }
-
                 self.tweets.extend(parsed.bundle.tweets);
                 self.tweets.sort_by_key(|b| std::cmp::Reverse(b.timestamp));
                 self.thread_tree = build_threads(&self.tweets);
@@ -238,20 +250,13 @@ impl ViewPage {
                     self.loading_archive = true;
                     self.pending_downloads += 1;
 
-                    let url = if let Ok(base) = reqwest::Url::parse(&self.composer) {
-                        match base.join(&prev.url) {
-                            Ok(joined) => joined.to_string(),
-                            Err(_) => prev.url.clone(),
-                        }
-                    } else {
-                        prev.url.clone()
-                    };
+                    let url = resolve_feed_link(&self.composer, &prev.url);
 
                     Task::perform(
                         download_and_parse_twtxt(
                             "archive".into(),
                             url,
-                            Some(self.composer.clone()),
+                            Some(self.hash_url()),
                             false,
                         ),
                         |result| Message::ArchiveLoaded {
@@ -281,8 +286,32 @@ impl ViewPage {
                 Task::done(Message::RedirectToPage(info))
             }
 
+            Message::Feed(threaded_feed::Message::ReplyClicked(index)) => {
+                let Some(tweet) = self.tweets.get(index) else {
+                    return Task::none();
+                };
+
+                let nicks: HashMap<FeedId, String> = self
+                    .metadata
+                    .as_ref()
+                    .and_then(|m| m.nick.clone())
+                    .map(|nick| (self.feed_id.clone(), nick))
+                    .into_iter()
+                    .collect();
+                let own_feed = config.metadata.urls.first().map(|u| FeedId::from_url(u));
+
+                let thread = thread_members(&self.tweets, &self.thread_tree, index);
+                let prefix = reply_prefix(&self.tweets, index, &thread, own_feed.as_ref(), &nicks);
+                Task::done(Message::Reply {
+                    subject: subject_of(tweet).to_string(),
+                    prefix,
+                })
+            }
+
             Message::Feed(msg) => self.feed.update(msg, &self.tweets).map(Message::Feed),
 
+            Message::Reply { .. } => Task::none(),
+
             Message::RedirectToPage(info) => Task::done(Message::RedirectToPage(info)),
 
             Message::FollowSelected(url) => {
@@ -351,64 +380,24 @@ impl ViewPage {
             .map(|m| m.links.clone())
             .unwrap_or_default();
 
-        let avatar_expanded: Element<_> =
-            if let Some(handle) = self.feed.avatars.get(&self.feed_hash) {
-                image::Image::new(handle.clone())
-                    .width(Length::Fixed(56.0))
-                    .height(Length::Fixed(56.0))
-                    .border_radius(28)
-                    .into()
-            } else {
-                container(text("?").size(28))
-                    .width(Length::Fixed(56.0))
-                    .height(Length::Fixed(56.0))
-                    .center_x(Length::Fixed(56.0))
-                    .center_y(Length::Fixed(56.0))
-                    .style(|theme: &Theme| {
-                        let ext = theme.extended_palette();
-                        container::Style {
-                            background: Some(Background::Color(ext.background.strong.color)),
-                            border: Border {
-                                radius: Radius::from(28.0),
-                                ..Default::default()
-                            },
-                            ..Default::default()
-                        }
-                    })
-                    .into()
-            };
-
-        let avatar_collapsed: Element<_> =
-            if let Some(handle) = self.feed.avatars.get(&self.feed_hash) {
-                image::Image::new(handle)
-                    .width(Length::Fixed(32.0))
-                    .height(Length::Fixed(32.0))
-                    .border_radius(16)
-                    .into()
-            } else {
-                container(text("?").size(16))
-                    .width(Length::Fixed(32.0))
-                    .height(Length::Fixed(32.0))
-                    .center_x(Length::Fixed(32.0))
-                    .center_y(Length::Fixed(32.0))
-                    .style(|theme: &Theme| {
-                        let ext = theme.extended_palette();
-                        container::Style {
-                            background: Some(Background::Color(ext.background.strong.color)),
-                            border: Border {
-                                radius: Radius::from(16.0),
-                                ..Default::default()
-                            },
-                            ..Default::default()
-                        }
-                    })
-                    .into()
-            };
-
-        let timeline = self
+        let avatar = self
             .feed
-            .view(theme, &self.tweets, false)
-            .map(Message::Feed);
+            .avatars
+            .get(&self.feed_id)
+            .cloned()
+            .unwrap_or_else(|| identicon(self.feed_id.as_str(), theme));
+
+        let avatar_expanded = image::Image::new(avatar.clone())
+            .width(Length::Fixed(56.0))
+            .height(Length::Fixed(56.0))
+            .border_radius(28);
+
+        let avatar_collapsed = image::Image::new(avatar)
+            .width(Length::Fixed(32.0))
+            .height(Length::Fixed(32.0))
+            .border_radius(16);
+
+        let timeline = self.feed.view(theme, &self.tweets, true).map(Message::Feed);
 
         // Links row
         let mut links_row: Row<Message> = row!().spacing(4);
@@ -543,6 +532,43 @@ impl ViewPage {
             .into()
     }
 
+    /// The tweets of the feed being viewed.
+    pub fn tweets(&self) -> &[Tweet] {
+        &self.tweets
+    }
+
+    /// Shows a reply the user just posted in the thread it belongs to, if that thread
+    /// is in the feed being viewed.
+    pub fn insert_reply(&mut self, tweet: Tweet) -> Task<Message> {
+        let Some(subject) = tweet.reply_to.as_deref() else {
+            return Task::none();
+        };
+        if !self.tweets.iter().any(|t| t.hash == subject)
+            || self.tweets.iter().any(|t| t.hash == tweet.hash)
+        {
+            return Task::none();
+        }
+
+        // Added at the end, so the indices the feed was built with stay valid
+        self.tweets.push(tweet);
+        self.thread_tree = build_threads(&self.tweets);
+        self.feed
+            .insert_reply(self.tweets.len() - 1, &self.tweets)
+            .map(Message::Feed)
+    }
+
+    /// The URL that twts of this feed and its archives are hashed against.
+    ///
+    /// This is the feed URL itself, unless it's a local feed that declares its public URL.
+    fn hash_url(&self) -> String {
+        local_feed_path(&self.composer)
+            .ok()
+            .flatten()
+            .and(self.metadata.as_ref())
+            .and_then(|m| m.urls.first().cloned())
+            .unwrap_or_else(|| self.composer.clone())
+    }
+
     pub fn process_redirect_info(&mut self, info: crate::app::RedirectInfo) -> Task<Message> {
         match info.page {
             crate::app::Page::View => {
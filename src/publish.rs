@@ -0,0 +1,352 @@
+//! Publishing the user's `twtxt.txt` to the servers configured in `config.toml`.
+//!
+//! Every backend takes plain URLs, hosts and ports, so they can be pointed at a local
+//! stand-in server (any WebDAV server, an HTTP server accepting uploads, an `sshd` on
+//! another port, or a bare git repository) to try a configuration out before using it
+//! for real.
+
+pub mod git;
+pub mod http;
+pub mod media;
+pub mod sftp;
+
+use std::{path::Path, time::Duration};
+
+use reqwest::Url;
+use tracing::{info, warn};
+
+use crate::config::{PublishBackend, PublishTarget, secrets::publish_credential};
+
+/// How long to wait before each retry of a failed upload.
+#[cfg(not(test))]
+const RETRY_DELAYS: &[Duration] = &[Duration::from_secs(5), Duration::from_secs(30)];
+#[cfg(test)]
+const RETRY_DELAYS: &[Duration] = &[Duration::from_millis(10), Duration::from_millis(10)];
+
+/// Uploads the feed at `twtxt_path` to `target`, retrying a few times on failure.
+///
+/// `archive` is the path of an archive feed that was split off since the last upload.
+/// It's uploaded next to the feed first, so the feed's `# prev` never points at an
+/// archive that isn't there yet.
+pub async fn publish(
+    target: PublishTarget,
+    twtxt_path: String,
+    archive: Option<String>,
+) -> Result<(), String> {
+    if let Some(archive_path) = archive {
+        let file_name = Path::new(&archive_path)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .ok_or_else(|| format!("Invalid archive path {}", archive_path))?;
+        let archive_target = PublishTarget {
+            name: target.name.clone(),
+            backend: sibling(&target.backend, &file_name)?,
+        };
+        publish_file(&archive_target, &archive_path).await?;
+    }
+
+    publish_file(&target, &twtxt_path).await
+}
+
+/// Uploads a single file, retrying a few times on failure.
+///
+/// The git backend isn't retried here, since it already retries rejected pushes
+/// itself and other git failures rarely go away on their own.
+async fn publish_file(target: &PublishTarget, path: &str) -> Result<(), String> {
+    let content = std::fs::read(path).map_err(|e| e.to_string())?;
+
+    let delays = match target.backend {
+        PublishBackend::Git { .. } => &[],
+        _ => RETRY_DELAYS,
+    };
+    let mut retries = delays.iter();
+    loop {
+        match upload(target, path, content.clone()).await {
+            Ok(()) => {
+                info!(
+                    "Published {} bytes of {} to {}",
+                    content.len(),
+                    path,
+                    target.name
+                );
+                return Ok(());
+            }
+            Err(e) => match retries.next() {
+                Some(delay) => {
+                    warn!(
+                        "Publishing to {} failed, retrying in {}s: {}",
+                        target.name,
+                        delay.as_secs(),
+                        e
+                    );
+                    tokio::time::sleep(*delay).await;
+                }
+                None => return Err(e),
+            },
+        }
+    }
+}
+
+/// Points a backend at a file in the same directory as the feed.
+fn sibling(backend: &PublishBackend, file_name: &str) -> Result<PublishBackend, String> {
+    let join_url = |url: &str| {
+        Url::parse(url)
+            .and_then(|url| url.join(file_name))
+            .map(|url| url.to_string())
+            .map_err(|e| format!("Invalid URL {}: {}", url, e))
+    };
+    let join_path = |path: &str| match path.rsplit_once('/') {
+        Some((dir, _)) => format!("{}/{}", dir, file_name),
+        None => file_name.to_string(),
+    };
+
+    Ok(match backend.clone() {
+        PublishBackend::WebDav { url } => PublishBackend::WebDav {
+            url: join_url(&url)?,
+        },
+        PublishBackend::Http { url, method } => PublishBackend::Http {
+            url: join_url(&url)?,
+            method,
+        },
+        PublishBackend::Sftp {
+            host,
+            port,
+            user,
+            path,
+            identity_file,
+        } => PublishBackend::Sftp {
+            host,
+            port,
+            user,
+            path: join_path(&path),
+            identity_file,
+        },
+        PublishBackend::Git {
+            repository,
+            path,
+            remote,
+            branch,
+        } => PublishBackend::Git {
+            repository,
+            path: join_path(&path),
+            remote,
+            branch,
+        },
+    })
+}
+
+/// Makes a single upload attempt.
+async fn upload(target: &PublishTarget, local_path: &str, content: Vec<u8>) -> Result<(), String> {
+    match &target.backend {
+        PublishBackend::WebDav { url } => {
+            http::webdav_put(
+                url,
+                publish_credential(&target.name),
+                content,
+                http::FEED_CONTENT_TYPE,
+            )
+            .await
+        }
+        PublishBackend::Http { url, method } => {
+            http::upload(
+                url,
+                *method,
+                publish_credential(&target.name),
+                content,
+                http::FEED_CONTENT_TYPE,
+            )
+            .await
+        }
+        PublishBackend::Sftp {
+            host,
+            port,
+            user,
+            path,
+            identity_file,
+        } => {
+            sftp::put(
+                host,
+                *port,
+                user.as_deref(),
+                identity_file.as_deref(),
+                local_path,
+                path,
+            )
+            .await
+        }
+        PublishBackend::Git {
+            repository,
+            path,
+            remote,
+            branch,
+        } => git::commit_and_push(repository, path, remote, branch.as_deref(), &content).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::{
+        config::HttpMethod,
+        utils::test_server::{Reply, TestServer, block_on, temp_dir},
+    };
+
+    fn http_target(url: String) -> PublishTarget {
+        PublishTarget {
+            name: "test-http".to_string(),
+            backend: PublishBackend::Http {
+                url,
+                method: HttpMethod::Put,
+            },
+        }
+    }
+
+    #[test]
+    fn uploads_archive_before_feed() {
+        let dir = temp_dir("publish-archive");
+        let feed = dir.join("twtxt.txt");
+        let archive = dir.join("twtxt-1.txt");
+        std::fs::write(&feed, "feed\n").unwrap();
+        std::fs::write(&archive, "archive\n").unwrap();
+
+        block_on(async {
+            let server = TestServer::start(|_| Reply::status(201)).await;
+
+            publish(
+                http_target(server.url("/feeds/twtxt.txt")),
+                feed.to_string_lossy().into_owned(),
+                Some(archive.to_string_lossy().into_owned()),
+            )
+            .await
+            .unwrap();
+
+            let requests = server.requests();
+            assert_eq!(requests.len(), 2);
+            assert_eq!(requests[0].path, "/feeds/twtxt-1.txt");
+            assert_eq!(requests[0].body, b"archive\n");
+            assert_eq!(requests[1].path, "/feeds/twtxt.txt");
+            assert_eq!(requests[1].body, b"feed\n");
+        });
+    }
+
+    #[test]
+    fn feed_isnt_uploaded_when_archive_fails() {
+        let dir = temp_dir("publish-archive-fails");
+        let feed = dir.join("twtxt.txt");
+        let archive = dir.join("twtxt-1.txt");
+        std::fs::write(&feed, "feed\n").unwrap();
+        std::fs::write(&archive, "archive\n").unwrap();
+
+        block_on(async {
+            let server = TestServer::start(|request| {
+                if request.path.ends_with("twtxt-1.txt") {
+                    Reply::status(500)
+                } else {
+                    Reply::status(201)
+                }
+            })
+            .await;
+
+            let result = publish(
+                http_target(server.url("/twtxt.txt")),
+                feed.to_string_lossy().into_owned(),
+                Some(archive.to_string_lossy().into_owned()),
+            )
+            .await;
+
+            assert!(result.is_err());
+            assert!(
+                server
+                    .requests()
+                    .iter()
+                    .all(|r| r.path.ends_with("twtxt-1.txt"))
+            );
+        });
+    }
+
+    #[test]
+    fn retries_failed_uploads() {
+        let dir = temp_dir("publish-retry");
+        let feed = dir.join("twtxt.txt");
+        std::fs::write(&feed, "feed\n").unwrap();
+
+        block_on(async {
+            let attempts = AtomicUsize::new(0);
+            let server = TestServer::start(move |_| {
+                if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                    Reply::status(503)
+                } else {
+                    Reply::status(204)
+                }
+            })
+            .await;
+
+            publish(
+                http_target(server.url("/twtxt.txt")),
+                feed.to_string_lossy().into_owned(),
+                None,
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(server.requests().len(), 2);
+        });
+    }
+
+    #[test]
+    fn gives_up_after_retries() {
+        let dir = temp_dir("publish-give-up");
+        let feed = dir.join("twtxt.txt");
+        std::fs::write(&feed, "feed\n").unwrap();
+
+        block_on(async {
+            let server = TestServer::start(|_| Reply::status(500)).await;
+
+            let result = publish(
+                http_target(server.url("/twtxt.txt")),
+                feed.to_string_lossy().into_owned(),
+                None,
+            )
+            .await;
+
+            assert!(result.unwrap_err().contains("500"));
+            assert_eq!(server.requests().len(), RETRY_DELAYS.len() + 1);
+        });
+    }
+
+    #[test]
+    fn sibling_replaces_file_name() {
+        let webdav = PublishBackend::WebDav {
+            url: "https://dav.example.com/www/twtxt.txt".to_string(),
+        };
+        let PublishBackend::WebDav { url } = sibling(&webdav, "twtxt-1.txt").unwrap() else {
+            panic!("backend changed kind");
+        };
+        assert_eq!(url, "https://dav.example.com/www/twtxt-1.txt");
+
+        let sftp = PublishBackend::Sftp {
+            host: "example.com".to_string(),
+            port: None,
+            user: None,
+            path: "www/twtxt.txt".to_string(),
+            identity_file: None,
+        };
+        let PublishBackend::Sftp { path, .. } = sibling(&sftp, "twtxt-1.txt").unwrap() else {
+            panic!("backend changed kind");
+        };
+        assert_eq!(path, "www/twtxt-1.txt");
+
+        let git = PublishBackend::Git {
+            repository: "/srv/site".to_string(),
+            path: "twtxt.txt".to_string(),
+            remote: "origin".to_string(),
+            branch: None,
+        };
+        let PublishBackend::Git { path, .. } = sibling(&git, "twtxt-1.txt").unwrap() else {
+            panic!("backend changed kind");
+        };
+        assert_eq!(path, "twtxt-1.txt");
+    }
+}
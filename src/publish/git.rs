@@ -0,0 +1,277 @@
+//! Git publishing backend, for feeds hosted on a static site built from a repository.
+//!
+//! The feed is copied into a local clone, committed and pushed with the system's `git`,
+//! so the user's usual git configuration and credentials are used.
+
+use std::path::Path;
+
+use chrono::Utc;
+use tokio::process::Command;
+use tracing::{debug, info, warn};
+
+/// How many times a push rejected because the remote moved is retried after a rebase.
+///
+/// Other failures, such as authentication or network errors, aren't retried here.
+const MAX_PUSH_ATTEMPTS: usize = 3;
+
+/// Commits `content` as `path` in the repository at `repository` and pushes it.
+///
+/// `branch` defaults to the branch currently checked out. If the remote has commits we
+/// don't, ours are rebased on top of them before pushing again.
+pub async fn commit_and_push(
+    repository: &str,
+    path: &str,
+    remote: &str,
+    branch: Option<&str>,
+    content: &[u8],
+) -> Result<(), String> {
+    let repo = Path::new(repository);
+    tokio::fs::write(repo.join(path), content)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // Unlike `rev-parse`, this also works before the first commit
+    let branch = match branch {
+        Some(branch) => branch.to_string(),
+        None => git(repo, &["symbolic-ref", "--short", "HEAD"]).await?,
+    };
+
+    git(repo, &["add", "--", path]).await?;
+
+    // `diff --quiet` exits with 1 when there are staged changes
+    if git(repo, &["diff", "--cached", "--quiet", "--", path])
+        .await
+        .is_err()
+    {
+        let message = format!(
+            "Update {} ({})",
+            path,
+            Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+        );
+        git(repo, &["commit", "-m", &message, "--", path]).await?;
+        info!("Git: committed {} in {}", path, repository);
+    } else {
+        // A previous push may have failed, so push anyway
+        debug!("Git: {} is unchanged in {}", path, repository);
+    }
+
+    let refspec = format!("HEAD:{}", branch);
+    let mut attempt = 1;
+    loop {
+        match git(repo, &["push", remote, &refspec]).await {
+            Ok(_) => {
+                info!("Git: pushed {} to {}/{}", repository, remote, branch);
+                return Ok(());
+            }
+            Err(e) if attempt < MAX_PUSH_ATTEMPTS && is_rejected(&e) => {
+                warn!(
+                    "Git: push rejected, rebasing onto {}/{}: {}",
+                    remote, branch, e
+                );
+                rebase_onto_remote(repo, remote, &branch).await?;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Returns whether a push failed because the remote has commits we don't.
+fn is_rejected(error: &str) -> bool {
+    error.contains("[rejected]")
+        && (error.contains("non-fast-forward") || error.contains("fetch first"))
+}
+
+/// Rebases local commits onto the remote branch, aborting if that causes conflicts.
+async fn rebase_onto_remote(repo: &Path, remote: &str, branch: &str) -> Result<(), String> {
+    if let Err(e) = git(repo, &["pull", "--rebase", "--autostash", remote, branch]).await {
+        // Leave the repository as it was rather than stuck mid-rebase
+        let _ = git(repo, &["rebase", "--abort"]).await;
+        return Err(format!(
+            "Could not rebase onto {}/{}, resolve it manually: {}",
+            remote, branch, e
+        ));
+    }
+    Ok(())
+}
+
+/// Runs a git command in `repo` and returns its trimmed output.
+async fn git(repo: &Path, args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        // Fail instead of waiting for a password prompt nobody can see
+        .env("GIT_TERMINAL_PROMPT", "0")
+        // Untranslated, so rejected pushes can be recognized
+        .env("LC_ALL", "C")
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        Err(format!(
+            "git {} failed ({}): {}",
+            args.first().unwrap_or(&""),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_server::{block_on, temp_dir};
+    use std::path::PathBuf;
+
+    /// Runs git synchronously to set up and inspect the test repositories.
+    fn run(dir: &Path, args: &[&str]) -> String {
+        let output = std::process::Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .output()
+            .expect("Failed to run git");
+        assert!(
+            output.status.success(),
+            "git {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    /// Makes a clone of `remote` to commit to.
+    fn clone(remote: &Path, dir: &Path) -> PathBuf {
+        run(
+            dir.parent().unwrap(),
+            &[
+                "clone",
+                "-q",
+                &remote.to_string_lossy(),
+                &dir.to_string_lossy(),
+            ],
+        );
+        run(dir, &["config", "user.name", "Test"]);
+        run(dir, &["config", "user.email", "test@example.com"]);
+        run(dir, &["config", "commit.gpgsign", "false"]);
+        dir.to_path_buf()
+    }
+
+    /// An empty bare repository on `main`, with a clone of it to publish from.
+    fn repos(name: &str) -> (PathBuf, PathBuf) {
+        let dir = temp_dir(name);
+        let remote = dir.join("remote.git");
+        run(&dir, &["init", "-q", "--bare", "-b", "main", "remote.git"]);
+        let local = clone(&remote, &dir.join("local"));
+        (remote, local)
+    }
+
+    fn publish(local: &Path, content: &str) -> Result<(), String> {
+        block_on(commit_and_push(
+            &local.to_string_lossy(),
+            "twtxt.txt",
+            "origin",
+            None,
+            content.as_bytes(),
+        ))
+    }
+
+    fn commit_count(remote: &Path) -> String {
+        run(remote, &["rev-list", "--count", "main"])
+    }
+
+    #[test]
+    fn first_commit_is_pushed() {
+        let (remote, local) = repos("git-first");
+        publish(&local, "hello\n").unwrap();
+
+        assert_eq!(run(&remote, &["show", "main:twtxt.txt"]), "hello");
+        assert_eq!(commit_count(&remote), "1");
+    }
+
+    #[test]
+    fn unchanged_file_makes_no_commit() {
+        let (remote, local) = repos("git-unchanged");
+        publish(&local, "hello\n").unwrap();
+        publish(&local, "hello\n").unwrap();
+
+        assert_eq!(commit_count(&remote), "1");
+    }
+
+    #[test]
+    fn rebases_when_the_remote_moved() {
+        let (remote, local) = repos("git-moved");
+        publish(&local, "hello\n").unwrap();
+
+        let other = clone(&remote, &local.with_file_name("other"));
+        std::fs::write(other.join("index.html"), "<h1>Hi</h1>\n").unwrap();
+        run(&other, &["add", "index.html"]);
+        run(&other, &["commit", "-q", "-m", "Add index"]);
+        run(&other, &["push", "-q", "origin", "main"]);
+
+        publish(&local, "hello\nagain\n").unwrap();
+
+        assert_eq!(run(&remote, &["show", "main:twtxt.txt"]), "hello\nagain");
+        assert_eq!(run(&remote, &["show", "main:index.html"]), "<h1>Hi</h1>");
+        assert_eq!(commit_count(&remote), "3");
+    }
+
+    #[test]
+    fn conflicts_abort_the_rebase() {
+        let (remote, local) = repos("git-conflict");
+        publish(&local, "hello\n").unwrap();
+
+        let other = clone(&remote, &local.with_file_name("other"));
+        std::fs::write(other.join("twtxt.txt"), "hello\nfrom elsewhere\n").unwrap();
+        run(&other, &["commit", "-q", "-am", "Edit elsewhere"]);
+        run(&other, &["push", "-q", "origin", "main"]);
+
+        let err = publish(&local, "hello\nfrom here\n").unwrap_err();
+        assert!(
+            err.starts_with("Could not rebase onto origin/main"),
+            "{}",
+            err
+        );
+
+        // The clone is left as it was, not stuck mid-rebase
+        assert!(!local.join(".git/rebase-merge").exists());
+        assert!(!local.join(".git/rebase-apply").exists());
+        assert_eq!(
+            run(&remote, &["show", "main:twtxt.txt"]),
+            "hello\nfrom elsewhere"
+        );
+    }
+
+    #[test]
+    fn other_push_failures_are_not_retried() {
+        let (_, local) = repos("git-unreachable");
+        run(
+            &local,
+            &["remote", "set-url", "origin", "/nonexistent/remote.git"],
+        );
+
+        let err = publish(&local, "hello\n").unwrap_err();
+        assert!(err.starts_with("git push failed"), "{}", err);
+    }
+
+    #[test]
+    fn recognizes_rejected_pushes() {
+        assert!(is_rejected(
+            " ! [rejected]        HEAD -> main (fetch first)\nerror: failed to push some refs"
+        ));
+        assert!(is_rejected(
+            " ! [rejected]        HEAD -> main (non-fast-forward)"
+        ));
+        assert!(!is_rejected(
+            "fatal: Authentication failed for 'https://example.com/repo.git/'"
+        ));
+        assert!(!is_rejected(
+            " ! [remote rejected] HEAD -> main (pre-receive hook declined)"
+        ));
+    }
+}
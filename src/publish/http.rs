@@ -0,0 +1,319 @@
+//! HTTP based publishing backends: WebDAV and a generic `PUT`/`POST`.
+//!
+//! These upload the feed as well as images attached in the composer, so the content
+//! type of the upload is passed in.
+
+use reqwest::{Method, Response, StatusCode, Url, header::CONTENT_TYPE};
+use tracing::{debug, info};
+
+use crate::config::{HttpMethod, secrets::Credential};
+use crate::utils::download::get_client;
+
+pub const FEED_CONTENT_TYPE: &str = "text/plain; charset=utf-8";
+
+/// Uploads a file with a plain `PUT` or `POST` request.
+pub async fn upload(
+    url: &str,
+    method: HttpMethod,
+    credential: Option<Credential>,
+    content: Vec<u8>,
+    content_type: &str,
+) -> Result<(), String> {
+    upload_with_response(url, method, credential, content, content_type)
+        .await
+        .map(|_| ())
+}
+
+/// Uploads a file with a plain `PUT` or `POST` request, returning the body of the
+/// response.
+pub async fn upload_with_response(
+    url: &str,
+    method: HttpMethod,
+    credential: Option<Credential>,
+    content: Vec<u8>,
+    content_type: &str,
+) -> Result<String, String> {
+    let method = match method {
+        HttpMethod::Put => Method::PUT,
+        HttpMethod::Post => Method::POST,
+    };
+
+    let response = send_request(
+        method.clone(),
+        url,
+        credential.as_ref(),
+        Some((content, content_type)),
+    )
+    .await?;
+    check_status(&method, url, response.status())?;
+    response.text().await.map_err(|e| e.to_string())
+}
+
+/// Uploads a file to a WebDAV server.
+///
+/// If the server responds with `409 Conflict` because a parent collection is missing,
+/// the missing collections are created and the upload is tried again.
+pub async fn webdav_put(
+    url: &str,
+    credential: Option<Credential>,
+    content: Vec<u8>,
+    content_type: &str,
+) -> Result<(), String> {
+    let status = send(
+        Method::PUT,
+        url,
+        credential.as_ref(),
+        Some((content.clone(), content_type)),
+    )
+    .await?;
+    if status != StatusCode::CONFLICT {
+        return check_status(&Method::PUT, url, status);
+    }
+
+    debug!(
+        "WebDAV: parent collection of {} is missing, creating it",
+        url
+    );
+    create_parent_collections(url, credential.as_ref()).await?;
+
+    let status = send(
+        Method::PUT,
+        url,
+        credential.as_ref(),
+        Some((content, content_type)),
+    )
+    .await?;
+    check_status(&Method::PUT, url, status)
+}
+
+/// Creates every collection between the server root and `url`, ignoring those that
+/// already exist.
+async fn create_parent_collections(
+    url: &str,
+    credential: Option<&Credential>,
+) -> Result<(), String> {
+    let mkcol = Method::from_bytes(b"MKCOL").map_err(|e| e.to_string())?;
+    let mut collection = Url::parse(url).map_err(|e| e.to_string())?;
+    let segments: Vec<String> = collection
+        .path_segments()
+        .map(|s| s.map(str::to_string).collect())
+        .unwrap_or_default();
+
+    let Some((_, parents)) = segments.split_last() else {
+        return Ok(());
+    };
+
+    let mut path = String::from("/");
+    for segment in parents.iter().filter(|s| !s.is_empty()) {
+        path.push_str(segment);
+        path.push('/');
+        collection.set_path(&path);
+
+        let status = send(mkcol.clone(), collection.as_str(), credential, None).await?;
+        // 405 Method Not Allowed means the collection already exists
+        if !status.is_success() && status != StatusCode::METHOD_NOT_ALLOWED {
+            return Err(format!("MKCOL {} returned {}", collection, status));
+        }
+    }
+
+    Ok(())
+}
+
+async fn send(
+    method: Method,
+    url: &str,
+    credential: Option<&Credential>,
+    body: Option<(Vec<u8>, &str)>,
+) -> Result<StatusCode, String> {
+    send_request(method, url, credential, body)
+        .await
+        .map(|response| response.status())
+}
+
+async fn send_request(
+    method: Method,
+    url: &str,
+    credential: Option<&Credential>,
+    body: Option<(Vec<u8>, &str)>,
+) -> Result<Response, String> {
+    let mut request = get_client().request(method.clone(), url);
+
+    request = match credential {
+        Some(Credential::Basic { username, password }) => {
+            request.basic_auth(username, Some(password))
+        }
+        Some(Credential::Bearer { token }) => request.bearer_auth(token),
+        None => request,
+    };
+
+    if let Some((body, content_type)) = body {
+        request = request.header(CONTENT_TYPE, content_type).body(body);
+    }
+
+    let response = request.send().await.map_err(|e| e.to_string())?;
+    info!("{} {}: {}", method, url, response.status());
+    Ok(response)
+}
+
+fn check_status(method: &Method, url: &str, status: StatusCode) -> Result<(), String> {
+    if status.is_success() {
+        Ok(())
+    } else {
+        Err(format!("{} {} returned {}", method, url, status))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+    use crate::utils::test_server::{Reply, TestServer, block_on};
+
+    #[test]
+    fn put_sends_content_and_basic_auth() {
+        block_on(async {
+            let server = TestServer::start(|_| Reply::status(201)).await;
+            let credential = Credential::Basic {
+                username: "alice".to_string(),
+                password: "secret".to_string(),
+            };
+
+            upload(
+                &server.url("/twtxt.txt"),
+                HttpMethod::Put,
+                Some(credential),
+                b"hello\n".to_vec(),
+                FEED_CONTENT_TYPE,
+            )
+            .await
+            .unwrap();
+
+            let requests = server.requests();
+            assert_eq!(requests.len(), 1);
+            assert_eq!(requests[0].method, "PUT");
+            assert_eq!(requests[0].path, "/twtxt.txt");
+            assert_eq!(requests[0].body, b"hello\n");
+            assert_eq!(requests[0].header("content-type"), Some(FEED_CONTENT_TYPE));
+            // alice:secret
+            assert_eq!(
+                requests[0].header("authorization"),
+                Some("Basic YWxpY2U6c2VjcmV0")
+            );
+        });
+    }
+
+    #[test]
+    fn post_sends_bearer_token_and_returns_response() {
+        block_on(async {
+            let server =
+                TestServer::start(|_| Reply::status(200).body("https://example.com/a.png")).await;
+            let credential = Credential::Bearer {
+                token: "t0ken".to_string(),
+            };
+
+            let response = upload_with_response(
+                &server.url("/upload"),
+                HttpMethod::Post,
+                Some(credential),
+                vec![1, 2, 3],
+                "image/png",
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(response, "https://example.com/a.png");
+            let requests = server.requests();
+            assert_eq!(requests[0].method, "POST");
+            assert_eq!(requests[0].header("authorization"), Some("Bearer t0ken"));
+            assert_eq!(requests[0].header("content-type"), Some("image/png"));
+        });
+    }
+
+    #[test]
+    fn upload_fails_on_error_status() {
+        block_on(async {
+            let server = TestServer::start(|_| Reply::status(403)).await;
+
+            let result = upload(
+                &server.url("/twtxt.txt"),
+                HttpMethod::Put,
+                None,
+                Vec::new(),
+                FEED_CONTENT_TYPE,
+            )
+            .await;
+
+            let error = result.unwrap_err();
+            assert!(error.contains("403"), "{}", error);
+            assert!(server.requests()[0].header("authorization").is_none());
+        });
+    }
+
+    #[test]
+    fn webdav_creates_missing_collections_and_retries() {
+        block_on(async {
+            // The first PUT fails because /a/b/ doesn't exist yet, /a/ already does
+            let created = AtomicBool::new(false);
+            let server = TestServer::start(move |request| match request.method.as_str() {
+                "PUT" if !created.load(Ordering::SeqCst) => Reply::status(409),
+                "PUT" => Reply::status(201),
+                "MKCOL" if request.path == "/a/" => Reply::status(405),
+                "MKCOL" => {
+                    created.store(true, Ordering::SeqCst);
+                    Reply::status(201)
+                }
+                _ => Reply::status(400),
+            })
+            .await;
+
+            webdav_put(
+                &server.url("/a/b/twtxt.txt"),
+                None,
+                b"hello\n".to_vec(),
+                FEED_CONTENT_TYPE,
+            )
+            .await
+            .unwrap();
+
+            let requests: Vec<(String, String)> = server
+                .requests()
+                .into_iter()
+                .map(|r| (r.method, r.path))
+                .collect();
+            assert_eq!(
+                requests,
+                [
+                    ("PUT".to_string(), "/a/b/twtxt.txt".to_string()),
+                    ("MKCOL".to_string(), "/a/".to_string()),
+                    ("MKCOL".to_string(), "/a/b/".to_string()),
+                    ("PUT".to_string(), "/a/b/twtxt.txt".to_string()),
+                ]
+            );
+            assert_eq!(server.requests()[3].body, b"hello\n");
+        });
+    }
+
+    #[test]
+    fn webdav_fails_when_collection_cant_be_created() {
+        block_on(async {
+            let server = TestServer::start(|request| match request.method.as_str() {
+                "PUT" => Reply::status(409),
+                _ => Reply::status(403),
+            })
+            .await;
+
+            let error = webdav_put(
+                &server.url("/a/twtxt.txt"),
+                None,
+                Vec::new(),
+                FEED_CONTENT_TYPE,
+            )
+            .await
+            .unwrap_err();
+
+            assert!(error.starts_with("MKCOL"), "{}", error);
+            assert_eq!(server.requests().len(), 2);
+        });
+    }
+}
@@ -0,0 +1,267 @@
+//! Uploading images attached in the composer to the configured media backend.
+//!
+//! Images are named after a hash of their contents, so uploading the same image twice
+//! doesn't leave two copies behind and the name of the file on the user's disk isn't
+//! published along with it.
+
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use image::{ImageFormat, codecs::jpeg::JpegEncoder, imageops::FilterType};
+use percent_encoding::percent_decode_str;
+use reqwest::Url;
+use tracing::info;
+
+use crate::{
+    config::{MediaBackend, MediaUploads, secrets::media_credential},
+    publish::http,
+    utils::{
+        hash::hash_sha256_bytes,
+        media::{SUPPORTED_FORMATS, decode},
+    },
+};
+
+/// The quality JPEGs are encoded at when they're re-encoded.
+const JPEG_QUALITY: u8 = 90;
+
+/// An image ready to be uploaded.
+struct PreparedImage {
+    file_name: String,
+    content_type: &'static str,
+    bytes: Vec<u8>,
+}
+
+/// Uploads the image at `path` to the configured media backend, returning the URL it
+/// can be linked with.
+pub async fn upload_image(path: PathBuf, settings: MediaUploads) -> Result<String, String> {
+    let backend = settings
+        .backend
+        .clone()
+        .ok_or("Set up a media backend under [media] in config.toml to attach images")?;
+
+    let image = tokio::task::spawn_blocking(move || prepare_image(&path, &settings))
+        .await
+        .map_err(|e| e.to_string())??;
+
+    let url = match backend {
+        MediaBackend::Http {
+            url,
+            method,
+            public_url,
+        } => {
+            let upload_url = if url.ends_with('/') {
+                join_url(&url, &image.file_name)?
+            } else {
+                url
+            };
+            let response = http::upload_with_response(
+                &upload_url,
+                method,
+                media_credential(),
+                image.bytes,
+                image.content_type,
+            )
+            .await?;
+
+            match public_url {
+                Some(public_url) => join_url(&public_url, &image.file_name)?,
+                None => url_from_response(&response)?,
+            }
+        }
+        MediaBackend::WebDav { url, public_url } => {
+            let upload_url = join_url(&url, &image.file_name)?;
+            http::webdav_put(
+                &upload_url,
+                media_credential(),
+                image.bytes,
+                image.content_type,
+            )
+            .await?;
+
+            match public_url {
+                Some(public_url) => join_url(&public_url, &image.file_name)?,
+                None => upload_url,
+            }
+        }
+        MediaBackend::Directory { path, public_url } => {
+            let dir = PathBuf::from(&path);
+            std::fs::create_dir_all(&dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+            let target = dir.join(&image.file_name);
+            std::fs::write(&target, &image.bytes)
+                .map_err(|e| format!("Failed to write {}: {}", target.display(), e))?;
+
+            join_url(&public_url, &image.file_name)?
+        }
+    };
+
+    info!("Uploaded image to {}", url);
+    Ok(url)
+}
+
+/// Reads an image and, depending on the settings, downscales it and re-encodes it
+/// without its metadata.
+fn prepare_image(path: &Path, settings: &MediaUploads) -> Result<PreparedImage, String> {
+    let bytes =
+        std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+    let format = image::guess_format(&bytes)
+        .ok()
+        .filter(|f| SUPPORTED_FORMATS.contains(f))
+        .ok_or_else(|| format!("{} isn't a PNG, JPEG or WebP image", path.display()))?;
+
+    let bytes = if settings.strip_metadata || settings.max_dimension.is_some() {
+        let mut image = decode(&bytes).map_err(|e| e.to_string())?;
+        if let Some(max) = settings.max_dimension
+            && (image.width() > max || image.height() > max)
+        {
+            image = image.resize(max, max, FilterType::Lanczos3);
+        }
+
+        // Encoders don't write any of the original metadata, so this strips it
+        let mut encoded = Cursor::new(Vec::new());
+        match format {
+            ImageFormat::Jpeg => image
+                .to_rgb8()
+                .write_with_encoder(JpegEncoder::new_with_quality(&mut encoded, JPEG_QUALITY)),
+            format => image.write_to(&mut encoded, format),
+        }
+        .map_err(|e| format!("Failed to encode the image: {}", e))?;
+        encoded.into_inner()
+    } else {
+        bytes
+    };
+
+    let (extension, content_type) = match format {
+        ImageFormat::Jpeg => ("jpg", "image/jpeg"),
+        ImageFormat::WebP => ("webp", "image/webp"),
+        _ => ("png", "image/png"),
+    };
+
+    Ok(PreparedImage {
+        file_name: format!("{}.{}", &hash_sha256_bytes(&bytes)[..16], extension),
+        content_type,
+        bytes,
+    })
+}
+
+/// Appends a file name to a directory URL, whether or not it ends with a `/`.
+fn join_url(base: &str, file_name: &str) -> Result<String, String> {
+    let base = if base.ends_with('/') {
+        base.to_string()
+    } else {
+        format!("{}/", base)
+    };
+
+    Url::parse(&base)
+        .and_then(|url| url.join(file_name))
+        .map(|url| url.to_string())
+        .map_err(|e| format!("Invalid URL {}: {}", base, e))
+}
+
+/// Reads the URL of an uploaded image from the response of an upload endpoint.
+fn url_from_response(response: &str) -> Result<String, String> {
+    Url::parse(response.trim())
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+        .map(|url| url.to_string())
+        .ok_or_else(|| {
+            "The upload endpoint didn't respond with the image's URL, set public_url for it"
+                .to_string()
+        })
+}
+
+/// Returns the image file named by text pasted into the composer, if it names one.
+///
+/// File managers put copied files on the clipboard as their paths or `file://` URIs.
+/// Copied image data, such as a screenshot, can't be pasted, since the clipboard is
+/// only read as text.
+pub fn pasted_image_path(text: &str) -> Option<PathBuf> {
+    let text = text.trim();
+    if text.is_empty() || text.contains('\n') {
+        return None;
+    }
+
+    let path = match text.strip_prefix("file://") {
+        Some(uri) => PathBuf::from(percent_decode_str(uri).decode_utf8().ok()?.as_ref()),
+        None => PathBuf::from(text),
+    };
+
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    (matches!(extension.as_str(), "png" | "jpg" | "jpeg" | "webp") && path.is_file())
+        .then_some(path)
+}
+
+/// Returns the markdown linking an uploaded image with its alt text.
+pub fn image_markdown(alt: &str, url: &str) -> String {
+    let alt = alt
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace('[', "\\[")
+        .replace(']', "\\]");
+    format!("![{}]({})", alt, url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_server::temp_dir;
+
+    #[test]
+    fn image_markdown_escapes_alt_text() {
+        assert_eq!(
+            image_markdown("a  [cat]\non a mat", "https://example.com/cat.png"),
+            "![a \\[cat\\] on a mat](https://example.com/cat.png)"
+        );
+        assert_eq!(
+            image_markdown("", "https://example.com/cat.png"),
+            "![](https://example.com/cat.png)"
+        );
+    }
+
+    #[test]
+    fn pasted_image_paths() {
+        let dir = temp_dir("pasted-image");
+        let image = dir.join("my cat.PNG");
+        std::fs::write(&image, b"").unwrap();
+        std::fs::write(dir.join("notes.txt"), b"").unwrap();
+
+        assert_eq!(
+            pasted_image_path(&format!("  {}\n", image.display())),
+            Some(image.clone())
+        );
+        let uri = format!("file://{}", image.display()).replace(' ', "%20");
+        assert_eq!(pasted_image_path(&uri), Some(image.clone()));
+
+        assert_eq!(
+            pasted_image_path(&dir.join("notes.txt").to_string_lossy()),
+            None
+        );
+        assert_eq!(
+            pasted_image_path(&dir.join("missing.png").to_string_lossy()),
+            None
+        );
+        assert_eq!(
+            pasted_image_path(&format!("{}\n{}", image.display(), image.display())),
+            None
+        );
+        assert_eq!(pasted_image_path("just some text"), None);
+    }
+
+    #[test]
+    fn upload_urls() {
+        assert_eq!(
+            join_url("https://example.com/media", "cat.png"),
+            Ok("https://example.com/media/cat.png".to_string())
+        );
+        assert_eq!(
+            url_from_response(" https://example.com/media/cat.png\n"),
+            Ok("https://example.com/media/cat.png".to_string())
+        );
+        assert!(url_from_response("{\"ok\": true}").is_err());
+        assert!(url_from_response("file:///etc/passwd").is_err());
+    }
+}
@@ -0,0 +1,230 @@
+//! SFTP publishing backend.
+//!
+//! This runs the system's OpenSSH `sftp` client in batch mode, so it picks up the
+//! user's SSH keys, agent, `known_hosts` and `~/.ssh/config` like any other SSH tool.
+//! Password logins aren't supported, since batch mode can't prompt for one.
+
+use std::process::Stdio;
+
+use tokio::{io::AsyncWriteExt, process::Command};
+use tracing::debug;
+
+/// Uploads `local_path` to `remote_path` on `host`.
+pub async fn put(
+    host: &str,
+    port: Option<u16>,
+    user: Option<&str>,
+    identity_file: Option<&str>,
+    local_path: &str,
+    remote_path: &str,
+) -> Result<(), String> {
+    let destination = destination(host, user);
+    let args = sftp_args(&destination, port, identity_file);
+    run("sftp", &args, &destination, local_path, remote_path).await
+}
+
+/// Runs `program` as the `sftp` client with `args`, feeding it the batch file that
+/// uploads `local_path` to `remote_path` on `destination`.
+async fn run(
+    program: &str,
+    args: &[String],
+    destination: &str,
+    local_path: &str,
+    remote_path: &str,
+) -> Result<(), String> {
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let mut child = command
+        .spawn()
+        .map_err(|e| format!("Failed to run sftp: {}", e))?;
+
+    let batch = batch_file(local_path, remote_path);
+    debug!("SFTP {}: {}", destination, batch.trim());
+
+    let mut stdin = child.stdin.take().ok_or("Failed to open sftp's stdin")?;
+    stdin
+        .write_all(batch.as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+    drop(stdin);
+
+    let output = child.wait_with_output().await.map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "sftp to {} failed ({}): {}",
+            destination,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+fn destination(host: &str, user: Option<&str>) -> String {
+    match user {
+        Some(user) => format!("{}@{}", user, host),
+        None => host.to_string(),
+    }
+}
+
+/// The arguments `sftp` is run with: a batch file read from stdin, and no prompts.
+fn sftp_args(destination: &str, port: Option<u16>, identity_file: Option<&str>) -> Vec<String> {
+    let mut args: Vec<String> = ["-b", "-", "-o", "BatchMode=yes"]
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+    if let Some(port) = port {
+        args.extend(["-P".to_string(), port.to_string()]);
+    }
+    if let Some(identity_file) = identity_file {
+        args.extend(["-i".to_string(), identity_file.to_string()]);
+    }
+    args.push(destination.to_string());
+    args
+}
+
+/// The batch file uploading `local_path` to `remote_path`.
+fn batch_file(local_path: &str, remote_path: &str) -> String {
+    format!("put {} {}\n", quote(local_path), quote(remote_path))
+}
+
+/// Quotes a path for an `sftp` batch file.
+fn quote(path: &str) -> String {
+    format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(unix)]
+    use crate::utils::test_server::{block_on, temp_dir};
+
+    /// Writes an executable script standing in for `sftp`, which records its arguments
+    /// and the batch file it's given next to itself.
+    #[cfg(unix)]
+    fn stub_sftp(name: &str, exit_code: i32) -> std::path::PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir(name);
+        let script = dir.join("sftp");
+        std::fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\ncd \"$(dirname \"$0\")\"\nprintf '%s\\n' \"$@\" > args\ncat > batch\necho \"Connection refused\" >&2\nexit {}\n",
+                exit_code
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        script
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sends_the_batch_file_to_sftp() {
+        let script = stub_sftp("sftp-put", 0);
+        let args = sftp_args("alice@example.com", Some(2222), None);
+
+        block_on(run(
+            script.to_str().unwrap(),
+            &args,
+            "alice@example.com",
+            "/tmp/my feed.txt",
+            "www/twtxt.txt",
+        ))
+        .unwrap();
+
+        let dir = script.parent().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join("batch")).unwrap(),
+            batch_file("/tmp/my feed.txt", "www/twtxt.txt")
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("args")).unwrap(),
+            format!("{}\n", args.join("\n"))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reports_sftp_failures() {
+        let script = stub_sftp("sftp-fail", 1);
+        let args = sftp_args("example.com", None, None);
+
+        let error = block_on(run(
+            script.to_str().unwrap(),
+            &args,
+            "example.com",
+            "/tmp/twtxt.txt",
+            "twtxt.txt",
+        ))
+        .unwrap_err();
+        assert_eq!(
+            error,
+            "sftp to example.com failed (exit status: 1): Connection refused"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reports_a_missing_sftp() {
+        let error = block_on(run(
+            "/nonexistent/sftp",
+            &[],
+            "example.com",
+            "/tmp/twtxt.txt",
+            "twtxt.txt",
+        ))
+        .unwrap_err();
+        assert!(error.starts_with("Failed to run sftp: "), "{}", error);
+    }
+
+    #[test]
+    fn runs_in_batch_mode_without_prompts() {
+        let args = sftp_args("example.com", None, None);
+        assert_eq!(args, ["-b", "-", "-o", "BatchMode=yes", "example.com"]);
+    }
+
+    #[test]
+    fn passes_port_and_identity_before_destination() {
+        let destination = destination("example.com", Some("alice"));
+        let args = sftp_args(
+            &destination,
+            Some(2222),
+            Some("/home/alice/.ssh/id_ed25519"),
+        );
+        assert_eq!(
+            args,
+            [
+                "-b",
+                "-",
+                "-o",
+                "BatchMode=yes",
+                "-P",
+                "2222",
+                "-i",
+                "/home/alice/.ssh/id_ed25519",
+                "alice@example.com",
+            ]
+        );
+    }
+
+    #[test]
+    fn quotes_paths_in_batch_file() {
+        assert_eq!(
+            batch_file("/tmp/my feed.txt", "www/twtxt.txt"),
+            "put \"/tmp/my feed.txt\" \"www/twtxt.txt\"\n"
+        );
+        assert_eq!(
+            batch_file(r#"C:\feeds\"odd".txt"#, "twtxt.txt"),
+            "put \"C:\\\\feeds\\\\\\\"odd\\\".txt\" \"twtxt.txt\"\n"
+        );
+    }
+}
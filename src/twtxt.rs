@@ -1,22 +1,33 @@
 //! Twtxt utilities for parsing, hashing, and threading tweets from twtxt feeds.
 
 use crate::config::AppConfig;
+use crate::twtxt::feed_id::FeedId;
+use crate::twtxt::hooks::{HookEnv, run_hook};
 use crate::twtxt::metadata::Metadata;
 use crate::twtxt::parsing::{parse_metadata, parse_tweets, parse_twt_contents};
+use crate::twtxt::rewrite::rewrite_twt;
 use crate::twtxt::twt_hash::compute_twt_hash;
-use crate::utils::download::{ParsedCache, download_text};
+use crate::utils::download::{ParsedCache, download_feed_text, local_feed_path};
 use crate::utils::hash::hash_sha256_str;
 use crate::utils::paths::get_parsed_cache_path;
 use chrono::{DateTime, Utc};
 use iced::widget::markdown;
 use std::fs::OpenOptions;
-use std::io::{Read, Write};
-use std::process::{Child, Command};
+use std::io::Write;
 
 use serde::{Deserialize, Serialize};
+use tracing::{debug, error};
 
+pub mod archive;
+pub mod drafts;
+pub mod feed_id;
+pub mod hooks;
+pub mod mentions;
 pub mod metadata;
 pub mod parsing;
+pub mod reply;
+pub mod rewrite;
+pub mod schedule;
 pub mod threading;
 pub mod twt_hash;
 
@@ -45,8 +56,8 @@ pub struct Tweet {
     /// The markdown-ready content extracted from the twtxt line.
     pub content: String,
 
-    /// The sha256 hash of the feed that provided this tweet.
-    pub feed_hash: String,
+    /// The identifier of the feed that provided this tweet.
+    pub feed_id: FeedId,
 
     /// The parsed markdown items used by the UI renderer.
     #[serde(skip)]
@@ -85,6 +96,7 @@ pub fn load_local_twtxt_feed(config: &AppConfig) -> Option<(String, String, Pars
     };
 
     let content_hash = hash_sha256_str(&content);
+    let feed_id = FeedId::from_url(&url);
 
     Some((
         nick,
@@ -92,6 +104,7 @@ pub fn load_local_twtxt_feed(config: &AppConfig) -> Option<(String, String, Pars
         ParsedCache {
             bundle,
             content_hash,
+            feed_id,
         },
     ))
 }
@@ -100,124 +113,235 @@ pub fn load_local_twtxt_feed(config: &AppConfig) -> Option<(String, String, Pars
 ///
 /// This helper is intentionally separated from the view layer so that the UI
 /// page only has to manage state changes and not the twtxt file logic.
-pub fn compose_twtxt_tweet(
-    composer_text: &str,
-    config: &AppConfig,
-    local_hash: Option<String>,
-) -> Option<Tweet> {
+///
+/// The hook scripts are run around writing the tweet. If `pre_tweet_script` or
+/// `tweet_script` fails, the tweet isn't posted and an error is returned. A failing
+/// `post_tweet_script` is only logged, since the tweet has been written by then.
+pub async fn compose_twtxt_tweet(
+    composer_text: String,
+    config: AppConfig,
+) -> Result<Tweet, String> {
     let trimmed = composer_text.trim();
     if trimmed.is_empty() {
-        return None;
+        return Err("Can't post an empty twt".to_string());
     }
 
-    let nick = config.metadata.nick.clone()?;
-    let now = Utc::now();
-    let (reply_to, display_content) = parse_twt_contents(trimmed);
+    let nick = config
+        .metadata
+        .nick
+        .clone()
+        .ok_or("Set a nick before posting")?;
     let url = config.metadata.urls.first().cloned().unwrap_or_default();
-    let timestamp_str = now.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
-    let written = trimmed.replace('\n', "\\u2028");
-
-    let feed_hash = local_hash.or_else(|| {
-        let mut file = OpenOptions::new()
-            .read(true)
-            .open(&config.paths.twtxt)
-            .ok()?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents).ok()?;
-        Some(hash_sha256_str(&contents))
-    })?;
-
-    let tweet = Tweet {
-        hash: compute_twt_hash(&url, &timestamp_str, &written),
-        reply_to,
-        timestamp: DateTime::parse_from_rfc3339(&timestamp_str)
-            .ok()?
-            .with_timezone(&Utc),
-        author: nick,
-        url: url.clone(),
-        content: display_content.clone(),
-        feed_hash: feed_hash.clone(),
-        md_items: markdown::parse(&display_content).collect(),
+    let timestamp_str = format_timestamp(Utc::now());
+    let written = escape_twt_text(trimmed);
+
+    let tweet = build_own_tweet(&nick, &url, &timestamp_str, &written)?;
+
+    let env = HookEnv {
+        hash: tweet.hash.clone(),
+        timestamp: timestamp_str.clone(),
+        text: written.clone(),
+        reply_to: tweet.reply_to.clone(),
+        feed_path: config.paths.twtxt.clone(),
+        feed_url: url,
+        nick,
     };
 
     if let Some(path) = &config.paths.pre_tweet_script {
-        run_script(path, &[]).ok();
+        run_hook("pre_tweet_script", path, &[], &env).await?;
     }
 
     if let Some(path) = &config.paths.tweet_script {
-        run_script(path, &[&written]).ok();
-    } else if let Ok(mut file) = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&config.paths.twtxt)
+        run_hook("tweet_script", path, &[&written], &env).await?;
+    } else {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.paths.twtxt)
+            .map_err(|e| format!("Failed to open {}: {}", config.paths.twtxt, e))?;
+        writeln!(file, "{}\t{}", timestamp_str, written).map_err(|e| e.to_string())?;
+    }
+
+    if let Some(path) = &config.paths.post_tweet_script
+        && let Err(e) = run_hook("post_tweet_script", path, &[], &env).await
     {
-        let _ = writeln!(file, "{}\t{}", timestamp_str, written);
+        error!("{}", e);
     }
 
+    Ok(tweet)
+}
+
+/// Edits one of the user's own tweets in the local feed, or deletes it if `new_text`
+/// is `None`.
+///
+/// The tweet keeps its timestamp, but editing it changes its hash, so replies to the
+/// old hash no longer point at it. Afterwards `post_tweet_script` is run again so the
+/// updated feed gets published. Returns the edited tweet.
+pub async fn rewrite_twtxt_tweet(
+    hash: String,
+    new_text: Option<String>,
+    config: AppConfig,
+) -> Result<Option<Tweet>, String> {
+    let nick = config.metadata.nick.clone().unwrap_or_default();
+    let url = config.metadata.urls.first().cloned().unwrap_or_default();
+
+    let written = match new_text.as_deref().map(str::trim) {
+        Some("") => return Err("Can't save an empty twt, delete it instead".to_string()),
+        Some(text) => Some(escape_twt_text(text)),
+        None => None,
+    };
+
+    let old = rewrite_twt(&config.paths.twtxt, &url, &hash, written.as_deref())?;
+
+    let tweet = match &written {
+        Some(written) => Some(build_own_tweet(&nick, &url, &old.timestamp, written)?),
+        None => None,
+    };
+
     if let Some(path) = &config.paths.post_tweet_script {
-        run_script(path, &[]).ok();
+        let env = HookEnv {
+            hash: tweet.as_ref().map_or(hash.clone(), |t| t.hash.clone()),
+            timestamp: old.timestamp,
+            text: written.unwrap_or_default(),
+            reply_to: tweet.as_ref().and_then(|t| t.reply_to.clone()),
+            feed_path: config.paths.twtxt.clone(),
+            feed_url: url,
+            nick,
+        };
+        if let Err(e) = run_hook("post_tweet_script", path, &[], &env).await {
+            error!("{}", e);
+        }
     }
 
-    Some(tweet)
+    Ok(tweet)
 }
 
-fn run_script(script: &str, args: &[&str]) -> std::io::Result<Child> {
-    if cfg!(target_os = "windows") {
-        Command::new("cmd").args(["/C", script]).args(args).spawn()
-    } else {
-        Command::new("sh").arg(script).args(args).spawn()
-    }
+/// Builds the tweet that would be written for `composer_text` at `timestamp`, so it
+/// can be previewed with its final hash before it's posted.
+pub fn preview_twtxt_tweet(
+    composer_text: &str,
+    timestamp: &str,
+    config: &AppConfig,
+) -> Result<Tweet, String> {
+    let nick = config.metadata.nick.clone().unwrap_or_default();
+    let url = config.metadata.urls.first().cloned().unwrap_or_default();
+    build_own_tweet(
+        &nick,
+        &url,
+        timestamp,
+        &escape_twt_text(composer_text.trim()),
+    )
+}
+
+/// Formats a timestamp the way it's written to `twtxt.txt`, which the hash depends on.
+pub fn format_timestamp(timestamp: DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+/// Escapes newlines, since every tweet has to fit on a single line of the feed.
+fn escape_twt_text(text: &str) -> String {
+    text.replace('\n', "\\u2028")
+}
+
+/// Builds a tweet of the user's own feed from a line as it's written to `twtxt.txt`.
+fn build_own_tweet(nick: &str, url: &str, timestamp: &str, written: &str) -> Result<Tweet, String> {
+    let (reply_to, display_content) = parse_twt_contents(written);
+
+    Ok(Tweet {
+        hash: compute_twt_hash(url, timestamp, written),
+        reply_to,
+        timestamp: DateTime::parse_from_rfc3339(timestamp)
+            .map_err(|e| e.to_string())?
+            .with_timezone(&Utc),
+        author: nick.to_string(),
+        url: url.to_string(),
+        md_items: markdown::parse(&display_content).collect(),
+        content: display_content,
+        feed_id: FeedId::from_url(url),
+    })
 }
 
 /// Downloads a twtxt feed, parses it into a `ParsedCache`, and caches the parsed result.
 ///
 /// If the feed content has not changed since the last download, the previously parsed
-/// bundle is reused.
+/// bundle is reused. If lines were only appended to it, only the new lines are parsed.
 ///
 /// `nick` is the display name to use for tweets when the feed metadata does not include one.
 /// `use_nick` controls whether the provided nick should override the feed's own nick.
 ///
 /// Note that `nick` is only used as a display name, and does not affect the actual cached content.
 ///
-/// `hash_url` is the URL to use for hashes (feed_hash, twt hash). If `None`, the main feed URL will be used.
+/// `hash_url` is the URL to use for hashes and the feed's [`FeedId`]. If `None`, the main feed URL will be used,
+/// or for local feeds (`file://` URLs and plain paths), the first `# url` the feed declares.
 pub async fn download_and_parse_twtxt(
     nick: String,
     url: String,
     hash_url: Option<String>,
     use_nick: bool,
 ) -> Result<ParsedCache, String> {
-    let raw = download_text(url.clone()).await?;
+    let fetched = download_feed_text(url.clone()).await?;
+    let raw = fetched.content;
     let raw_hash = hash_sha256_str(&raw);
-    let parsed_path = get_parsed_cache_path(&url)?;
-
-    if let Ok(cached_str) = std::fs::read_to_string(&parsed_path)
-        && let Ok(mut cache) = serde_json::from_str::<ParsedCache>(&cached_str)
-        && cache.content_hash == raw_hash
-    {
-        for tweet in &mut cache.bundle.tweets {
-            tweet.md_items = markdown::parse(&tweet.content).collect();
+    let parsed_path = get_parsed_cache_path(&FeedId::from_url(&url))?;
+
+    let cached = std::fs::read_to_string(&parsed_path)
+        .ok()
+        .and_then(|s| serde_json::from_str::<ParsedCache>(&s).ok());
+
+    let cached = match cached {
+        Some(mut cache) if cache.content_hash == raw_hash => {
+            for tweet in &mut cache.bundle.tweets {
+                tweet.md_items = markdown::parse(&tweet.content).collect();
+            }
+            return Ok(apply_nick_override(cache, &nick, use_nick));
         }
-        return Ok(apply_nick_override(cache, &nick, use_nick));
-    }
+        cached => cached,
+    };
 
     let metadata = parse_metadata(&raw);
 
-    let canonical_nick = metadata
-        .as_ref()
-        .and_then(|m| m.nick.as_ref())
-        .cloned()
-        .unwrap_or_else(|| {
-            reqwest::Url::parse(&url)
-                .ok()
-                .and_then(|u| u.host_str().map(str::to_string))
-                .unwrap_or_else(|| nick.clone())
-        });
-
-    let tweets = parse_tweets(&canonical_nick, &url, hash_url.as_deref(), &raw);
-
-    let mut cache = ParsedCache {
-        content_hash: raw_hash,
-        bundle: FeedBundle { tweets, metadata },
+    // Local feeds are hashed against the URL they declare, since that's the URL
+    // everyone else sees them at.
+    let hash_url = hash_url.or_else(|| {
+        local_feed_path(&url)
+            .ok()
+            .flatten()
+            .and(metadata.as_ref())
+            .and_then(|m| m.urls.first().cloned())
+    });
+
+    let canonical_nick = canonical_nick(
+        metadata.as_ref(),
+        hash_url.as_deref().unwrap_or(&url),
+        &nick,
+    );
+
+    let mut cache = match (fetched.appended_at, cached) {
+        // Only new lines were appended to the content we parsed last time
+        (Some(offset), Some(mut cache))
+            if cache.content_hash == hash_sha256_str(&raw[..offset]) =>
+        {
+            debug!("Parsing {} appended bytes of {}", raw.len() - offset, url);
+
+            let appended = parse_tweets(&canonical_nick, &url, hash_url.as_deref(), &raw[offset..]);
+            cache.bundle.tweets.extend(appended);
+
+            for tweet in &mut cache.bundle.tweets {
+                tweet.author = canonical_nick.clone();
+            }
+
+            cache.bundle.metadata = metadata;
+            cache.content_hash = raw_hash;
+            cache
+        }
+        _ => ParsedCache {
+            bundle: FeedBundle {
+                tweets: parse_tweets(&canonical_nick, &url, hash_url.as_deref(), &raw),
+                metadata,
+            },
+            content_hash: raw_hash,
+            feed_id: FeedId::from_url(hash_url.as_deref().unwrap_or(&url)),
+        },
     };
 
     let serialized = serde_json::to_string(&cache).map_err(|e| e.to_string())?;
@@ -230,6 +354,19 @@ pub async fn download_and_parse_twtxt(
     Ok(apply_nick_override(cache, &nick, use_nick))
 }
 
+/// Picks the name used for a feed's tweets: its declared nick, or else its host.
+fn canonical_nick(metadata: Option<&Metadata>, url: &str, fallback: &str) -> String {
+    metadata
+        .and_then(|m| m.nick.as_ref())
+        .cloned()
+        .unwrap_or_else(|| {
+            reqwest::Url::parse(url)
+                .ok()
+                .and_then(|u| u.host_str().map(str::to_string))
+                .unwrap_or_else(|| fallback.to_string())
+        })
+}
+
 /// Optionally overrides the author name for all tweets in the bundle.
 fn apply_nick_override(mut parsed: ParsedCache, nick: &str, use_nick: bool) -> ParsedCache {
     if use_nick {
@@ -0,0 +1,405 @@
+//! Splitting old twts out of the user's feed into archive feeds.
+//!
+//! Follows the [Archive Feeds](https://twtxt.dev/exts/archive-feeds.html) extension:
+//! old twts are moved into a new file next to the feed, which gets a copy of the
+//! feed's metadata, and the feed's `# prev = <hash> <url>` is pointed at it. The copied
+//! `# prev` of the archive continues the chain to the archive before it. Archives keep
+//! the main feed's `# url`, so the twts in them keep their hashes.
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use tracing::info;
+
+use crate::{
+    config::AppConfig,
+    twtxt::{metadata::Link, rewrite::replace_file, twt_hash::compute_twt_hash},
+};
+
+/// A line of a feed, along with its timestamp if it's a twt.
+type Line<'a> = (&'a str, Option<DateTime<Utc>>);
+
+/// A feed split into its metadata header and the lines after it.
+struct SplitFeed<'a> {
+    header: Vec<&'a str>,
+    /// Every line after the header.
+    body: Vec<Line<'a>>,
+}
+
+impl<'a> SplitFeed<'a> {
+    /// Splits a feed the same way [`AppConfig::save`] does, so the header stays intact.
+    fn parse(content: &'a str) -> Self {
+        let mut header = Vec::new();
+        let mut body = Vec::new();
+        let mut in_header = true;
+
+        for line in content.lines() {
+            if in_header && !line.starts_with('#') && !line.trim().is_empty() {
+                in_header = false;
+            }
+            if in_header {
+                header.push(line);
+            } else {
+                let timestamp = line
+                    .split_once('\t')
+                    .filter(|_| !line.starts_with('#'))
+                    .and_then(|(ts, _)| DateTime::parse_from_rfc3339(ts).ok())
+                    .map(|ts| ts.with_timezone(&Utc));
+                body.push((line, timestamp));
+            }
+        }
+
+        Self { header, body }
+    }
+
+    fn twt_count(&self) -> usize {
+        self.body.iter().filter(|(_, ts)| ts.is_some()).count()
+    }
+
+    /// Splits the body into the lines to archive and the lines to keep, keeping the
+    /// newest `keep` twts by timestamp, whatever order they're in the file. Twts with
+    /// the same timestamp are archived in the order they appear in.
+    ///
+    /// Both keep the order of the file. Returns `None` if there are no more than
+    /// `keep` twts.
+    #[allow(clippy::type_complexity)]
+    fn split_off_oldest(
+        &self,
+        keep: usize,
+    ) -> Option<(
+        Vec<&(&'a str, Option<DateTime<Utc>>)>,
+        Vec<&(&'a str, Option<DateTime<Utc>>)>,
+    )> {
+        let mut twts: Vec<(usize, DateTime<Utc>)> = self
+            .body
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (_, ts))| Some((i, (*ts)?)))
+            .collect();
+        if twts.len() <= keep {
+            return None;
+        }
+        twts.sort_by_key(|(_, ts)| *ts);
+
+        let mut to_archive = vec![false; self.body.len()];
+        for (i, _) in &twts[..twts.len() - keep] {
+            to_archive[*i] = true;
+        }
+
+        let (archived, kept) = self
+            .body
+            .iter()
+            .zip(to_archive)
+            .partition::<Vec<_>, _>(|(_, archive)| *archive);
+        Some((
+            archived.into_iter().map(|(line, _)| line).collect(),
+            kept.into_iter().map(|(line, _)| line).collect(),
+        ))
+    }
+}
+
+/// Returns whether the feed has grown past the limits in the archiving settings, and
+/// archiving it would move any twts.
+///
+/// A feed can be over `max_bytes` with no more than `keep_twts` twts in it, such as
+/// when they're long. Archiving it wouldn't make it any smaller, and would only leave
+/// an empty archive behind every time a twt is posted.
+pub fn needs_archiving(config: &AppConfig) -> bool {
+    let settings = &config.archive;
+    if settings.max_twts.is_none() && settings.max_bytes.is_none() {
+        return false;
+    }
+
+    let Ok(content) = std::fs::read_to_string(&config.paths.twtxt) else {
+        return false;
+    };
+    let twt_count = SplitFeed::parse(&content).twt_count();
+
+    let over_limit = settings
+        .max_bytes
+        .is_some_and(|max| content.len() as u64 > max)
+        || settings.max_twts.is_some_and(|max| twt_count > max);
+    over_limit && twt_count > settings.keep_twts
+}
+
+/// The files that archiving a feed results in.
+struct Archive {
+    /// The contents of the new archive.
+    content: String,
+    /// The contents of the feed, without the archived twts.
+    remaining: String,
+    /// The hash of the newest archived twt, which identifies the archive in `# prev`.
+    newest_hash: String,
+    oldest: DateTime<Utc>,
+    newest: DateTime<Utc>,
+    twt_count: usize,
+}
+
+impl Archive {
+    /// Moves all but the newest `keep` twts of the feed `content`, published at `url`,
+    /// into an archive. Returns `None` if there's nothing to archive.
+    fn build(content: &str, url: &str, keep: usize) -> Option<Self> {
+        let feed = SplitFeed::parse(content);
+        let (archived, kept) = feed.split_off_oldest(keep)?;
+
+        let (newest_line, newest) = archived.iter().max_by_key(|(_, ts)| *ts)?;
+        let newest = (*newest)?;
+        let (timestamp, text) = newest_line.split_once('\t').unwrap_or_default();
+        let oldest = archived
+            .iter()
+            .filter_map(|(_, ts)| *ts)
+            .min()
+            .unwrap_or(newest);
+
+        // The archive gets the feed's header as it is now, including its `# prev`
+        let mut archive = Vec::with_capacity(feed.header.len() + archived.len() + 1);
+        archive.extend(feed.header.iter().copied());
+        let url_line = format!("# url = {}", url);
+        if !feed.header.iter().any(|l| header_key(l) == Some("url")) {
+            archive.push(&url_line);
+        }
+        archive.extend(archived.iter().map(|(line, _)| *line));
+
+        let mut remaining = feed.header.clone();
+        remaining.extend(kept.iter().map(|(line, _)| *line));
+
+        Some(Self {
+            content: archive.join("\n") + "\n",
+            remaining: remaining.join("\n") + "\n",
+            newest_hash: compute_twt_hash(url, timestamp, text.trim()),
+            oldest,
+            newest,
+            twt_count: archived.len(),
+        })
+    }
+}
+
+/// Moves all but the newest `keep_twts` twts of the user's feed into a new archive.
+///
+/// Returns the path of the new archive, or `None` if there was nothing to archive.
+/// The feed's `# prev` is updated through the config, which is saved.
+pub fn archive_feed(config: &mut AppConfig) -> Result<Option<PathBuf>, String> {
+    let feed_path = PathBuf::from(&config.paths.twtxt);
+    let url = config
+        .metadata
+        .urls
+        .first()
+        .cloned()
+        .ok_or("Set the feed's URL before archiving it")?;
+
+    let content = std::fs::read_to_string(&feed_path)
+        .map_err(|e| format!("Failed to read {}: {}", feed_path.display(), e))?;
+    let Some(archive) = Archive::build(&content, &url, config.archive.keep_twts) else {
+        return Ok(None);
+    };
+
+    let archive_path = archive_path(&feed_path, archive.oldest, archive.newest);
+    let file_name = archive_path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .ok_or("Invalid archive path")?;
+
+    // Written first, so the feed never points at an archive that doesn't exist
+    std::fs::write(&archive_path, &archive.content)
+        .map_err(|e| format!("Failed to write {}: {}", archive_path.display(), e))?;
+    replace_file(&feed_path, &archive.remaining)?;
+
+    config.metadata.prev = Some(Link {
+        text: archive.newest_hash,
+        url: file_name,
+    });
+    config
+        .save()
+        .map_err(|e| format!("Failed to update the feed's prev link: {}", e))?;
+
+    info!(
+        "Archived {} twts to {}",
+        archive.twt_count,
+        archive_path.display()
+    );
+
+    Ok(Some(archive_path))
+}
+
+/// Returns the key of a metadata line like `# url = ...`.
+fn header_key(line: &str) -> Option<&str> {
+    let (key, _) = line.strip_prefix('#')?.split_once('=')?;
+    Some(key.trim())
+}
+
+/// Picks an unused name for an archive next to the feed, like
+/// `twtxt-2024-01-01-2024-06-30.txt`.
+fn archive_path(feed_path: &Path, from: DateTime<Utc>, to: DateTime<Utc>) -> PathBuf {
+    let stem = feed_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "twtxt".to_string());
+    let base = format!(
+        "{}-{}-{}",
+        stem,
+        from.format("%Y-%m-%d"),
+        to.format("%Y-%m-%d")
+    );
+
+    let mut path = feed_path.with_file_name(format!("{}.txt", base));
+    let mut n = 2;
+    while path.exists() {
+        path = feed_path.with_file_name(format!("{}-{}.txt", base, n));
+        n += 1;
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEED: &str = "\
+# nick = alice
+# prev = abcdefg twtxt-old.txt
+
+2024-01-03T00:00:00Z\tthird
+2024-01-01T00:00:00Z\tfirst
+# a comment
+2024-01-02T00:00:00Z\tsecond
+2024-01-02T00:00:00Z\tsecond again
+";
+
+    #[test]
+    fn splits_header_from_body() {
+        let feed = SplitFeed::parse(FEED);
+        assert_eq!(
+            feed.header,
+            ["# nick = alice", "# prev = abcdefg twtxt-old.txt", ""]
+        );
+        assert_eq!(feed.body.len(), 5);
+        assert_eq!(feed.body[2], ("# a comment", None));
+        assert_eq!(feed.twt_count(), 4);
+    }
+
+    #[test]
+    fn keeps_exactly_the_newest_twts() {
+        let feed = SplitFeed::parse(FEED);
+        let (archived, kept) = feed.split_off_oldest(2).unwrap();
+
+        // Of the two twts with the same timestamp, the later one in the file is kept
+        assert_eq!(
+            archived.iter().map(|(line, _)| *line).collect::<Vec<_>>(),
+            [
+                "2024-01-01T00:00:00Z\tfirst",
+                "2024-01-02T00:00:00Z\tsecond"
+            ]
+        );
+        assert_eq!(
+            kept.iter().map(|(line, _)| *line).collect::<Vec<_>>(),
+            [
+                "2024-01-03T00:00:00Z\tthird",
+                "# a comment",
+                "2024-01-02T00:00:00Z\tsecond again"
+            ]
+        );
+    }
+
+    #[test]
+    fn nothing_to_split_within_the_limit() {
+        assert!(SplitFeed::parse(FEED).split_off_oldest(4).is_none());
+        assert!(Archive::build(FEED, "https://example.com/twtxt.txt", 4).is_none());
+    }
+
+    #[test]
+    fn builds_archive_with_the_feeds_header() {
+        let url = "https://example.com/twtxt.txt";
+        let archive = Archive::build(FEED, url, 1).unwrap();
+
+        assert_eq!(
+            archive.content,
+            "\
+# nick = alice
+# prev = abcdefg twtxt-old.txt
+
+# url = https://example.com/twtxt.txt
+2024-01-01T00:00:00Z\tfirst
+2024-01-02T00:00:00Z\tsecond
+2024-01-02T00:00:00Z\tsecond again
+"
+        );
+        assert_eq!(
+            archive.remaining,
+            "\
+# nick = alice
+# prev = abcdefg twtxt-old.txt
+
+2024-01-03T00:00:00Z\tthird
+# a comment
+"
+        );
+        assert_eq!(archive.twt_count, 3);
+        assert_eq!(archive.oldest.to_rfc3339(), "2024-01-01T00:00:00+00:00");
+        assert_eq!(archive.newest.to_rfc3339(), "2024-01-02T00:00:00+00:00");
+        assert_eq!(
+            archive.newest_hash,
+            compute_twt_hash(url, "2024-01-02T00:00:00Z", "second again")
+        );
+    }
+
+    fn archiving_config(
+        name: &str,
+        max_twts: Option<usize>,
+        max_bytes: Option<u64>,
+        keep_twts: usize,
+    ) -> AppConfig {
+        let feed = crate::utils::test_server::temp_dir(name).join("twtxt.txt");
+        std::fs::write(&feed, FEED).unwrap();
+
+        let mut config = AppConfig::default();
+        config.paths.twtxt = feed.to_string_lossy().into_owned();
+        config.archive.max_twts = max_twts;
+        config.archive.max_bytes = max_bytes;
+        config.archive.keep_twts = keep_twts;
+        config
+    }
+
+    #[test]
+    fn archives_oversized_feeds_only_if_twts_would_move() {
+        assert!(needs_archiving(&archiving_config(
+            "archive-bytes",
+            None,
+            Some(10),
+            3
+        )));
+        assert!(!needs_archiving(&archiving_config(
+            "archive-bytes-kept",
+            None,
+            Some(10),
+            4
+        )));
+        assert!(!needs_archiving(&archiving_config(
+            "archive-bytes-small",
+            None,
+            Some(FEED.len() as u64),
+            0
+        )));
+    }
+
+    #[test]
+    fn archives_feeds_with_too_many_twts() {
+        assert!(needs_archiving(&archiving_config(
+            "archive-twts",
+            Some(3),
+            None,
+            2
+        )));
+        assert!(!needs_archiving(&archiving_config(
+            "archive-twts-few",
+            Some(4),
+            None,
+            2
+        )));
+        assert!(!needs_archiving(&archiving_config(
+            "archive-off",
+            None,
+            None,
+            0
+        )));
+    }
+}
@@ -0,0 +1,295 @@
+//! Unsent tweets from the composer, autosaved to the data directory.
+//!
+//! A draft is updated on every edit and only removed once it's posted or discarded, so
+//! a half-written tweet survives cancelling the composer, restarting the app or a
+//! crash. The composer saves the drafts shortly after the typing stops. Replies remember the subject of the thread they answer, so replying in the
+//! same thread again picks the draft back up.
+
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+use crate::twtxt::{
+    metadata::Link,
+    parsing::{get_mention_re, get_subject_re},
+};
+use crate::utils::{paths::get_drafts_path, write_atomically};
+
+/// The tweet a draft replies to, taken from the start of its text.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplyContext {
+    /// The hash from the `(#<hash>)` subject.
+    pub hash: String,
+    /// The mentions directly following the subject, usually the author being replied to.
+    pub mentions: Vec<Link>,
+}
+
+impl ReplyContext {
+    /// Reads the reply context from the start of a tweet, if it has a subject.
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim_start();
+        let cap = get_subject_re().captures(text)?;
+        let mut rest = &text[cap.get(0).unwrap().end()..];
+
+        let mut mentions = Vec::new();
+        while let Some(cap) = get_mention_re().captures(rest) {
+            let m = cap.get(0).unwrap();
+            if m.start() != 0 {
+                break;
+            }
+
+            let nick = cap["nick"].to_string();
+            mentions.push(Link {
+                url: cap
+                    .name("url")
+                    .map(|u| u.as_str().trim().to_string())
+                    .unwrap_or_else(|| nick.clone()),
+                text: nick,
+            });
+            rest = rest[m.end()..].trim_start();
+        }
+
+        Some(Self {
+            hash: cap["hash"].to_string(),
+            mentions,
+        })
+    }
+}
+
+/// A tweet that hasn't been posted yet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Draft {
+    pub id: u64,
+    /// The raw text of the composer, including any subject and mentions.
+    pub text: String,
+    pub reply: Option<ReplyContext>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Draft {
+    /// The first line of the draft without its reply context, for showing in a list.
+    pub fn preview(&self) -> String {
+        let line = body(&self.text).lines().next().unwrap_or("").trim();
+        if line.chars().count() > 60 {
+            format!("{}...", line.chars().take(60).collect::<String>())
+        } else {
+            line.to_string()
+        }
+    }
+}
+
+/// Returns the text of a tweet after its subject and leading mentions.
+fn body(text: &str) -> &str {
+    let mut text = text.trim_start();
+    if let Some(cap) = get_subject_re().captures(text) {
+        text = &text[cap.get(0).unwrap().end()..];
+    }
+    while let Some(m) = get_mention_re().find(text)
+        && m.start() == 0
+    {
+        text = text[m.end()..].trim_start();
+    }
+    text
+}
+
+/// The user's drafts, loaded from the drafts file.
+#[derive(Debug, Default)]
+pub struct Drafts {
+    /// Where the drafts are saved, or `None` if the data directory couldn't be found.
+    path: Option<PathBuf>,
+    drafts: Vec<Draft>,
+}
+
+impl Drafts {
+    /// Loads the saved drafts, starting with none if the file is missing or unreadable.
+    pub fn load() -> Self {
+        match get_drafts_path() {
+            Ok(path) => Self::load_from(path),
+            Err(e) => {
+                error!("Failed to locate drafts: {}", e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Loads the drafts saved at `path`.
+    fn load_from(path: PathBuf) -> Self {
+        let drafts = match std::fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                warn!("Failed to parse drafts at {}: {}", path.display(), e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+
+        Self {
+            path: Some(path),
+            drafts,
+        }
+    }
+
+    /// All drafts, most recently edited first.
+    pub fn list(&self) -> &[Draft] {
+        &self.drafts
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Draft> {
+        self.drafts.iter().find(|d| d.id == id)
+    }
+
+    /// The most recent draft replying to the tweet with the given hash.
+    pub fn for_reply(&self, hash: &str) -> Option<&Draft> {
+        self.drafts
+            .iter()
+            .find(|d| d.reply.as_ref().is_some_and(|r| r.hash == hash))
+    }
+
+    /// Saves `text` to the draft with the given id, or to a new draft if there's none.
+    ///
+    /// Drafts with nothing but their reply context are removed. Returns the id of the
+    /// draft the text was saved to, if it was kept.
+    pub fn update(&mut self, id: Option<u64>, text: &str) -> Option<u64> {
+        if body(text).trim().is_empty() {
+            if let Some(id) = id {
+                self.remove(id);
+            }
+            return None;
+        }
+
+        let id = id.unwrap_or_else(|| self.drafts.iter().map(|d| d.id).max().unwrap_or(0) + 1);
+        self.drafts.retain(|d| d.id != id);
+        self.drafts.insert(
+            0,
+            Draft {
+                id,
+                text: text.to_string(),
+                reply: ReplyContext::parse(text),
+                updated_at: Utc::now(),
+            },
+        );
+        Some(id)
+    }
+
+    pub fn remove(&mut self, id: u64) {
+        self.drafts.retain(|d| d.id != id);
+    }
+
+    /// Saves the drafts as they are now. The file is written on a blocking thread, so
+    /// saving doesn't hold up the UI.
+    pub fn save(&self) -> impl Future<Output = Result<(), String>> + use<> {
+        let path = self.path.clone();
+        let json = serde_json::to_string_pretty(&self.drafts).map_err(|e| e.to_string());
+
+        async move {
+            let Some(path) = path else {
+                return Ok(());
+            };
+            let json = json?;
+            tokio::task::spawn_blocking(move || write_atomically(&path, json))
+                .await
+                .map_err(|e| e.to_string())?
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_server::{block_on, temp_dir};
+
+    fn drafts(name: &str) -> (Drafts, PathBuf) {
+        let path = temp_dir(name).join("drafts.json");
+        (Drafts::load_from(path.clone()), path)
+    }
+
+    #[test]
+    fn parses_the_subject_and_leading_mentions() {
+        let reply = ReplyContext::parse(
+            "  (#abc1234) @<alice https://a.example/twtxt.txt> @<bob> hi @<carol https://c.example>",
+        )
+        .unwrap();
+        assert_eq!(reply.hash, "abc1234");
+        assert_eq!(
+            reply.mentions,
+            vec![
+                Link {
+                    text: "alice".to_string(),
+                    url: "https://a.example/twtxt.txt".to_string(),
+                },
+                Link {
+                    text: "bob".to_string(),
+                    url: "bob".to_string(),
+                },
+            ]
+        );
+
+        assert_eq!(ReplyContext::parse("hi (#abc1234)"), None);
+    }
+
+    #[test]
+    fn body_skips_the_reply_context() {
+        assert_eq!(
+            body("(#abc1234) @<alice https://a.example> hi @<bob>"),
+            "hi @<bob>"
+        );
+        assert_eq!(body("@<bob> @<alice> hi"), "hi");
+        assert_eq!(body("  just text"), "just text");
+        assert_eq!(body("(#abc1234) @<bob> "), "");
+    }
+
+    #[test]
+    fn finds_the_latest_draft_for_a_reply() {
+        let (mut drafts, _) = drafts("drafts-reply");
+        let first = drafts.update(None, "(#abc1234) @<bob> first").unwrap();
+        drafts.update(None, "unrelated").unwrap();
+        assert_eq!(drafts.for_reply("abc1234").unwrap().id, first);
+
+        let second = drafts.update(None, "(#abc1234) @<bob> second").unwrap();
+        assert_eq!(drafts.for_reply("abc1234").unwrap().id, second);
+        assert!(drafts.for_reply("def5678").is_none());
+    }
+
+    #[test]
+    fn drops_drafts_without_a_body() {
+        let (mut drafts, _) = drafts("drafts-empty");
+        assert_eq!(drafts.update(None, "(#abc1234) @<bob> "), None);
+
+        let id = drafts.update(None, "(#abc1234) @<bob> hi").unwrap();
+        assert_eq!(drafts.update(Some(id), "(#abc1234) @<bob>"), None);
+        assert!(drafts.list().is_empty());
+    }
+
+    #[test]
+    fn saves_and_loads_drafts() {
+        let (mut drafts, path) = drafts("drafts-save");
+        let first = drafts.update(None, "first").unwrap();
+        let second = drafts.update(None, "(#abc1234) second").unwrap();
+        assert_eq!(drafts.update(Some(first), "first, edited"), Some(first));
+        block_on(drafts.save()).unwrap();
+
+        let mut loaded = Drafts::load_from(path.clone());
+        let ids: Vec<u64> = loaded.list().iter().map(|d| d.id).collect();
+        assert_eq!(ids, vec![first, second]);
+        assert_eq!(loaded.get(first).unwrap().text, "first, edited");
+        assert_eq!(
+            loaded.get(second).unwrap().reply.as_ref().unwrap().hash,
+            "abc1234"
+        );
+
+        loaded.remove(first);
+        block_on(loaded.save()).unwrap();
+        let loaded = Drafts::load_from(path.clone());
+        assert!(loaded.get(first).is_none());
+        assert!(loaded.get(second).is_some());
+        assert!(!path.with_file_name(".drafts.json.tmp").exists());
+    }
+
+    #[test]
+    fn starts_empty_with_a_corrupt_file() {
+        let (_, path) = drafts("drafts-corrupt");
+        std::fs::write(&path, "not json").unwrap();
+        assert!(Drafts::load_from(path).list().is_empty());
+    }
+}
@@ -0,0 +1,89 @@
+//! Module providing a stable identifier for twtxt feeds.
+
+use std::fmt;
+
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+/// A stable identifier for a feed, derived from its canonical URL.
+///
+/// Unlike a hash of the feed's content, this stays the same when the author posts,
+/// so it's used to key anything that belongs to a feed, such as its avatar, its
+/// caches and per-feed settings. Archives share the identifier of the main feed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct FeedId(String);
+
+impl FeedId {
+    /// Derives the identifier of the feed at `url`.
+    ///
+    /// URLs are normalized first, so the scheme and host are lowercased and default
+    /// ports and fragments are dropped. Local paths are used as they are.
+    pub fn from_url(url: &str) -> Self {
+        let url = url.trim();
+        match Url::parse(url) {
+            Ok(mut parsed) if parsed.has_host() => {
+                parsed.set_fragment(None);
+                FeedId(parsed.to_string())
+            }
+            _ => FeedId(url.to_string()),
+        }
+    }
+
+    /// The canonical URL the identifier was derived from.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for FeedId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_urls() {
+        let id = FeedId::from_url("https://example.com/twtxt.txt");
+        assert_eq!(id.as_str(), "https://example.com/twtxt.txt");
+
+        for url in [
+            "HTTPS://Example.COM/twtxt.txt",
+            "https://example.com:443/twtxt.txt",
+            "https://example.com/twtxt.txt#section",
+            "  https://example.com/twtxt.txt\n",
+        ] {
+            assert_eq!(FeedId::from_url(url), id, "{}", url);
+        }
+        assert_eq!(
+            FeedId::from_url("http://example.com:80/").as_str(),
+            "http://example.com/"
+        );
+    }
+
+    #[test]
+    fn keeps_what_tells_feeds_apart() {
+        let id = FeedId::from_url("https://example.com/twtxt.txt");
+        for url in [
+            "http://example.com/twtxt.txt",
+            "https://example.com:8443/twtxt.txt",
+            "https://example.com/Twtxt.txt",
+            "https://example.com/twtxt.txt?user=bob",
+        ] {
+            assert_ne!(FeedId::from_url(url), id, "{}", url);
+        }
+    }
+
+    #[test]
+    fn local_paths_are_used_as_they_are() {
+        assert_eq!(
+            FeedId::from_url("/home/me/twtxt.txt").as_str(),
+            "/home/me/twtxt.txt"
+        );
+        assert_eq!(FeedId::from_url("~/twtxt.txt").to_string(), "~/twtxt.txt");
+    }
+}
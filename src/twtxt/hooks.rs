@@ -0,0 +1,228 @@
+//! Module for running the user's hook scripts around posting a tweet.
+//!
+//! Scripts are awaited with a timeout, their output is written to the logs and their
+//! exit status is checked, so a failing script is never silently ignored.
+
+use std::{process::Stdio, time::Duration};
+
+use tokio::process::Command;
+use tracing::{info, warn};
+
+/// How long a script may run before it's killed.
+#[cfg(not(test))]
+const SCRIPT_TIMEOUT: Duration = Duration::from_secs(60);
+#[cfg(test)]
+const SCRIPT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Details about the tweet being posted, passed to scripts as environment variables.
+#[derive(Debug, Clone, Default)]
+pub struct HookEnv {
+    /// `TWTGUI_TWT_HASH`: the hash of the new tweet.
+    pub hash: String,
+    /// `TWTGUI_TWT_TIMESTAMP`: the RFC 3339 timestamp of the new tweet.
+    pub timestamp: String,
+    /// `TWTGUI_TWT_TEXT`: the tweet as written to the feed.
+    pub text: String,
+    /// `TWTGUI_REPLY_TO`: the hash of the tweet being replied to, if any.
+    pub reply_to: Option<String>,
+    /// `TWTGUI_FEED_PATH`: the path of the local `twtxt.txt`.
+    pub feed_path: String,
+    /// `TWTGUI_FEED_URL`: the public URL of the feed.
+    pub feed_url: String,
+    /// `TWTGUI_NICK`: the user's nick.
+    pub nick: String,
+}
+
+impl HookEnv {
+    fn vars(&self) -> Vec<(&'static str, &str)> {
+        vec![
+            ("TWTGUI_TWT_HASH", &self.hash),
+            ("TWTGUI_TWT_TIMESTAMP", &self.timestamp),
+            ("TWTGUI_TWT_TEXT", &self.text),
+            ("TWTGUI_REPLY_TO", self.reply_to.as_deref().unwrap_or("")),
+            ("TWTGUI_FEED_PATH", &self.feed_path),
+            ("TWTGUI_FEED_URL", &self.feed_url),
+            ("TWTGUI_NICK", &self.nick),
+        ]
+    }
+}
+
+/// Runs the script at `script` and waits for it to finish.
+///
+/// `name` identifies the hook in the logs and errors. Returns an error if the script
+/// can't be started, times out or exits with a non-zero status.
+pub async fn run_hook(
+    name: &str,
+    script: &str,
+    args: &[&str],
+    env: &HookEnv,
+) -> Result<(), String> {
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = Command::new("cmd");
+        command.args(["/C", script]);
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.arg(script);
+        command
+    };
+
+    command
+        .args(args)
+        .envs(env.vars())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    info!("Running {}: {}", name, script);
+
+    let child = command
+        .spawn()
+        .map_err(|e| format!("Failed to start {} {}: {}", name, script, e))?;
+
+    // Dropping the future on timeout kills the script
+    let output = tokio::time::timeout(SCRIPT_TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| {
+            format!(
+                "{} {} timed out after {}s",
+                name,
+                script,
+                SCRIPT_TIMEOUT.as_secs()
+            )
+        })?
+        .map_err(|e| e.to_string())?;
+
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        info!("[{}] {}", name, line);
+    }
+    for line in String::from_utf8_lossy(&output.stderr).lines() {
+        warn!("[{}] {}", name, line);
+    }
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} {} failed ({})", name, script, output.status))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::config::AppConfig;
+    use crate::twtxt::{compose_twtxt_tweet, format_timestamp};
+    use crate::utils::test_server::{block_on, temp_dir};
+    use std::path::{Path, PathBuf};
+
+    fn script(dir: &Path, name: &str, body: &str) -> String {
+        let path = dir.join(name);
+        std::fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn run(script: &str, args: &[&str], env: &HookEnv) -> Result<(), String> {
+        block_on(run_hook("test_script", script, args, env))
+    }
+
+    #[test]
+    fn exit_status_decides_success() {
+        let dir = temp_dir("hook-status");
+        let env = HookEnv::default();
+
+        assert!(run(&script(&dir, "ok.sh", "echo fine"), &[], &env).is_ok());
+
+        let failing = script(&dir, "fail.sh", "echo nope >&2\nexit 3");
+        let err = run(&failing, &[], &env).unwrap_err();
+        assert!(
+            err.starts_with(&format!("test_script {} failed", failing)),
+            "{}",
+            err
+        );
+        assert!(err.contains('3'), "{}", err);
+    }
+
+    #[test]
+    fn passes_arguments_and_environment() {
+        let dir = temp_dir("hook-env");
+        let out = dir.join("out.txt");
+        let hook = script(
+            &dir,
+            "env.sh",
+            &format!(
+                "{{ echo \"$1\"; env | grep ^TWTGUI_ | sort; }} > '{}'",
+                out.display()
+            ),
+        );
+        let env = HookEnv {
+            hash: "abcdefg".to_string(),
+            timestamp: "2024-01-01T00:00:00Z".to_string(),
+            text: "hello world".to_string(),
+            reply_to: None,
+            feed_path: "/feeds/twtxt.txt".to_string(),
+            feed_url: "https://example.com/twtxt.txt".to_string(),
+            nick: "me".to_string(),
+        };
+
+        run(&hook, &["the twt"], &env).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&out).unwrap(),
+            "\
+the twt
+TWTGUI_FEED_PATH=/feeds/twtxt.txt
+TWTGUI_FEED_URL=https://example.com/twtxt.txt
+TWTGUI_NICK=me
+TWTGUI_REPLY_TO=
+TWTGUI_TWT_HASH=abcdefg
+TWTGUI_TWT_TEXT=hello world
+TWTGUI_TWT_TIMESTAMP=2024-01-01T00:00:00Z
+"
+        );
+    }
+
+    #[test]
+    fn slow_scripts_are_killed() {
+        let dir = temp_dir("hook-timeout");
+        let marker = dir.join("finished");
+        let hook = script(
+            &dir,
+            "slow.sh",
+            &format!("sleep 2\ntouch '{}'", marker.display()),
+        );
+
+        let err = run(&hook, &[], &HookEnv::default()).unwrap_err();
+        assert!(err.ends_with("timed out after 1s"), "{}", err);
+
+        // Had the script kept running, it would have finished by now
+        std::thread::sleep(Duration::from_millis(2500));
+        assert!(!marker.exists());
+    }
+
+    fn posting_config(dir: &Path) -> (AppConfig, PathBuf) {
+        let feed = dir.join("twtxt.txt");
+        let mut config = AppConfig::default();
+        config.metadata.nick = Some("me".to_string());
+        config.metadata.urls = vec!["https://example.com/twtxt.txt".to_string()];
+        config.paths.twtxt = feed.to_string_lossy().into_owned();
+        (config, feed)
+    }
+
+    #[test]
+    fn failing_pre_tweet_script_stops_the_post() {
+        let dir = temp_dir("hook-pre-tweet");
+        let (mut config, feed) = posting_config(&dir);
+        config.paths.pre_tweet_script = Some(script(&dir, "pre.sh", "exit 1"));
+
+        let err = block_on(compose_twtxt_tweet("hello".to_string(), config.clone())).unwrap_err();
+        assert!(err.starts_with("pre_tweet_script"), "{}", err);
+        assert!(!feed.exists());
+
+        config.paths.pre_tweet_script = Some(script(&dir, "pre.sh", "exit 0"));
+        let tweet = block_on(compose_twtxt_tweet("hello".to_string(), config)).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&feed).unwrap(),
+            format!("{}\thello\n", format_timestamp(tweet.timestamp))
+        );
+    }
+}
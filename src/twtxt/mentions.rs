@@ -0,0 +1,198 @@
+//! Suggesting feeds to mention while writing a tweet.
+//!
+//! Candidates come from the user's follows, the authors of the loaded tweets and
+//! every feed in the parsed feed cache, so people who only show up in replies can be
+//! mentioned too. Picking one inserts the full `@<nick url>` mention.
+
+use std::collections::HashSet;
+
+use tracing::warn;
+
+use crate::{
+    twtxt::{Tweet, feed_id::FeedId, metadata::Link},
+    utils::{download::ParsedCache, paths::cache_root},
+};
+
+/// How many suggestions are shown at once.
+pub const MAX_SUGGESTIONS: usize = 6;
+
+/// Finds the mention being typed at `column` (a byte offset) of `line`.
+///
+/// Returns the byte offset of its `@` and what was typed after it, or `None` if the
+/// cursor isn't in a bare `@nick`. Finished `@<nick url>` mentions are left alone.
+pub fn mention_query(line: &str, column: usize) -> Option<(usize, &str)> {
+    let before = line.get(..column)?;
+    let at = before.rfind('@')?;
+    let query = &before[at + 1..];
+
+    let starts_word = before[..at]
+        .chars()
+        .next_back()
+        .is_none_or(|c| c.is_whitespace() || c == '(');
+    let is_bare = query
+        .chars()
+        .all(|c| !c.is_whitespace() && c != '<' && c != '>' && c != '@');
+
+    (starts_word && is_bare).then_some((at, query))
+}
+
+/// Formats a feed as a twtxt mention, followed by a space to keep typing after it.
+pub fn format_mention(feed: &Link) -> String {
+    format!("@<{} {}> ", feed.text, feed.url)
+}
+
+/// Returns the author of each tweet as a feed that can be mentioned.
+pub fn tweet_authors(tweets: &[Tweet]) -> Vec<Link> {
+    tweets
+        .iter()
+        .map(|t| Link {
+            text: t.author.clone(),
+            url: t.url.clone(),
+        })
+        .collect()
+}
+
+/// Returns the feeds matching `query`, in order of `sources`.
+///
+/// Feeds whose nick starts with the query come before ones that only contain it in
+/// their nick or URL. Each feed is only suggested once, even if it's in several sources.
+pub fn suggest<'a>(query: &str, sources: &[&'a [Link]]) -> Vec<&'a Link> {
+    let query = query.to_lowercase();
+    let mut seen = HashSet::new();
+    let mut prefixed = Vec::new();
+    let mut contained = Vec::new();
+
+    for feed in sources.iter().flat_map(|s| s.iter()) {
+        if feed.url.is_empty() || !seen.insert(FeedId::from_url(&feed.url)) {
+            continue;
+        }
+
+        let nick = feed.text.to_lowercase();
+        if nick.starts_with(&query) {
+            prefixed.push(feed);
+        } else if nick.contains(&query) || feed.url.to_lowercase().contains(&query) {
+            contained.push(feed);
+        }
+    }
+
+    prefixed
+        .into_iter()
+        .chain(contained)
+        .take(MAX_SUGGESTIONS)
+        .collect()
+}
+
+/// Reads the nick and URL of every feed in the parsed feed cache.
+pub async fn load_cached_feeds() -> Vec<Link> {
+    let result = tokio::task::spawn_blocking(|| {
+        let dir = cache_root()?;
+        let entries = std::fs::read_dir(&dir).map_err(|e| e.to_string())?;
+
+        Ok::<_, String>(
+            entries
+                .flatten()
+                .filter(|e| e.file_name().to_string_lossy().ends_with(".parsed.json"))
+                .filter_map(|e| {
+                    let contents = std::fs::read_to_string(e.path()).ok()?;
+                    let cache: ParsedCache = serde_json::from_str(&contents).ok()?;
+                    cached_feed(&cache)
+                })
+                .collect(),
+        )
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|result| result);
+
+    result.unwrap_or_else(|e| {
+        warn!("Failed to read the feed cache for mentions: {}", e);
+        Vec::new()
+    })
+}
+
+/// The nick and URL a cached feed is mentioned by.
+fn cached_feed(cache: &ParsedCache) -> Option<Link> {
+    let metadata = cache.bundle.metadata.as_ref();
+    let first = cache.bundle.tweets.first();
+
+    let nick = metadata
+        .and_then(|m| m.nick.clone())
+        .or_else(|| first.map(|t| t.author.clone()))?;
+    let url = metadata
+        .and_then(|m| m.urls.first().cloned())
+        .or_else(|| first.map(|t| t.url.clone()))?;
+
+    Some(Link { text: nick, url })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(nick: &str, url: &str) -> Link {
+        Link {
+            text: nick.to_string(),
+            url: url.to_string(),
+        }
+    }
+
+    #[test]
+    fn finds_the_mention_being_typed() {
+        assert_eq!(mention_query("hi @al", 6), Some((3, "al")));
+        assert_eq!(mention_query("@", 1), Some((0, "")));
+        assert_eq!(mention_query("(#abc) (@bo there", 11), Some((8, "bo")));
+        // Only what's before the cursor counts
+        assert_eq!(mention_query("hi @alice", 6), Some((3, "al")));
+    }
+
+    #[test]
+    fn ignores_anything_but_a_bare_mention() {
+        assert_eq!(mention_query("mail me@example.com", 19), None);
+        assert_eq!(mention_query("@alice done", 11), None);
+        assert_eq!(
+            mention_query("@<alice https://example.com/twtxt.txt>", 38),
+            None
+        );
+        assert_eq!(mention_query("no mention", 10), None);
+        // Past the end of the line, or not on a character boundary
+        assert_eq!(mention_query("@al", 10), None);
+        assert_eq!(mention_query("@é", 2), None);
+    }
+
+    #[test]
+    fn suggests_prefix_matches_first_without_duplicates() {
+        let follows = [
+            link("bob", "https://example.com/bob.txt"),
+            link("alice", "https://example.com/alice.txt"),
+        ];
+        let authors = [
+            link("Alina", "https://example.org/alina.txt"),
+            link("alice", "https://EXAMPLE.com/alice.txt"),
+            link("malice", "https://example.net/twtxt.txt"),
+            link("nobody", ""),
+        ];
+
+        let nicks = |query| {
+            suggest(query, &[&follows, &authors])
+                .into_iter()
+                .map(|l| l.text.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(nicks("ali"), ["alice", "Alina", "malice"]);
+        assert_eq!(nicks("example.org"), ["Alina"]);
+        assert_eq!(nicks("nobody"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn limits_the_number_of_suggestions() {
+        let feeds: Vec<Link> = (0..MAX_SUGGESTIONS + 2)
+            .map(|i| {
+                link(
+                    &format!("user{}", i),
+                    &format!("https://example.com/{}.txt", i),
+                )
+            })
+            .collect();
+        assert_eq!(suggest("user", &[&feeds]).len(), MAX_SUGGESTIONS);
+    }
+}
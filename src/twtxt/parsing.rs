@@ -5,10 +5,11 @@ use std::sync::OnceLock;
 use crate::{
     twtxt::{
         Tweet,
+        feed_id::FeedId,
         metadata::{Link, Metadata},
         twt_hash::compute_twt_hash,
     },
-    utils::{hash::hash_sha256_str, is_image_url},
+    utils::is_image_url,
 };
 use chrono::{DateTime, Utc};
 use iced::widget::markdown;
@@ -204,7 +205,7 @@ pub fn parse_metadata(input: &str) -> Option<Metadata> {
 /// canonical feed URL used for hash computation.
 pub fn parse_tweets(author: &str, url: &str, hash_url: Option<&str>, input: &str) -> Vec<Tweet> {
     let author_name = author.to_string();
-    let feed_hash = hash_sha256_str(input);
+    let feed_id = FeedId::from_url(hash_url.unwrap_or(url));
 
     input
         .lines()
@@ -221,7 +222,7 @@ pub fn parse_tweets(author: &str, url: &str, hash_url: Option<&str>, input: &str
                 timestamp: DateTime::parse_from_rfc3339(timestamp_str)
                     .ok()?
                     .with_timezone(&Utc),
-                feed_hash: feed_hash.clone(),
+                feed_id: feed_id.clone(),
                 author: author_name.clone(),
                 url: url.to_string(),
                 content: display_content,
@@ -0,0 +1,178 @@
+//! Starting replies the way the [Twt Subject](https://twtxt.dev/exts/twt-subject.html)
+//! extension describes.
+//!
+//! A reply carries the subject of the twt it answers, so every reply in a thread points
+//! at the twt that started it, and mentions everyone else taking part in the thread by
+//! the nick their feed declares for itself.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::twtxt::{Tweet, TweetNode, feed_id::FeedId, mentions::format_mention, metadata::Link};
+
+/// Returns the hash a reply to `tweet` uses as its subject: the tweet's own subject if
+/// it's a reply itself, or its hash if it starts a thread.
+pub fn subject_of(tweet: &Tweet) -> &str {
+    tweet.reply_to.as_deref().unwrap_or(&tweet.hash)
+}
+
+/// Returns the indices of every tweet in the same thread as `tweets[index]`.
+///
+/// That's the tree the tweet is in, as well as any tweet sharing its subject, which
+/// covers replies to a thread whose first tweet isn't loaded.
+pub fn thread_members(tweets: &[Tweet], thread_tree: &[TweetNode], index: usize) -> Vec<usize> {
+    let mut members = Vec::new();
+    if let Some(root) = thread_tree.iter().find(|node| contains(node, index)) {
+        collect(root, &mut members);
+    }
+
+    let subject = subject_of(&tweets[index]);
+    for (i, tweet) in tweets.iter().enumerate() {
+        if (tweet.hash == subject || tweet.reply_to.as_deref() == Some(subject))
+            && !members.contains(&i)
+        {
+            members.push(i);
+        }
+    }
+
+    members
+}
+
+/// Builds the start of a reply to `tweets[index]`: the thread's subject, followed by a
+/// mention of every other participant in `thread`.
+///
+/// The author being replied to is mentioned first, then everyone else from the oldest
+/// tweet on. Each feed is only mentioned once, and the user's own feed isn't mentioned.
+/// `nicks` holds the nicks feeds declare in their metadata, which are used over the
+/// names the user follows them by.
+pub fn reply_prefix(
+    tweets: &[Tweet],
+    index: usize,
+    thread: &[usize],
+    own_feed: Option<&FeedId>,
+    nicks: &HashMap<FeedId, String>,
+) -> String {
+    let tweet = &tweets[index];
+
+    let mut others: Vec<&Tweet> = thread
+        .iter()
+        .filter(|&&i| i != index)
+        .filter_map(|&i| tweets.get(i))
+        .collect();
+    others.sort_by_key(|t| t.timestamp);
+
+    let mut seen: HashSet<&FeedId> = own_feed.into_iter().collect();
+    let mut prefix = format!("(#{}) ", subject_of(tweet));
+    for participant in std::iter::once(tweet).chain(others) {
+        if !seen.insert(&participant.feed_id) {
+            continue;
+        }
+
+        let nick = nicks
+            .get(&participant.feed_id)
+            .unwrap_or(&participant.author);
+        prefix.push_str(&format_mention(&Link {
+            text: nick.clone(),
+            url: participant.url.clone(),
+        }));
+    }
+
+    prefix
+}
+
+fn contains(node: &TweetNode, index: usize) -> bool {
+    node.index == index || node.children.iter().any(|child| contains(child, index))
+}
+
+fn collect(node: &TweetNode, indices: &mut Vec<usize>) {
+    indices.push(node.index);
+    for child in &node.children {
+        collect(child, indices);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn tweet(hash: &str, reply_to: Option<&str>, author: &str, day: u32) -> Tweet {
+        let url = format!("https://example.com/{}.txt", author);
+        Tweet {
+            hash: hash.to_string(),
+            reply_to: reply_to.map(str::to_string),
+            author: author.to_string(),
+            timestamp: Utc.with_ymd_and_hms(2024, 1, day, 0, 0, 0).unwrap(),
+            feed_id: FeedId::from_url(&url),
+            url,
+            content: String::new(),
+            md_items: Vec::new(),
+        }
+    }
+
+    fn node(index: usize, children: Vec<TweetNode>) -> TweetNode {
+        TweetNode { index, children }
+    }
+
+    #[test]
+    fn subject_is_the_threads_first_twt() {
+        assert_eq!(subject_of(&tweet("aaaaaaa", None, "alice", 1)), "aaaaaaa");
+        assert_eq!(
+            subject_of(&tweet("bbbbbbb", Some("aaaaaaa"), "bob", 2)),
+            "aaaaaaa"
+        );
+    }
+
+    #[test]
+    fn thread_members_include_replies_outside_the_tree() {
+        let tweets = [
+            tweet("aaaaaaa", None, "alice", 1),
+            tweet("bbbbbbb", Some("aaaaaaa"), "bob", 2),
+            tweet("ccccccc", None, "carol", 3),
+            // Its parent isn't loaded, so it's not under the root in the tree
+            tweet("ddddddd", Some("aaaaaaa"), "dave", 4),
+        ];
+        let tree = [
+            node(0, vec![node(1, vec![])]),
+            node(2, vec![]),
+            node(3, vec![]),
+        ];
+
+        let mut members = thread_members(&tweets, &tree, 1);
+        members.sort();
+        assert_eq!(members, [0, 1, 3]);
+
+        assert_eq!(thread_members(&tweets, &tree, 2), [2]);
+    }
+
+    #[test]
+    fn reply_prefix_mentions_each_participant_once() {
+        let tweets = [
+            tweet("aaaaaaa", None, "alice", 1),
+            tweet("bbbbbbb", Some("aaaaaaa"), "bob", 2),
+            tweet("ccccccc", Some("aaaaaaa"), "me", 3),
+            tweet("ddddddd", Some("aaaaaaa"), "alice", 4),
+            tweet("eeeeeee", Some("aaaaaaa"), "carol", 5),
+        ];
+        let own = FeedId::from_url("https://example.com/me.txt");
+        let nicks = HashMap::from([(
+            FeedId::from_url("https://example.com/bob.txt"),
+            "bobby".to_string(),
+        )]);
+
+        // The author replied to comes first, then everyone else oldest first
+        assert_eq!(
+            reply_prefix(&tweets, 4, &[0, 1, 2, 3, 4], Some(&own), &nicks),
+            "(#aaaaaaa) @<carol https://example.com/carol.txt> \
+             @<alice https://example.com/alice.txt> @<bobby https://example.com/bob.txt> "
+        );
+    }
+
+    #[test]
+    fn reply_prefix_for_a_new_thread() {
+        let tweets = [tweet("aaaaaaa", None, "alice", 1)];
+        assert_eq!(
+            reply_prefix(&tweets, 0, &[0], None, &HashMap::new()),
+            "(#aaaaaaa) @<alice https://example.com/alice.txt> "
+        );
+    }
+}
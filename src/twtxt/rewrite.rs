@@ -0,0 +1,166 @@
+//! Rewriting lines of the user's own `twtxt.txt`, used to edit and delete posted tweets.
+//!
+//! The feed is never modified in place, see [`write_atomically`]. The previous version
+//! is backed up to the app's data directory, where it isn't published along with the
+//! feed.
+
+use std::path::Path;
+
+use crate::{
+    twtxt::twt_hash::compute_twt_hash,
+    utils::{paths::get_backup_path, write_atomically},
+};
+
+/// A tweet found in the local feed.
+#[derive(Debug, Clone)]
+pub struct FoundTwt {
+    /// The timestamp exactly as written in the feed.
+    pub timestamp: String,
+    /// The text exactly as written in the feed, with newlines escaped as `\u2028`.
+    pub text: String,
+}
+
+impl FoundTwt {
+    /// The text with escaped newlines turned back into real ones, for editing.
+    pub fn editable_text(&self) -> String {
+        self.text.replace("\\u2028", "\n")
+    }
+}
+
+/// Finds the tweet with `hash` in the contents of a feed published at `url`.
+///
+/// Returns the index of its line along with the tweet.
+pub fn find_twt(content: &str, url: &str, hash: &str) -> Option<(usize, FoundTwt)> {
+    content.lines().enumerate().find_map(|(i, line)| {
+        if line.starts_with('#') {
+            return None;
+        }
+        let (timestamp, text) = line.split_once('\t')?;
+        let text = text.trim();
+
+        // Lines with malformed timestamps can't be hashed, and aren't shown anyway
+        timestamp.parse::<chrono::DateTime<chrono::Utc>>().ok()?;
+
+        (compute_twt_hash(url, timestamp, text) == hash).then(|| {
+            (
+                i,
+                FoundTwt {
+                    timestamp: timestamp.to_string(),
+                    text: text.to_string(),
+                },
+            )
+        })
+    })
+}
+
+/// Reads the tweet with `hash` from the feed at `path`.
+pub fn read_twt(path: &str, url: &str, hash: &str) -> Result<FoundTwt, String> {
+    let content =
+        std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    find_twt(&content, url, hash)
+        .map(|(_, twt)| twt)
+        .ok_or_else(|| format!("Couldn't find #{} in {}", hash, path))
+}
+
+/// Replaces the text of the tweet with `hash`, or removes its line if `new_text` is
+/// `None`. The timestamp is kept, so an edited tweet stays in place.
+///
+/// `new_text` must already be escaped for writing to the feed. Returns the tweet as it
+/// was before the change.
+pub fn rewrite_twt(
+    path: &str,
+    url: &str,
+    hash: &str,
+    new_text: Option<&str>,
+) -> Result<FoundTwt, String> {
+    let content =
+        std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let (line_index, found) = find_twt(&content, url, hash)
+        .ok_or_else(|| format!("Couldn't find #{} in {}", hash, path))?;
+
+    let mut rewritten = String::with_capacity(content.len());
+    for (i, line) in content.split_inclusive('\n').enumerate() {
+        if i != line_index {
+            rewritten.push_str(line);
+            continue;
+        }
+        if let Some(text) = new_text {
+            let ending = if line.ends_with("\r\n") {
+                "\r\n"
+            } else if line.ends_with('\n') {
+                "\n"
+            } else {
+                ""
+            };
+            rewritten.push_str(&format!("{}\t{}{}", found.timestamp, text, ending));
+        }
+    }
+
+    replace_file(Path::new(path), &rewritten)?;
+    Ok(found)
+}
+
+/// Replaces the contents of a file with [`write_atomically`].
+///
+/// The previous contents are backed up outside the folder the file is in, since that
+/// folder is usually published and a deleted tweet shouldn't stay online.
+pub fn replace_file(path: &Path, contents: &str) -> Result<(), String> {
+    if path.exists() {
+        let backup = get_backup_path(path)?;
+        std::fs::copy(path, &backup)
+            .map_err(|e| format!("Failed to back up {}: {}", path.display(), e))?;
+    }
+    write_atomically(path, contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_server::temp_dir;
+
+    const URL: &str = "https://example.com/twtxt.txt";
+    const FEED: &str = "\
+# nick = me
+# url = https://example.com/twtxt.txt
+2024-01-01T00:00:00Z\tfirst
+not a twt\tat all
+2024-01-02T00:00:00Z\tline one\\u2028line two  \r
+";
+
+    fn feed_file(name: &str) -> String {
+        let path = temp_dir(name).join("twtxt.txt");
+        std::fs::write(&path, FEED).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn reads_a_twt_by_hash() {
+        let path = feed_file("read-twt");
+        let hash = compute_twt_hash(URL, "2024-01-02T00:00:00Z", "line one\\u2028line two");
+
+        let twt = read_twt(&path, URL, &hash).unwrap();
+        assert_eq!(twt.timestamp, "2024-01-02T00:00:00Z");
+        assert_eq!(twt.text, "line one\\u2028line two");
+        assert_eq!(twt.editable_text(), "line one\nline two");
+
+        assert_eq!(find_twt(FEED, URL, &hash).map(|(i, _)| i), Some(4));
+    }
+
+    #[test]
+    fn read_twt_errors() {
+        let path = feed_file("read-twt-errors");
+        let hash = compute_twt_hash(URL, "2024-01-01T00:00:00Z", "first");
+
+        // The hash depends on the URL the feed is published at
+        let err = read_twt(&path, "https://example.org/twtxt.txt", &hash).unwrap_err();
+        assert!(
+            err.starts_with(&format!("Couldn't find #{}", hash)),
+            "{}",
+            err
+        );
+
+        let missing = format!("{}.missing", path);
+        let err = read_twt(&missing, URL, &hash).unwrap_err();
+        assert!(err.starts_with("Failed to read"), "{}", err);
+    }
+}
@@ -0,0 +1,272 @@
+//! Tweets queued to be posted at a later time.
+//!
+//! The queue is saved to the data directory, so posts that came due while the app was
+//! closed are posted as soon as it starts again. A queued post is stored as the raw
+//! composer text and only turned into a tweet when it's posted, so its timestamp and
+//! hash reflect when it was actually published.
+
+use std::path::PathBuf;
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+use crate::utils::{paths::get_schedule_path, write_atomically};
+
+/// The format scheduled times are entered and shown in, in local time.
+pub const TIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+/// A tweet waiting to be posted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledPost {
+    pub id: u64,
+    /// The raw composer text, including any subject and mentions.
+    pub text: String,
+    pub publish_at: DateTime<Utc>,
+    /// The feed path of the identity to post as. Posts queued before identities were
+    /// recorded don't have one, and are posted as the active identity.
+    #[serde(default)]
+    pub identity: Option<String>,
+    /// Why the last attempt to post failed. Failed posts aren't retried automatically.
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// The queue of scheduled posts, kept in sync with the schedule file.
+#[derive(Debug, Default)]
+pub struct Schedule {
+    /// Where the queue is saved, or `None` if the data directory couldn't be found.
+    path: Option<PathBuf>,
+    posts: Vec<ScheduledPost>,
+}
+
+impl Schedule {
+    /// Loads the queue, starting with an empty one if the file is missing or unreadable.
+    pub fn load() -> Self {
+        match get_schedule_path() {
+            Ok(path) => Self::load_from(path),
+            Err(e) => {
+                error!("Failed to locate scheduled posts: {}", e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Loads the queue saved at `path`.
+    fn load_from(path: PathBuf) -> Self {
+        let posts = match std::fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                warn!(
+                    "Failed to parse scheduled posts at {}: {}",
+                    path.display(),
+                    e
+                );
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+
+        Self {
+            path: Some(path),
+            posts,
+        }
+    }
+
+    /// All queued posts, soonest first.
+    pub fn list(&self) -> &[ScheduledPost] {
+        &self.posts
+    }
+
+    pub fn get(&self, id: u64) -> Option<&ScheduledPost> {
+        self.posts.iter().find(|p| p.id == id)
+    }
+
+    /// The earliest post that is due at `now` and hasn't failed, other than `skip`.
+    pub fn next_due(&self, now: DateTime<Utc>, skip: Option<u64>) -> Option<&ScheduledPost> {
+        self.posts
+            .iter()
+            .find(|p| p.publish_at <= now && p.error.is_none() && Some(p.id) != skip)
+    }
+
+    /// Queues a new post, to be posted as the identity whose feed is at `identity`,
+    /// and returns its id.
+    pub fn add(&mut self, text: String, publish_at: DateTime<Utc>, identity: String) -> u64 {
+        let id = self.posts.iter().map(|p| p.id).max().unwrap_or(0) + 1;
+        self.posts.push(ScheduledPost {
+            id,
+            text,
+            publish_at,
+            identity: Some(identity),
+            error: None,
+        });
+        self.save();
+        id
+    }
+
+    /// Replaces the text and time of a queued post, clearing any earlier failure.
+    pub fn update(&mut self, id: u64, text: String, publish_at: DateTime<Utc>) {
+        if let Some(post) = self.posts.iter_mut().find(|p| p.id == id) {
+            post.text = text;
+            post.publish_at = publish_at;
+            post.error = None;
+            self.save();
+        }
+    }
+
+    /// Records that posting failed, so it isn't retried until the user edits it.
+    pub fn set_error(&mut self, id: u64, error: String) {
+        if let Some(post) = self.posts.iter_mut().find(|p| p.id == id) {
+            post.error = Some(error);
+            self.save();
+        }
+    }
+
+    pub fn remove(&mut self, id: u64) {
+        let len = self.posts.len();
+        self.posts.retain(|p| p.id != id);
+        if self.posts.len() != len {
+            self.save();
+        }
+    }
+
+    /// Sorts the queue and saves it.
+    fn save(&mut self) {
+        self.posts.sort_by_key(|p| (p.publish_at, p.id));
+
+        let Some(path) = &self.path else {
+            return;
+        };
+        let result = serde_json::to_string_pretty(&self.posts)
+            .map_err(|e| e.to_string())
+            .and_then(|json| write_atomically(path, json));
+
+        if let Err(e) = result {
+            error!("Failed to save scheduled posts: {}", e);
+        }
+    }
+}
+
+/// Parses a local time in [`TIME_FORMAT`].
+pub fn parse_local_time(input: &str) -> Result<DateTime<Utc>, String> {
+    let naive = NaiveDateTime::parse_from_str(input.trim(), TIME_FORMAT)
+        .map_err(|_| format!("Enter a time like {}", Local::now().format(TIME_FORMAT)))?;
+
+    // Times skipped by a DST change don't exist, and repeated ones use the first of the two
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|t| t.with_timezone(&Utc))
+        .ok_or_else(|| format!("{} doesn't exist in the local time zone", input.trim()))
+}
+
+/// Formats a time as local time in [`TIME_FORMAT`].
+pub fn format_local_time(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local).format(TIME_FORMAT).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_server::temp_dir;
+    use chrono::Duration;
+
+    fn schedule(name: &str) -> (Schedule, PathBuf) {
+        let path = temp_dir(name).join("scheduled.json");
+        (Schedule::load_from(path.clone()), path)
+    }
+
+    fn at(minutes: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap() + Duration::minutes(minutes)
+    }
+
+    #[test]
+    fn keeps_posts_in_order_of_time() {
+        let (mut schedule, path) = schedule("schedule-order");
+        let late = schedule.add("late".to_string(), at(30), "/feeds/me.txt".to_string());
+        let early = schedule.add("early".to_string(), at(10), "/feeds/me.txt".to_string());
+        let tie = schedule.add("tie".to_string(), at(10), "/feeds/other.txt".to_string());
+        assert_eq!((late, early, tie), (1, 2, 3));
+
+        let texts = |s: &Schedule| s.list().iter().map(|p| p.text.clone()).collect::<Vec<_>>();
+        assert_eq!(texts(&schedule), ["early", "tie", "late"]);
+
+        schedule.update(early, "moved".to_string(), at(40));
+        assert_eq!(texts(&schedule), ["tie", "late", "moved"]);
+
+        // Saved in the same order, along with who to post as
+        let loaded = Schedule::load_from(path);
+        assert_eq!(texts(&loaded), ["tie", "late", "moved"]);
+        assert_eq!(
+            loaded.get(tie).unwrap().identity.as_deref(),
+            Some("/feeds/other.txt")
+        );
+
+        // Ids aren't reused while later ones are queued
+        schedule.remove(early);
+        assert_eq!(schedule.add("new".to_string(), at(0), String::new()), 4);
+    }
+
+    #[test]
+    fn next_due_skips_failed_and_skipped_posts() {
+        let (mut schedule, _) = schedule("schedule-due");
+        let first = schedule.add("first".to_string(), at(0), String::new());
+        let second = schedule.add("second".to_string(), at(5), String::new());
+        let third = schedule.add("third".to_string(), at(10), String::new());
+
+        assert_eq!(schedule.next_due(at(-1), None).map(|p| p.id), None);
+        assert_eq!(schedule.next_due(at(7), None).map(|p| p.id), Some(first));
+        assert_eq!(
+            schedule.next_due(at(7), Some(first)).map(|p| p.id),
+            Some(second)
+        );
+        assert_eq!(
+            schedule.next_due(at(7), Some(second)).map(|p| p.id),
+            Some(first)
+        );
+
+        schedule.set_error(first, "offline".to_string());
+        assert_eq!(schedule.next_due(at(7), None).map(|p| p.id), Some(second));
+        assert_eq!(schedule.next_due(at(7), Some(second)).map(|p| p.id), None);
+        assert_eq!(
+            schedule.next_due(at(10), Some(second)).map(|p| p.id),
+            Some(third)
+        );
+
+        // Editing a failed post clears its error, so it's tried again
+        schedule.update(first, "first".to_string(), at(0));
+        assert_eq!(schedule.get(first).unwrap().error, None);
+        assert_eq!(schedule.next_due(at(7), None).map(|p| p.id), Some(first));
+    }
+
+    #[test]
+    fn loads_posts_queued_without_an_identity() {
+        let (_, path) = schedule("schedule-old");
+        std::fs::write(
+            &path,
+            r#"[{"id": 1, "text": "hi", "publish_at": "2024-06-01T12:00:00Z", "error": "offline"}]"#,
+        )
+        .unwrap();
+
+        let schedule = Schedule::load_from(path);
+        let post = schedule.get(1).unwrap();
+        assert_eq!(post.identity, None);
+        assert_eq!(post.error.as_deref(), Some("offline"));
+    }
+
+    #[test]
+    fn unreadable_queues_start_empty() {
+        let (_, path) = schedule("schedule-corrupt");
+        std::fs::write(&path, "not json").unwrap();
+        assert!(Schedule::load_from(path).list().is_empty());
+    }
+
+    #[test]
+    fn parses_local_times() {
+        let time = parse_local_time(" 2024-06-01 12:30 ").unwrap();
+        assert_eq!(format_local_time(time), "2024-06-01 12:30");
+
+        assert!(parse_local_time("2024-06-01").is_err());
+        assert!(parse_local_time("2024-13-01 12:30").is_err());
+        assert!(parse_local_time("tomorrow").is_err());
+    }
+}
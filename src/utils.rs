@@ -2,11 +2,21 @@
 
 pub mod download;
 pub mod hash;
+pub mod identicon;
+pub mod lru;
+pub mod media;
 pub mod paths;
+pub mod privacy;
 pub mod styling;
+#[cfg(test)]
+pub mod test_server;
+
+use std::path::Path;
 
 use reqwest::Url;
 
+use crate::utils::download::local_feed_path;
+
 const IMAGE_EXTENSIONS: &[&str] = &[
     "png", "jpg", "jpeg", "gif", "webp", "svg", "bmp", "ico", "tiff",
 ];
@@ -27,6 +37,29 @@ const SKIP_EXTENSIONS: &[&[&str]] = &[
     TEXT_EXTENSIONS,
 ];
 
+/// Replaces the contents of the file at `path` by writing them to a temporary file
+/// next to it and renaming that over it, so a crash mid-write can't leave the file
+/// truncated. The file keeps its permissions.
+pub fn write_atomically(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), String> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| format!("{} is not a file", path.display()))?
+        .to_string_lossy();
+    let tmp = path.with_file_name(format!(".{}.tmp", file_name));
+
+    std::fs::write(&tmp, contents)
+        .map_err(|e| format!("Failed to write {}: {}", tmp.display(), e))?;
+
+    // Feeds are usually served by a web server, which still has to be able to read them
+    if let Ok(meta) = std::fs::metadata(path)
+        && let Err(e) = std::fs::set_permissions(&tmp, meta.permissions())
+    {
+        tracing::warn!("Failed to copy permissions to {}: {}", tmp.display(), e);
+    }
+
+    std::fs::rename(&tmp, path).map_err(|e| format!("Failed to replace {}: {}", path.display(), e))
+}
+
 pub fn is_file_url(url: &str) -> bool {
     let Ok(parsed) = url.parse::<Url>() else {
         return false;
@@ -52,3 +85,22 @@ pub fn is_image_url(url: &str) -> bool {
         .to_lowercase();
     IMAGE_EXTENSIONS.contains(&ext.as_str())
 }
+
+/// Resolves a possibly relative link found in a feed (such as a `# prev` archive)
+/// against the URL of that feed.
+///
+/// Links in local feeds are resolved relative to the directory the feed is in.
+pub fn resolve_feed_link(base: &str, link: &str) -> String {
+    if !base.contains("://")
+        && !link.contains("://")
+        && let Ok(Some(path)) = local_feed_path(base)
+    {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        return dir.join(link).to_string_lossy().into_owned();
+    }
+
+    match Url::parse(base).and_then(|base| base.join(link)) {
+        Ok(joined) => joined.to_string(),
+        Err(_) => link.to_string(),
+    }
+}
@@ -1,22 +1,52 @@
 //! Module for downloading and caching files/twtxt feeds, with support for caching.
+//!
+//! HTTP(S) is handled with `reqwest`, while `gemini://` and `gopher://` URLs are
+//! dispatched to the clients in the [`gemini`] and [`gopher`] modules. `file://` URLs
+//! and plain filesystem paths are read straight from disk. OpenGraph metadata for link
+//! previews has its own cache in the [`og`] module.
 
+pub mod gemini;
+pub mod gopher;
+mod og;
+
+use crate::config::secrets::{Credential, credential_for_request};
 use crate::twtxt::FeedBundle;
+use crate::twtxt::feed_id::FeedId;
 use bytes::Bytes;
-use opengraph::{self, Object};
-use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use chrono::{DateTime, Utc};
+use directories::BaseDirs;
+use opengraph::Object;
+use reqwest::{
+    Method,
+    header::{
+        CONTENT_RANGE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, LAST_MODIFIED, RANGE,
+    },
+};
 use serde::{Deserialize, Serialize};
 
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
+use crate::utils::media::{MAX_DOWNLOAD_BYTES, MediaError, check_download_size};
 use crate::utils::paths::{get_bin_cache_paths, get_txt_cache_path};
 
+/// How many bytes of the cached content are re-requested when fetching only the tail
+/// of a feed, so we can check that the server's copy still starts with what we have.
+const RANGE_OVERLAP: usize = 1024;
+
+/// The largest feed we're willing to download. Even busy feeds are a few megabytes at
+/// most, so anything larger is most likely a broken or hostile server.
+pub const MAX_FEED_BYTES: u64 = 32 * 1024 * 1024;
+
 static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("BUILD_VERSION"));
 
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::sync::OnceLock;
 
 static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
 
-fn get_client() -> reqwest::Client {
+/// Returns the HTTP client shared by the whole application.
+pub fn get_client() -> reqwest::Client {
     CLIENT
         .get_or_init(|| {
             reqwest::Client::builder()
@@ -33,6 +63,8 @@ fn get_client() -> reqwest::Client {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ParsedCache {
     pub content_hash: String,
+    /// The identifier of the feed the tweets belong to.
+    pub feed_id: FeedId,
     pub bundle: FeedBundle,
 }
 
@@ -48,24 +80,41 @@ struct CacheEntry {
     metadata: CacheMetadata,
 }
 
+/// Text content of a downloaded feed.
+#[derive(Debug, Clone)]
+pub struct FeedText {
+    /// The full content of the feed.
+    pub content: String,
+    /// The byte offset in `content` where newly appended data starts.
+    ///
+    /// This is only set when the feed was fetched with a `Range` request, in which case
+    /// everything before the offset is identical to the previously cached content.
+    pub appended_at: Option<usize>,
+}
+
 /// Downloads a binary file and caches it on disk using HTTP caching headers.
 ///
 /// This can be used to download anything, however it's primarily intended for images
 /// (avatars and inline media) used in tweets.
 ///
-/// Returns the cached bytes if the server responds with `304 Not Modified`.
-pub async fn download_binary(url: String) -> Result<Bytes, String> {
+/// Returns the cached bytes if the server responds with `304 Not Modified`. Files
+/// larger than [`MAX_DOWNLOAD_BYTES`](crate::utils::media::MAX_DOWNLOAD_BYTES) are rejected, without downloading them in full.
+pub async fn download_binary(url: String) -> Result<Bytes, MediaError> {
     let client = get_client();
 
     debug!("Downloading file from {}", url);
 
+    if let Some(result) = fetch_other_protocol(&url, MAX_DOWNLOAD_BYTES).await {
+        return Ok(result?);
+    }
+
     let (data_path, meta_path) = get_bin_cache_paths(&url)?;
 
     let metadata: Option<CacheMetadata> = std::fs::read_to_string(&meta_path)
         .ok()
         .and_then(|s| serde_json::from_str(&s).ok());
 
-    let mut request = client.get(&url);
+    let mut request = authorized_get(&client, &url);
     if let Some(ref meta) = metadata {
         if let Some(ref etag) = meta.etag {
             request = request.header(IF_NONE_MATCH, etag);
@@ -86,9 +135,15 @@ pub async fn download_binary(url: String) -> Result<Bytes, String> {
             meta_path.display()
         );
         let raw_bytes = std::fs::read(&data_path).map_err(|e| e.to_string())?;
+        check_download_size(raw_bytes.len() as u64)?;
         return Ok(Bytes::from(raw_bytes));
     }
 
+    // Refuse early if the server tells us the file is too large
+    if let Some(len) = response.content_length() {
+        check_download_size(len)?;
+    }
+
     // 200 OK
     let etag = response
         .headers()
@@ -102,7 +157,7 @@ pub async fn download_binary(url: String) -> Result<Bytes, String> {
         .and_then(|v| v.to_str().ok())
         .map(|s| s.to_string());
 
-    let data = response.bytes().await.map_err(|e| e.to_string())?;
+    let data = read_capped_body(response).await?;
 
     let meta_json = serde_json::to_string(&CacheMetadata {
         etag,
@@ -133,21 +188,20 @@ pub async fn download_text(url: String) -> Result<String, String> {
 
     debug!("Downloading text from {}", url);
 
-    let cache_path = get_txt_cache_path(&url)?;
+    if let Some(result) = fetch_other_protocol(&url, MAX_FEED_BYTES).await {
+        return result.map(|data| String::from_utf8_lossy(&data).into_owned());
+    }
+
+    let cache_path = get_txt_cache_path(&FeedId::from_url(&url))?;
 
     let cached_data: Option<CacheEntry> = std::fs::read_to_string(&cache_path)
         .ok()
         .and_then(|content| serde_json::from_str(&content).ok());
 
-    let mut request = client.get(&url);
-    if let Some(ref entry) = cached_data {
-        if let Some(ref etag) = entry.metadata.etag {
-            request = request.header(IF_NONE_MATCH, etag);
-        }
-        if let Some(ref last_mod) = entry.metadata.last_modified {
-            request = request.header(IF_MODIFIED_SINCE, last_mod);
-        }
-    }
+    let request = with_cache_headers(
+        authorized_get(&client, &url),
+        cached_data.as_ref().map(|e| &e.metadata),
+    );
 
     let response = request.send().await.map_err(|e| e.to_string())?;
 
@@ -160,6 +214,395 @@ pub async fn download_text(url: String) -> Result<String, String> {
     }
 
     // 200 OK
+    let status = response.status();
+    let metadata = response_cache_metadata(&response);
+    let content = read_capped_text(response).await?;
+
+    write_text_cache(status, &url, &cache_path, content.clone(), metadata)?;
+
+    Ok(content)
+}
+
+/// Downloads a twtxt feed as plain text and caches it locally.
+///
+/// twtxt feeds are almost always append-only, so when a cached copy exists this first
+/// asks the server for only the bytes past the end of it with a `Range` request. The
+/// response has to start with the last `RANGE_OVERLAP` bytes we already have, otherwise
+/// the feed was rewritten and this falls back to a full download with [`download_text`].
+///
+/// Local feeds (see [`local_feed_path`]) are read from disk instead.
+pub async fn download_feed_text(url: String) -> Result<FeedText, String> {
+    if let Some(path) = local_feed_path(&url)? {
+        return read_local_feed(&url, &path);
+    }
+
+    let cache_path = get_txt_cache_path(&FeedId::from_url(&url))?;
+
+    let cached_data: Option<CacheEntry> = std::fs::read_to_string(&cache_path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok());
+
+    // Only try a range request when the cached copy ends on a complete line, so that
+    // anything appended is guaranteed to start with a new line.
+    if let Some(entry) = cached_data
+        && entry.content.len() > RANGE_OVERLAP
+        && entry.content.ends_with('\n')
+    {
+        match download_text_range(&url, &cache_path, entry).await {
+            Ok(Some(text)) => return Ok(text),
+            Ok(None) => {}
+            Err(e) => warn!("Range request failed for {}: {}", url, e),
+        }
+    }
+
+    let content = download_text(url).await?;
+    Ok(FeedText {
+        content,
+        appended_at: None,
+    })
+}
+
+/// Reads a feed from the local filesystem.
+///
+/// Changes are detected using the file's modification time and size instead of HTTP
+/// caching headers, so an unchanged file is not read again.
+fn read_local_feed(url: &str, path: &Path) -> Result<FeedText, String> {
+    debug!("Reading feed from {}", path.display());
+
+    let cache_path = get_txt_cache_path(&FeedId::from_url(url))?;
+
+    let file_meta = std::fs::metadata(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let modified = file_meta
+        .modified()
+        .map(|time| DateTime::<Utc>::from(time).to_rfc3339())
+        .ok();
+
+    let cached_data: Option<CacheEntry> = std::fs::read_to_string(&cache_path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok());
+
+    if let Some(entry) = &cached_data
+        && modified.is_some()
+        && entry.metadata.last_modified == modified
+        && entry.content.len() as u64 == file_meta.len()
+    {
+        info!(
+            "Not modified: {}\n\t{}",
+            path.display(),
+            cache_path.display()
+        );
+        return Ok(FeedText {
+            content: entry.content.clone(),
+            appended_at: None,
+        });
+    }
+
+    let content =
+        std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    let appended_at = cached_data
+        .filter(|entry| {
+            entry.content.ends_with('\n')
+                && content.len() > entry.content.len()
+                && content.starts_with(&entry.content)
+        })
+        .map(|entry| entry.content.len());
+
+    write_text_cache(
+        "Read",
+        url,
+        &cache_path,
+        content.clone(),
+        CacheMetadata {
+            etag: None,
+            last_modified: modified,
+        },
+    )?;
+
+    Ok(FeedText {
+        content,
+        appended_at,
+    })
+}
+
+/// Returns the filesystem path of a local feed, given as a `file://` URL, an absolute
+/// path or a path starting with `~/`.
+///
+/// Returns `None` for URLs with any other scheme, and an error for anything else, such
+/// as a relative path or a URL missing its scheme, since it's not clear what was meant.
+pub fn local_feed_path(url: &str) -> Result<Option<PathBuf>, String> {
+    if url.starts_with("file://") {
+        return reqwest::Url::parse(url)
+            .ok()
+            .and_then(|parsed| parsed.to_file_path().ok())
+            .map(Some)
+            .ok_or_else(|| format!("Invalid file URL {}", url));
+    }
+
+    if url.contains("://") {
+        return Ok(None);
+    }
+
+    if let Some(rest) = url.strip_prefix("~/") {
+        let dirs = BaseDirs::new().ok_or("Failed to find the home directory")?;
+        return Ok(Some(dirs.home_dir().join(rest)));
+    }
+
+    let path = PathBuf::from(url);
+    if path.is_absolute() {
+        Ok(Some(path))
+    } else {
+        Err(format!(
+            "{} is neither a URL nor an absolute path. Add the scheme, such as https://, for a remote feed",
+            url
+        ))
+    }
+}
+
+/// Requests everything past the cached content of a feed, overlapping the end of the
+/// cached content by `RANGE_OVERLAP` bytes.
+///
+/// The range is conditional on the cached validator through `If-Range`, so a server
+/// sends the whole feed if it changed since it was cached, and `If-None-Match` still
+/// gets a 304 if it didn't change at all.
+///
+/// Returns `None` when the server does not support ranges or the overlap doesn't match,
+/// meaning the caller should fall back to a full download.
+async fn download_text_range(
+    url: &str,
+    cache_path: &Path,
+    entry: CacheEntry,
+) -> Result<Option<FeedText>, String> {
+    let client = get_client();
+
+    let cached_len = entry.content.len();
+    let start = cached_len - RANGE_OVERLAP;
+
+    debug!("Downloading text from {} (bytes {}-)", url, start);
+
+    let mut request = with_cache_headers(authorized_get(&client, url), Some(&entry.metadata))
+        .header(RANGE, format!("bytes={}-", start));
+    if let Some(validator) = range_validator(&entry.metadata) {
+        request = request.header(IF_RANGE, validator);
+    }
+
+    let response = request.send().await.map_err(|e| e.to_string())?;
+
+    match response.status() {
+        // 304 Not Modified
+        reqwest::StatusCode::NOT_MODIFIED => {
+            info!("304 Not Modified: {}\n\t{}", url, cache_path.display());
+            Ok(Some(FeedText {
+                content: entry.content,
+                appended_at: None,
+            }))
+        }
+
+        // 200 OK, the server ignored the range and sent everything
+        reqwest::StatusCode::OK => {
+            let metadata = response_cache_metadata(&response);
+            let content = read_capped_text(response).await?;
+
+            write_text_cache(
+                reqwest::StatusCode::OK,
+                url,
+                cache_path,
+                content.clone(),
+                metadata,
+            )?;
+
+            Ok(Some(FeedText {
+                content,
+                appended_at: None,
+            }))
+        }
+
+        // 206 Partial Content
+        reqwest::StatusCode::PARTIAL_CONTENT => {
+            let range_start = response
+                .headers()
+                .get(CONTENT_RANGE)
+                .and_then(|v| v.to_str().ok())
+                .and_then(parse_content_range_start);
+
+            if range_start != Some(start) {
+                debug!("206 with unexpected Content-Range for {}", url);
+                return Ok(None);
+            }
+
+            let metadata = response_cache_metadata(&response);
+            let data = read_capped_feed(response).await?;
+
+            if !data.starts_with(&entry.content.as_bytes()[start..]) {
+                info!(
+                    "206 Partial Content: {}\n\tFeed was rewritten, refetching",
+                    url
+                );
+                return Ok(None);
+            }
+
+            let appended = match std::str::from_utf8(&data[RANGE_OVERLAP..]) {
+                Ok(appended) => appended,
+                Err(_) => return Ok(None),
+            };
+
+            debug!("{} new bytes appended to {}", appended.len(), url);
+
+            let mut content = entry.content;
+            content.push_str(appended);
+
+            write_text_cache(
+                reqwest::StatusCode::PARTIAL_CONTENT,
+                url,
+                cache_path,
+                content.clone(),
+                metadata,
+            )?;
+
+            Ok(Some(FeedText {
+                content,
+                appended_at: (!appended.is_empty()).then_some(cached_len),
+            }))
+        }
+
+        // Most likely 416 Range Not Satisfiable, the feed shrank
+        status => {
+            debug!("{}: range request not usable for {}", status, url);
+            Ok(None)
+        }
+    }
+}
+
+/// Fetches a URL that isn't served over HTTP, dispatching on its scheme, and refuses
+/// responses larger than `limit` bytes.
+///
+/// Returns `None` for HTTP(S) URLs, which are fetched with `reqwest` and cached using
+/// HTTP caching headers instead.
+async fn fetch_other_protocol(url: &str, limit: u64) -> Option<Result<Bytes, String>> {
+    let (scheme, _) = url.split_once("://")?;
+    match scheme.to_ascii_lowercase().as_str() {
+        "gemini" => Some(gemini::fetch(url, limit).await),
+        "gopher" => Some(gopher::fetch(url, limit).await),
+        "file" => Some(
+            local_feed_path(url)
+                .and_then(|path| path.ok_or_else(|| format!("Invalid file URL {}", url)))
+                .and_then(|path| read_capped_file(&path, limit)),
+        ),
+        _ => None,
+    }
+}
+
+/// Reads a local file, refusing files larger than `limit` bytes.
+fn read_capped_file(path: &Path, limit: u64) -> Result<Bytes, String> {
+    let file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+
+    // Reading one byte past the limit is enough to tell the file is too large, even if
+    // it grows while it's read
+    let mut data = Vec::new();
+    file.take(limit + 1)
+        .read_to_end(&mut data)
+        .map_err(|e| e.to_string())?;
+    if data.len() as u64 > limit {
+        return Err(format!("{} is larger than {} bytes", path.display(), limit));
+    }
+    Ok(Bytes::from(data))
+}
+
+/// Reads a response body, giving up as soon as it grows past [`MAX_DOWNLOAD_BYTES`](crate::utils::media::MAX_DOWNLOAD_BYTES).
+///
+/// The `Content-Length` header can be missing or wrong, so it isn't enough on its own.
+async fn read_capped_body(mut response: reqwest::Response) -> Result<Bytes, MediaError> {
+    let mut data = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        check_download_size((data.len() + chunk.len()) as u64)?;
+        data.extend_from_slice(&chunk);
+    }
+    Ok(Bytes::from(data))
+}
+
+/// Reads a feed's response body, giving up as soon as it grows past [`MAX_FEED_BYTES`].
+async fn read_capped_feed(mut response: reqwest::Response) -> Result<Bytes, String> {
+    if let Some(len) = response.content_length() {
+        check_feed_size(len)?;
+    }
+
+    let mut data = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        check_feed_size((data.len() + chunk.len()) as u64)?;
+        data.extend_from_slice(&chunk);
+    }
+    Ok(Bytes::from(data))
+}
+
+/// Like [`read_capped_feed`], but decodes the body as text.
+async fn read_capped_text(response: reqwest::Response) -> Result<String, String> {
+    let data = read_capped_feed(response).await?;
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+/// Returns an error if a feed of `len` bytes is more than we're willing to download.
+fn check_feed_size(len: u64) -> Result<(), String> {
+    if len > MAX_FEED_BYTES {
+        return Err(format!(
+            "The feed is {} bytes, the limit is {}",
+            len, MAX_FEED_BYTES
+        ));
+    }
+    Ok(())
+}
+
+/// Starts a GET request, authenticating it if credentials are stored for the URL's origin.
+///
+/// `reqwest` drops the `Authorization` header when following a redirect to another
+/// host, so the credentials never leave that origin.
+fn authorized_get(client: &reqwest::Client, url: &str) -> reqwest::RequestBuilder {
+    authorized_request(client, Method::GET, url)
+}
+
+/// Like [`authorized_get`], but for any request method.
+fn authorized_request(
+    client: &reqwest::Client,
+    method: Method,
+    url: &str,
+) -> reqwest::RequestBuilder {
+    let request = client.request(method, url);
+    match credential_for_request(url) {
+        Some(Credential::Basic { username, password }) => {
+            request.basic_auth(username, Some(password))
+        }
+        Some(Credential::Bearer { token }) => request.bearer_auth(token),
+        None => request,
+    }
+}
+
+/// Adds conditional request headers from previously cached metadata.
+fn with_cache_headers(
+    mut request: reqwest::RequestBuilder,
+    metadata: Option<&CacheMetadata>,
+) -> reqwest::RequestBuilder {
+    if let Some(meta) = metadata {
+        if let Some(ref etag) = meta.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(ref last_mod) = meta.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_mod);
+        }
+    }
+    request
+}
+
+/// The validator a range request is made conditional on. Weak ETags can't be used for
+/// `If-Range`, so the last modified date is used instead if there's one.
+fn range_validator(metadata: &CacheMetadata) -> Option<&str> {
+    metadata
+        .etag
+        .as_deref()
+        .filter(|etag| !etag.starts_with("W/"))
+        .or(metadata.last_modified.as_deref())
+}
+
+/// Reads the caching headers from a response.
+fn response_cache_metadata(response: &reqwest::Response) -> CacheMetadata {
     let etag = response
         .headers()
         .get(ETAG)
@@ -172,31 +615,269 @@ pub async fn download_text(url: String) -> Result<String, String> {
         .and_then(|v| v.to_str().ok())
         .map(|s| s.to_string());
 
-    let content = response.text().await.map_err(|e| e.to_string())?;
+    CacheMetadata {
+        etag,
+        last_modified,
+    }
+}
 
-    let new_entry = CacheEntry {
-        content: content.clone(),
-        metadata: CacheMetadata {
-            etag,
-            last_modified,
-        },
-    };
+/// Writes downloaded text and its caching headers to the text cache.
+fn write_text_cache(
+    status: impl std::fmt::Display,
+    url: &str,
+    cache_path: &Path,
+    content: String,
+    metadata: CacheMetadata,
+) -> Result<(), String> {
+    let new_entry = CacheEntry { content, metadata };
 
     let serialized = serde_json::to_string(&new_entry).map_err(|e| e.to_string())?;
     info!(
-        "200 OK: {}\n\tWriting {} bytes to {}",
+        "{}: {}\n\tWriting {} bytes to {}",
+        status,
         url,
         serialized.len(),
         cache_path.display()
     );
-    std::fs::write(cache_path, serialized).map_err(|e| e.to_string())?;
+    std::fs::write(cache_path, serialized).map_err(|e| e.to_string())
+}
 
-    Ok(content)
+/// Parses the start offset out of a `Content-Range: bytes <start>-<end>/<total>` header.
+fn parse_content_range_start(value: &str) -> Option<usize> {
+    value
+        .strip_prefix("bytes ")?
+        .split_once('-')?
+        .0
+        .trim()
+        .parse()
+        .ok()
 }
 
+/// Fetches the OpenGraph metadata of a page, used for link previews.
+///
+/// Results are kept in their own cache for a while, see the [`og`] module.
 pub async fn download_opengraph(url: String) -> Result<Object, String> {
-    let page = download_text(url.clone()).await?;
-    let obj = opengraph::extract(&mut page.to_string().as_bytes(), Default::default())
-        .map_err(|e| e.to_string())?;
-    Ok(obj)
+    og::fetch(&url).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_server::{Reply, Request, TestServer, block_on, temp_dir};
+
+    /// A cached feed long enough for range requests, and where the range requested
+    /// for it starts.
+    fn cached_feed() -> (String, usize) {
+        let content: String = (1..=28)
+            .map(|day| {
+                format!(
+                    "2024-01-{:02}T00:00:00Z\tHello, this is twt number {}\n",
+                    day, day
+                )
+            })
+            .collect();
+        let start = content.len() - RANGE_OVERLAP;
+        (content, start)
+    }
+
+    fn cache_entry(content: &str) -> CacheEntry {
+        CacheEntry {
+            content: content.to_string(),
+            metadata: CacheMetadata {
+                etag: Some("\"v1\"".to_string()),
+                last_modified: None,
+            },
+        }
+    }
+
+    /// Runs a range request for the cached feed against a server answering with
+    /// `reply`, which gets the cached feed and the start of the requested range.
+    ///
+    /// Returns the result, the request the server got and the cache file.
+    fn range_request(
+        name: &str,
+        reply: impl Fn(&str, usize) -> Reply + Send + Sync + 'static,
+    ) -> (Option<FeedText>, Request, PathBuf) {
+        let (content, start) = cached_feed();
+        let cache_path = temp_dir(name).join("cache.json");
+        let entry = cache_entry(&content);
+
+        block_on(async {
+            let server = TestServer::start(move |_| reply(&content, start)).await;
+            let result = download_text_range(&server.url("/twtxt.txt"), &cache_path, entry)
+                .await
+                .unwrap();
+            let request = server.requests().remove(0);
+            (result, request, cache_path.clone())
+        })
+    }
+
+    fn cached_content(cache_path: &Path) -> String {
+        let cached: CacheEntry =
+            serde_json::from_str(&std::fs::read_to_string(cache_path).unwrap()).unwrap();
+        cached.content
+    }
+
+    #[test]
+    fn appends_the_bytes_past_the_overlap() {
+        let appended = "2024-02-01T00:00:00Z\tA new twt\n";
+        let (result, request, cache_path) = range_request("range-append", move |content, start| {
+            let body = format!("{}{}", &content[start..], appended);
+            Reply::status(206)
+                .header(
+                    "Content-Range",
+                    &format!("bytes {}-{}/*", start, start + body.len() - 1),
+                )
+                .body(body)
+        });
+
+        let (content, start) = cached_feed();
+        assert_eq!(
+            request.header("range"),
+            Some(format!("bytes={}-", start).as_str())
+        );
+        assert_eq!(request.header("if-range"), Some("\"v1\""));
+        assert_eq!(request.header("if-none-match"), Some("\"v1\""));
+
+        let text = result.unwrap();
+        assert_eq!(text.content, format!("{}{}", content, appended));
+        assert_eq!(text.appended_at, Some(content.len()));
+        assert_eq!(cached_content(&cache_path), text.content);
+    }
+
+    #[test]
+    fn nothing_appended_past_the_overlap() {
+        let (result, _, _) = range_request("range-unchanged", |content, start| {
+            Reply::status(206)
+                .header(
+                    "Content-Range",
+                    &format!("bytes {}-{}/{}", start, content.len() - 1, content.len()),
+                )
+                .body(&content[start..])
+        });
+
+        let text = result.unwrap();
+        assert_eq!(text.content, cached_feed().0);
+        assert_eq!(text.appended_at, None);
+    }
+
+    #[test]
+    fn falls_back_when_the_overlap_differs() {
+        let (result, _, cache_path) = range_request("range-rewritten", |content, start| {
+            let body = content[start..].replace("Hello", "Howdy");
+            Reply::status(206)
+                .header(
+                    "Content-Range",
+                    &format!("bytes {}-{}/*", start, start + body.len() - 1),
+                )
+                .body(body)
+        });
+
+        assert!(result.is_none());
+        assert!(!cache_path.exists());
+    }
+
+    #[test]
+    fn falls_back_when_the_range_starts_elsewhere() {
+        let (result, _, _) = range_request("range-elsewhere", |content, start| {
+            let body = &content[start - 1..];
+            Reply::status(206)
+                .header(
+                    "Content-Range",
+                    &format!("bytes {}-{}/*", start - 1, content.len() - 1),
+                )
+                .body(body)
+        });
+        assert!(result.is_none());
+
+        let (result, _, _) = range_request("range-missing", |content, start| {
+            Reply::status(206).body(&content[start..])
+        });
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn takes_the_whole_feed_when_ranges_are_ignored() {
+        let (result, _, cache_path) = range_request("range-ignored", |_, _| {
+            Reply::status(200).body("2024-03-01T00:00:00Z\tStarting over\n")
+        });
+
+        let text = result.unwrap();
+        assert_eq!(text.content, "2024-03-01T00:00:00Z\tStarting over\n");
+        assert_eq!(text.appended_at, None);
+        assert_eq!(cached_content(&cache_path), text.content);
+    }
+
+    #[test]
+    fn keeps_the_cache_when_not_modified_or_unsatisfiable() {
+        let (result, _, _) = range_request("range-not-modified", |_, _| Reply::status(304));
+        let text = result.unwrap();
+        assert_eq!(text.content, cached_feed().0);
+        assert_eq!(text.appended_at, None);
+
+        let (result, _, _) = range_request("range-unsatisfiable", |_, _| Reply::status(416));
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn conditions_the_range_on_a_strong_validator() {
+        let metadata = |etag: Option<&str>, last_modified: Option<&str>| CacheMetadata {
+            etag: etag.map(str::to_string),
+            last_modified: last_modified.map(str::to_string),
+        };
+        let date = "Mon, 01 Jan 2024 00:00:00 GMT";
+
+        assert_eq!(
+            range_validator(&metadata(Some("\"v1\""), Some(date))),
+            Some("\"v1\"")
+        );
+        assert_eq!(
+            range_validator(&metadata(Some("W/\"v1\""), Some(date))),
+            Some(date)
+        );
+        assert_eq!(range_validator(&metadata(Some("W/\"v1\""), None)), None);
+        assert_eq!(range_validator(&metadata(None, None)), None);
+    }
+
+    #[test]
+    fn local_feed_paths() {
+        assert_eq!(
+            local_feed_path("file:///home/me/twtxt.txt"),
+            Ok(Some(PathBuf::from("/home/me/twtxt.txt")))
+        );
+        assert_eq!(
+            local_feed_path("/home/me/twtxt.txt"),
+            Ok(Some(PathBuf::from("/home/me/twtxt.txt")))
+        );
+        assert!(
+            local_feed_path("~/twtxt.txt")
+                .unwrap()
+                .unwrap()
+                .is_absolute()
+        );
+        assert_eq!(local_feed_path("https://example.com/twtxt.txt"), Ok(None));
+        assert_eq!(local_feed_path("gemini://example.com/twtxt.txt"), Ok(None));
+    }
+
+    #[test]
+    fn caps_the_size_of_local_files() {
+        let path = temp_dir("file-limit").join("twtxt.txt");
+        std::fs::write(&path, "0123456789").unwrap();
+        let url = reqwest::Url::from_file_path(&path).unwrap().to_string();
+
+        let data = block_on(fetch_other_protocol(&url, 10)).unwrap();
+        assert_eq!(data.unwrap(), "0123456789");
+
+        let error = block_on(fetch_other_protocol(&url, 9))
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(error, format!("{} is larger than 9 bytes", path.display()));
+    }
+
+    #[test]
+    fn rejects_ambiguous_feed_locations() {
+        assert!(local_feed_path("example.com/twtxt.txt").is_err());
+        assert!(local_feed_path("twtxt.txt").is_err());
+        assert!(local_feed_path("file://host:bad/twtxt.txt").is_err());
+    }
 }
@@ -0,0 +1,396 @@
+//! Minimal Gemini client used to fetch feeds hosted on Gemini capsules.
+//!
+//! Gemini servers almost always use self-signed certificates, so instead of checking
+//! them against a certificate authority, each host's certificate is pinned the first
+//! time it is seen (trust on first use) and connections are refused if it changes.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tokio_rustls::{
+    TlsConnector,
+    rustls::{
+        self, DigitallySignedStruct, SignatureScheme,
+        client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        crypto::CryptoProvider,
+        pki_types::{CertificateDer, ServerName, UnixTime},
+    },
+};
+use tracing::{debug, info, warn};
+
+use crate::utils::paths::get_gemini_known_hosts_path;
+
+/// The default port for Gemini servers.
+const DEFAULT_PORT: u16 = 1965;
+/// How many redirects are followed before giving up.
+const MAX_REDIRECTS: usize = 5;
+/// How long a single request may take before giving up.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// The longest valid response header: a two digit status, a space, up to 1024 bytes
+/// of meta and a CRLF.
+const MAX_HEADER_LEN: usize = 1029;
+
+static CONNECTOR: OnceLock<TlsConnector> = OnceLock::new();
+
+/// Guards the known hosts file so that concurrent requests don't overwrite each other.
+static KNOWN_HOSTS_LOCK: Mutex<()> = Mutex::new(());
+
+fn get_connector() -> TlsConnector {
+    CONNECTOR
+        .get_or_init(|| {
+            let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+            let config = rustls::ClientConfig::builder_with_provider(provider.clone())
+                .with_safe_default_protocol_versions()
+                .expect("Failed to build TLS config")
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(TofuVerifier { provider }))
+                .with_no_client_auth();
+            TlsConnector::from(Arc::new(config))
+        })
+        .clone()
+}
+
+/// A parsed Gemini response.
+struct Response {
+    status: u8,
+    meta: String,
+    body: Bytes,
+}
+
+/// What a successful response asks the client to do.
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Body(Bytes),
+    Redirect(Url),
+}
+
+/// Fetches a `gemini://` URL, following redirects, and returns the response body,
+/// refusing bodies larger than `limit` bytes.
+pub async fn fetch(url: &str, limit: u64) -> Result<Bytes, String> {
+    let mut url = Url::parse(url).map_err(|e| e.to_string())?;
+
+    for _ in 0..=MAX_REDIRECTS {
+        let response = tokio::time::timeout(REQUEST_TIMEOUT, request(&url, limit))
+            .await
+            .map_err(|_| format!("Timed out fetching {}", url))??;
+
+        match handle_response(&url, response)? {
+            Outcome::Body(body) => return Ok(body),
+            Outcome::Redirect(target) => url = target,
+        }
+    }
+
+    Err(format!("Too many redirects fetching {}", url))
+}
+
+/// Turns a response to a request for `url` into its body or where it redirects to,
+/// or an error for any other status.
+fn handle_response(url: &Url, response: Response) -> Result<Outcome, String> {
+    match response.status / 10 {
+        2 => {
+            info!("{} {}: {}", response.status, response.meta, url);
+            Ok(Outcome::Body(response.body))
+        }
+        3 => {
+            let target = url.join(&response.meta).map_err(|e| e.to_string())?;
+            if target.scheme() != "gemini" {
+                return Err(format!("Refusing to follow redirect to {}", target));
+            }
+            debug!("{} Redirect: {} -> {}", response.status, url, target);
+            Ok(Outcome::Redirect(target))
+        }
+        1 => Err(format!("{} requires input", url)),
+        4 => Err(format!(
+            "{} Temporary failure: {}",
+            response.status, response.meta
+        )),
+        5 => Err(format!(
+            "{} Permanent failure: {}",
+            response.status, response.meta
+        )),
+        6 => Err(format!(
+            "{} Client certificate required: {}",
+            response.status, response.meta
+        )),
+        _ => Err(format!("Invalid Gemini status {}", response.status)),
+    }
+}
+
+/// Sends a single Gemini request and reads the whole response, as long as its body
+/// isn't larger than `limit` bytes.
+async fn request(url: &Url, limit: u64) -> Result<Response, String> {
+    let host = url.host_str().ok_or("Gemini URL has no host")?.to_string();
+    let port = url.port().unwrap_or(DEFAULT_PORT);
+
+    let tcp = TcpStream::connect((host.as_str(), port))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let server_name = ServerName::try_from(host.clone()).map_err(|e| e.to_string())?;
+    let mut stream = get_connector()
+        .connect(server_name, tcp)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let certificate = stream
+        .get_ref()
+        .1
+        .peer_certificates()
+        .and_then(|certs| certs.first())
+        .ok_or("Server did not send a certificate")?;
+    check_pinned_certificate(&host, port, certificate)?;
+
+    stream
+        .write_all(format!("{}\r\n", url).as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // Reading one byte past the limit is enough to tell the body is too large
+    let mut raw = Vec::new();
+    let max_len = limit + MAX_HEADER_LEN as u64 + 1;
+    if let Err(e) = (&mut stream).take(max_len).read_to_end(&mut raw).await {
+        // Plenty of servers close the connection without a TLS close_notify,
+        // which is harmless once we have the response.
+        if e.kind() != std::io::ErrorKind::UnexpectedEof || raw.is_empty() {
+            return Err(e.to_string());
+        }
+    }
+
+    let response = parse_response(Bytes::from(raw))?;
+    if response.body.len() as u64 > limit {
+        return Err(format!("{} is larger than {} bytes", url, limit));
+    }
+    Ok(response)
+}
+
+/// Splits a raw response into its `<status> <meta>\r\n` header and body.
+fn parse_response(raw: Bytes) -> Result<Response, String> {
+    let header_end = raw
+        .iter()
+        .take(MAX_HEADER_LEN)
+        .position(|&b| b == b'\n')
+        .ok_or("Invalid Gemini response header")?;
+
+    let header = std::str::from_utf8(&raw[..header_end])
+        .map_err(|e| e.to_string())?
+        .trim_end_matches('\r');
+
+    let (status, meta) = header.split_once(' ').unwrap_or((header, ""));
+    let status = status
+        .parse::<u8>()
+        .ok()
+        .filter(|s| (10..70).contains(s))
+        .ok_or_else(|| format!("Invalid Gemini status {:?}", status))?;
+
+    Ok(Response {
+        status,
+        meta: meta.trim().to_string(),
+        body: raw.slice(header_end + 1..),
+    })
+}
+
+/// Checks a server's certificate against the one pinned for that host, pinning it
+/// if the host hasn't been seen before.
+fn check_pinned_certificate(
+    host: &str,
+    port: u16,
+    certificate: &CertificateDer<'_>,
+) -> Result<(), String> {
+    let fingerprint = hex::encode(Sha256::digest(certificate.as_ref()));
+    let key = format!("{}:{}", host, port);
+
+    let _guard = KNOWN_HOSTS_LOCK.lock().map_err(|e| e.to_string())?;
+
+    let path = get_gemini_known_hosts_path()?;
+    let mut known_hosts: HashMap<String, String> = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default();
+
+    match known_hosts.get(&key) {
+        Some(pinned) if *pinned == fingerprint => Ok(()),
+        Some(pinned) => {
+            warn!(
+                "Certificate for {} changed!\n\tPinned: {}\n\tReceived: {}",
+                key, pinned, fingerprint
+            );
+            Err(format!(
+                "Certificate for {} does not match the pinned certificate. \
+                 If this is expected, remove its entry from {}",
+                key,
+                path.display()
+            ))
+        }
+        None => {
+            info!("Pinning certificate for {}: {}", key, fingerprint);
+            known_hosts.insert(key, fingerprint);
+            let serialized =
+                serde_json::to_string_pretty(&known_hosts).map_err(|e| e.to_string())?;
+            std::fs::write(&path, serialized).map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// A certificate verifier that accepts any certificate, as long as the handshake
+/// signatures are valid.
+///
+/// The certificate itself is checked with [`check_pinned_certificate`] once the
+/// handshake is done.
+#[derive(Debug)]
+struct TofuVerifier {
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for TofuVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handle(raw: &'static [u8]) -> Result<Outcome, String> {
+        let url = Url::parse("gemini://example.com/feeds/twtxt.txt").unwrap();
+        handle_response(&url, parse_response(Bytes::from_static(raw))?)
+    }
+
+    #[test]
+    fn returns_the_body_of_successful_responses() {
+        assert_eq!(
+            handle(b"20 text/plain\r\n2024-01-01T00:00:00Z\thi\n"),
+            Ok(Outcome::Body(Bytes::from_static(
+                b"2024-01-01T00:00:00Z\thi\n"
+            )))
+        );
+        // Some servers end the header with a bare LF
+        assert_eq!(
+            handle(b"20 text/plain\nhi"),
+            Ok(Outcome::Body(Bytes::from_static(b"hi")))
+        );
+    }
+
+    #[test]
+    fn resolves_redirects() {
+        assert_eq!(
+            handle(b"31 ../twtxt.txt\r\n"),
+            Ok(Outcome::Redirect(
+                Url::parse("gemini://example.com/twtxt.txt").unwrap()
+            ))
+        );
+        assert_eq!(
+            handle(b"30 gemini://other.example/twtxt.txt\r\n"),
+            Ok(Outcome::Redirect(
+                Url::parse("gemini://other.example/twtxt.txt").unwrap()
+            ))
+        );
+        assert_eq!(
+            handle(b"31 https://example.com/twtxt.txt\r\n"),
+            Err("Refusing to follow redirect to https://example.com/twtxt.txt".to_string())
+        );
+    }
+
+    #[test]
+    fn reports_failures() {
+        assert_eq!(
+            handle(b"10 Your name?\r\n"),
+            Err("gemini://example.com/feeds/twtxt.txt requires input".to_string())
+        );
+        assert_eq!(
+            handle(b"44 Slow down\r\n"),
+            Err("44 Temporary failure: Slow down".to_string())
+        );
+        assert_eq!(
+            handle(b"51 Not found\r\n"),
+            Err("51 Permanent failure: Not found".to_string())
+        );
+        assert_eq!(
+            handle(b"60 Certificate needed\r\n"),
+            Err("60 Client certificate required: Certificate needed".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        assert_eq!(
+            handle(b"2 text/plain\r\n"),
+            Err("Invalid Gemini status \"2\"".to_string())
+        );
+        assert_eq!(
+            handle(b"75 text/plain\r\n"),
+            Err("Invalid Gemini status \"75\"".to_string())
+        );
+        assert_eq!(
+            handle(b"OK\r\n"),
+            Err("Invalid Gemini status \"OK\"".to_string())
+        );
+        assert_eq!(
+            handle(b"20 text/plain"),
+            Err("Invalid Gemini response header".to_string())
+        );
+        assert!(handle(b"20 \xff\r\n").is_err());
+    }
+
+    #[test]
+    fn limits_the_meta_length() {
+        let header = |meta_len: usize| format!("20 {}\r\nbody", "a".repeat(meta_len));
+
+        let response = parse_response(Bytes::from(header(1024))).unwrap();
+        assert_eq!(response.meta.len(), 1024);
+        assert_eq!(response.body, Bytes::from_static(b"body"));
+
+        assert!(parse_response(Bytes::from(header(1025))).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+//! Minimal Gopher client used to fetch feeds hosted on Gopher servers.
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use percent_encoding::percent_decode_str;
+use reqwest::Url;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tracing::info;
+
+/// The default port for Gopher servers.
+const DEFAULT_PORT: u16 = 70;
+/// How long a single request may take before giving up.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Fetches a `gopher://` URL and returns the response body, refusing bodies larger
+/// than `limit` bytes.
+///
+/// URLs follow RFC 4266, `gopher://<host>:<port>/<type><selector>`, so a feed would
+/// usually be at something like `gopher://example.com/0/twtxt.txt`.
+pub async fn fetch(url: &str, limit: u64) -> Result<Bytes, String> {
+    let url = Url::parse(url).map_err(|e| e.to_string())?;
+
+    tokio::time::timeout(REQUEST_TIMEOUT, request(&url, limit))
+        .await
+        .map_err(|_| format!("Timed out fetching {}", url))?
+}
+
+async fn request(url: &Url, limit: u64) -> Result<Bytes, String> {
+    let host = url.host_str().ok_or("Gopher URL has no host")?;
+    let port = url.port().unwrap_or(DEFAULT_PORT);
+
+    // The first character of the path is the item type, the rest is the selector
+    let path = url.path().strip_prefix('/').unwrap_or(url.path());
+    let mut chars = path.chars();
+    let item_type = chars.next().unwrap_or('1');
+    let mut selector = percent_decode_str(chars.as_str())
+        .decode_utf8_lossy()
+        .into_owned();
+
+    if let Some(query) = url.query() {
+        selector.push('\t');
+        selector.push_str(&percent_decode_str(query).decode_utf8_lossy());
+    }
+
+    let mut stream = TcpStream::connect((host, port))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    stream
+        .write_all(format!("{}\r\n", selector).as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // Reading one byte past the limit is enough to tell the response is too large
+    let mut raw = Vec::new();
+    (&mut stream)
+        .take(limit + 1)
+        .read_to_end(&mut raw)
+        .await
+        .map_err(|e| e.to_string())?;
+    if raw.len() as u64 > limit {
+        return Err(format!("{} is larger than {} bytes", url, limit));
+    }
+
+    info!("Gopher: {}\n\tReceived {} bytes", url, raw.len());
+
+    let data = Bytes::from(raw);
+    if item_type == '0' {
+        Ok(unstuff_dots(strip_terminator(data)))
+    } else {
+        Ok(data)
+    }
+}
+
+/// Removes the lone `.` line that terminates Gopher text documents.
+fn strip_terminator(data: Bytes) -> Bytes {
+    if data.as_ref() == b".\r\n" || data.as_ref() == b"." {
+        return Bytes::new();
+    }
+
+    for terminator in [&b"\n.\r\n"[..], b"\n.\n", b"\n."] {
+        if data.ends_with(terminator) {
+            // Keep the newline ending the last real line
+            return data.slice(..data.len() - terminator.len() + 1);
+        }
+    }
+
+    data
+}
+
+/// Undoes the escaping of lines starting with a `.` in Gopher text documents, which
+/// servers send with the dot doubled so they can't be mistaken for the terminator.
+fn unstuff_dots(data: Bytes) -> Bytes {
+    if !data.starts_with(b"..") && !data.windows(3).any(|w| w == b"\n..") {
+        return data;
+    }
+
+    let mut unstuffed = Vec::with_capacity(data.len());
+    for line in data.split_inclusive(|&b| b == b'\n') {
+        unstuffed.extend_from_slice(
+            line.strip_prefix(b".")
+                .filter(|l| l.starts_with(b"."))
+                .unwrap_or(line),
+        );
+    }
+    Bytes::from(unstuffed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_the_terminator() {
+        let text = |s: &'static str| strip_terminator(Bytes::from_static(s.as_bytes()));
+
+        assert_eq!(text("a\r\nb\r\n.\r\n"), "a\r\nb\r\n");
+        assert_eq!(text("a\nb\n.\n"), "a\nb\n");
+        assert_eq!(text("a\nb\n."), "a\nb\n");
+        assert_eq!(text(".\r\n"), "");
+        assert_eq!(text("."), "");
+        // Servers that don't send a terminator
+        assert_eq!(text("a\nb\n"), "a\nb\n");
+        assert_eq!(text("a\n.b\n"), "a\n.b\n");
+    }
+
+    #[test]
+    fn unstuffs_leading_dots() {
+        let text = |s: &'static str| unstuff_dots(Bytes::from_static(s.as_bytes()));
+
+        assert_eq!(text("..a\r\nb\r\n...c\r\n"), ".a\r\nb\r\n..c\r\n");
+        assert_eq!(text("a.\n.b\n"), "a.\n.b\n");
+        assert_eq!(text("a\n.."), "a\n.");
+    }
+}
@@ -0,0 +1,329 @@
+//! Fetching and caching the OpenGraph metadata used for link previews.
+//!
+//! Only the extracted [`Object`] is cached, never the page itself, with its title and
+//! description shortened to what a preview card shows. Links are checked with a
+//! `HEAD` request first, so files and very large pages aren't downloaded just to find
+//! out they have no preview.
+
+use std::path::Path;
+
+use chrono::{DateTime, Duration, Utc};
+use opengraph::Object;
+use reqwest::{
+    Method, Response, StatusCode,
+    header::{CONTENT_LENGTH, CONTENT_TYPE},
+};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use super::{authorized_request, get_client};
+use crate::utils::paths::get_opengraph_cache_path;
+
+/// How long a fetched preview is used before the page is checked again.
+const TTL: Duration = Duration::days(7);
+/// How long to remember that a link has no preview.
+const NEGATIVE_TTL: Duration = Duration::days(1);
+/// How much of a page is read when looking for its metadata. OpenGraph tags live in
+/// the `<head>`, so anything past this is very unlikely to matter.
+const MAX_PAGE_BYTES: usize = 2 * 1024 * 1024;
+/// Pages larger than this (according to their `Content-Length`) are skipped entirely.
+const MAX_CONTENT_LENGTH: u64 = 16 * 1024 * 1024;
+/// How many characters of a page's title are kept.
+const MAX_TITLE_CHARS: usize = 200;
+/// How many characters of a page's description are kept.
+const MAX_DESCRIPTION_CHARS: usize = 500;
+
+/// A cached preview, or the reason a link doesn't have one.
+#[derive(Serialize, Deserialize, Debug)]
+struct CacheEntry {
+    fetched_at: DateTime<Utc>,
+    result: Result<Object, String>,
+}
+
+impl CacheEntry {
+    fn is_fresh(&self) -> bool {
+        let ttl = if self.result.is_ok() {
+            TTL
+        } else {
+            NEGATIVE_TTL
+        };
+        Utc::now() - self.fetched_at < ttl
+    }
+}
+
+/// Returns the OpenGraph metadata of the page at `url`, from the cache if possible.
+pub async fn fetch(url: &str) -> Result<Object, String> {
+    let cache_path = get_opengraph_cache_path(url)?;
+    fetch_cached(url, &cache_path).await
+}
+
+/// Returns the metadata of the page at `url`, cached at `cache_path`.
+async fn fetch_cached(url: &str, cache_path: &Path) -> Result<Object, String> {
+    let cached: Option<CacheEntry> = std::fs::read_to_string(cache_path)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok());
+
+    if let Some(entry) = cached
+        && entry.is_fresh()
+    {
+        debug!("Using cached OpenGraph metadata for {}", url);
+        return entry.result;
+    }
+
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(format!("No link preview available for {}", url));
+    }
+
+    let result = fetch_uncached(url).await;
+
+    // Network errors aren't cached, since they're usually temporary
+    let Some(result) = result else {
+        return Err(format!("Failed to fetch OpenGraph metadata for {}", url));
+    };
+
+    let entry = CacheEntry {
+        fetched_at: Utc::now(),
+        result,
+    };
+    match serde_json::to_string(&entry) {
+        Ok(json) => {
+            if let Err(e) = std::fs::write(cache_path, json) {
+                warn!("Failed to cache OpenGraph metadata for {}: {}", url, e);
+            }
+        }
+        Err(e) => warn!("Failed to serialize OpenGraph metadata for {}: {}", url, e),
+    }
+
+    entry.result
+}
+
+/// Fetches and extracts the metadata of a page.
+///
+/// Returns `None` if the request failed, and `Some(Err(_))` if the link has no
+/// preview, such as when it points to a file rather than a page.
+async fn fetch_uncached(url: &str) -> Option<Result<Object, String>> {
+    let client = get_client();
+
+    // Some servers don't support HEAD, in which case the same checks are done on the
+    // GET response before its body is read
+    match authorized_request(&client, Method::HEAD, url).send().await {
+        Ok(response) if response.status().is_success() => {
+            if let Err(e) = check_page(&response) {
+                info!("HEAD {}: {}", url, e);
+                return Some(Err(e));
+            }
+        }
+        Ok(response) => debug!("HEAD {} returned {}", url, response.status()),
+        Err(e) => {
+            warn!("HEAD {} failed: {}", url, e);
+            return None;
+        }
+    }
+
+    let mut response = match authorized_request(&client, Method::GET, url).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            warn!("GET {} failed: {}", url, e);
+            return None;
+        }
+    };
+
+    if response.status() != StatusCode::OK {
+        return Some(Err(format!("{} returned {}", url, response.status())));
+    }
+    if let Err(e) = check_page(&response) {
+        return Some(Err(e));
+    }
+
+    let mut page = Vec::new();
+    loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                page.extend_from_slice(&chunk);
+                if page.len() >= MAX_PAGE_BYTES {
+                    debug!("Only reading the first {} bytes of {}", MAX_PAGE_BYTES, url);
+                    page.truncate(MAX_PAGE_BYTES);
+                    break;
+                }
+            }
+            Ok(None) => break,
+            Err(e) => {
+                warn!("Failed to read {}: {}", url, e);
+                return None;
+            }
+        }
+    }
+
+    info!(
+        "200 OK: {}\n\tExtracting OpenGraph metadata from {} bytes",
+        url,
+        page.len()
+    );
+
+    let page = String::from_utf8_lossy(&page);
+    Some(
+        opengraph::extract(&mut page.as_bytes(), Default::default())
+            .map(shorten)
+            .map_err(|e| e.to_string()),
+    )
+}
+
+/// Cuts the title and description of a page down to what a preview shows.
+fn shorten(mut object: Object) -> Object {
+    truncate(&mut object.title, MAX_TITLE_CHARS);
+    if let Some(description) = &mut object.description {
+        truncate(description, MAX_DESCRIPTION_CHARS);
+    }
+    object
+}
+
+fn truncate(text: &mut String, max_chars: usize) {
+    if let Some((end, _)) = text.char_indices().nth(max_chars) {
+        text.truncate(end);
+        text.push_str("...");
+    }
+}
+
+/// Checks from a response's headers that it's an HTML page of a reasonable size.
+fn check_page(response: &Response) -> Result<(), String> {
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(';').next().unwrap_or(v).trim().to_ascii_lowercase());
+
+    if let Some(content_type) = content_type
+        && content_type != "text/html"
+        && content_type != "application/xhtml+xml"
+    {
+        return Err(format!("Not an HTML page ({})", content_type));
+    }
+
+    // `Response::content_length` is always zero for HEAD responses, so read the header
+    let content_length = response
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+
+    if let Some(len) = content_length
+        && len > MAX_CONTENT_LENGTH
+    {
+        return Err(format!("Page is too large ({} bytes)", len));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_server::{Reply, TestServer, block_on, temp_dir};
+
+    const PAGE: &str = r#"<html><head><meta property="og:title" content="Hello"></head></html>"#;
+
+    /// Makes the cached entry look like it was fetched `age` ago.
+    fn age(cache_path: &Path, age: Duration) {
+        let mut entry: CacheEntry =
+            serde_json::from_str(&std::fs::read_to_string(cache_path).unwrap()).unwrap();
+        entry.fetched_at = Utc::now() - age;
+        std::fs::write(cache_path, serde_json::to_string(&entry).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn caches_previews_until_they_expire() {
+        let cache_path = temp_dir("og-ttl").join("og.json");
+
+        block_on(async {
+            let server = TestServer::start(|request| {
+                let reply = Reply::status(200).header("Content-Type", "text/html; charset=utf-8");
+                if request.method == "GET" {
+                    reply.body(PAGE)
+                } else {
+                    reply
+                }
+            })
+            .await;
+            let url = server.url("/post");
+            let methods =
+                || -> Vec<String> { server.requests().into_iter().map(|r| r.method).collect() };
+
+            fetch_cached(&url, &cache_path).await.unwrap();
+            assert_eq!(methods(), ["HEAD", "GET"]);
+
+            fetch_cached(&url, &cache_path).await.unwrap();
+            age(&cache_path, TTL - Duration::hours(1));
+            fetch_cached(&url, &cache_path).await.unwrap();
+            assert_eq!(methods().len(), 2);
+
+            age(&cache_path, TTL + Duration::hours(1));
+            fetch_cached(&url, &cache_path).await.unwrap();
+            assert_eq!(methods(), ["HEAD", "GET", "HEAD", "GET"]);
+        });
+    }
+
+    #[test]
+    fn does_not_download_files() {
+        let cache_path = temp_dir("og-file").join("og.json");
+
+        block_on(async {
+            let server = TestServer::start(|request| {
+                let reply = Reply::status(200).header("Content-Type", "image/png");
+                if request.method == "GET" {
+                    reply.body(vec![0; 64])
+                } else {
+                    reply
+                }
+            })
+            .await;
+            let url = server.url("/photo.png");
+
+            let result = fetch_cached(&url, &cache_path).await;
+            assert_eq!(result.unwrap_err(), "Not an HTML page (image/png)");
+            assert_eq!(server.requests().len(), 1);
+            assert_eq!(server.requests()[0].method, "HEAD");
+
+            // Links without a preview are remembered for a shorter while
+            age(&cache_path, NEGATIVE_TTL - Duration::hours(1));
+            assert!(fetch_cached(&url, &cache_path).await.is_err());
+            assert_eq!(server.requests().len(), 1);
+
+            age(&cache_path, NEGATIVE_TTL + Duration::hours(1));
+            assert!(fetch_cached(&url, &cache_path).await.is_err());
+            assert_eq!(server.requests().len(), 2);
+        });
+    }
+
+    #[test]
+    fn does_not_download_huge_pages() {
+        let cache_path = temp_dir("og-huge").join("og.json");
+
+        block_on(async {
+            let server = TestServer::start(|_| {
+                Reply::status(200)
+                    .header("Content-Type", "text/html")
+                    .header("Content-Length", &(MAX_CONTENT_LENGTH + 1).to_string())
+            })
+            .await;
+
+            let result = fetch_cached(&server.url("/huge"), &cache_path).await;
+            assert_eq!(
+                result.unwrap_err(),
+                format!("Page is too large ({} bytes)", MAX_CONTENT_LENGTH + 1)
+            );
+            let methods: Vec<String> = server.requests().into_iter().map(|r| r.method).collect();
+            assert_eq!(methods, ["HEAD"]);
+        });
+    }
+
+    #[test]
+    fn truncates_long_text() {
+        let mut title = "é".repeat(MAX_TITLE_CHARS + 1);
+        truncate(&mut title, MAX_TITLE_CHARS);
+        assert_eq!(title, format!("{}...", "é".repeat(MAX_TITLE_CHARS)));
+
+        let mut description = "a".repeat(MAX_DESCRIPTION_CHARS);
+        truncate(&mut description, MAX_DESCRIPTION_CHARS);
+        assert_eq!(description, "a".repeat(MAX_DESCRIPTION_CHARS));
+    }
+}
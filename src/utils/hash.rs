@@ -1,11 +1,16 @@
-//! Module for computing SHA-256 hashes of strings.
+//! Module for computing SHA-256 hashes of strings and bytes.
 
 use hex;
 use sha2::{Digest, Sha256};
 
 /// Computes a SHA-256 hash of the provided string.
 pub fn hash_sha256_str(s: &str) -> String {
+    hash_sha256_bytes(s.as_bytes())
+}
+
+/// Computes a SHA-256 hash of the provided bytes.
+pub fn hash_sha256_bytes(bytes: &[u8]) -> String {
     let mut hasher = Sha256::new();
-    hasher.update(s.as_bytes());
+    hasher.update(bytes);
     hex::encode(hasher.finalize())
 }
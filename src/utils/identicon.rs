@@ -0,0 +1,131 @@
+//! Module for generating identicons, used as avatars for feeds that don't have one.
+
+use std::sync::{LazyLock, Mutex, PoisonError};
+
+use iced::{Color, Theme, widget::image::Handle};
+use sha2::{Digest, Sha256};
+
+use crate::utils::lru::Lru;
+
+/// The number of cells along each side of the pattern.
+const GRID: usize = 5;
+/// The size of a single cell, in pixels.
+const CELL: usize = 16;
+/// The empty border around the pattern, in pixels.
+const MARGIN: usize = CELL / 2;
+/// The size of the generated image, in pixels.
+const SIZE: usize = GRID * CELL + 2 * MARGIN;
+/// How many identicons are kept around, about 18 MiB of them.
+const CACHED_IDENTICONS: u64 = 512;
+
+/// A seed, with the foreground and background colors it's drawn in.
+type Key = (String, [u8; 4], [u8; 4]);
+
+/// Recently generated identicons, keyed by their seed and colors so each is only drawn
+/// once and iced doesn't have to upload a new image every frame.
+static CACHE: LazyLock<Mutex<Lru<Key, Handle>>> =
+    LazyLock::new(|| Mutex::new(Lru::new(CACHED_IDENTICONS)));
+
+/// Returns the identicon for `seed`, usually the URL of a feed, in the colors of `theme`.
+///
+/// The pattern is a horizontally symmetric 5x5 grid, and both the pattern and its
+/// color are picked from a hash of the seed, so the same feed always gets the same
+/// identicon.
+pub fn identicon(seed: &str, theme: &Theme) -> Handle {
+    let hash = Sha256::digest(seed.as_bytes());
+
+    let palette = theme.palette();
+    let ext = theme.extended_palette();
+    let colors = [
+        palette.primary,
+        palette.success,
+        palette.warning,
+        palette.danger,
+        ext.secondary.base.color,
+    ];
+
+    // Blend towards the text color a little, so feeds sharing a base color still differ
+    let base = colors[hash[0] as usize % colors.len()];
+    let fg = mix(base, palette.text, f32::from(hash[1]) / 255.0 * 0.4).into_rgba8();
+    let bg = ext.background.strong.color.into_rgba8();
+
+    let key = (seed.to_string(), fg, bg);
+    let mut cache = CACHE.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(handle) = cache.get(&key) {
+        return handle;
+    }
+    let handle = render(&hash[2..], fg, bg);
+    cache.insert(key, handle.clone(), 1);
+    handle
+}
+
+/// Draws the pattern described by the bits of `bits` into an image.
+fn render(bits: &[u8], fg: [u8; 4], bg: [u8; 4]) -> Handle {
+    let filled = |row: usize, col: usize| {
+        // Only the left half (and middle column) is stored, the rest is mirrored
+        let col = col.min(GRID - 1 - col);
+        let bit = row * GRID.div_ceil(2) + col;
+        bits[bit / 8] & (1 << (bit % 8)) != 0
+    };
+
+    let mut pixels = Vec::with_capacity(SIZE * SIZE * 4);
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            let in_grid =
+                (MARGIN..SIZE - MARGIN).contains(&x) && (MARGIN..SIZE - MARGIN).contains(&y);
+            let color = if in_grid && filled((y - MARGIN) / CELL, (x - MARGIN) / CELL) {
+                fg
+            } else {
+                bg
+            };
+            pixels.extend_from_slice(&color);
+        }
+    }
+
+    Handle::from_rgba(SIZE as u32, SIZE as u32, pixels)
+}
+
+/// Linearly interpolates between two colors.
+fn mix(a: Color, b: Color, t: f32) -> Color {
+    Color {
+        r: a.r + (b.r - a.r) * t,
+        g: a.g + (b.g - a.g) * t,
+        b: a.b + (b.b - a.b) * t,
+        a: 1.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixels(handle: &Handle) -> &[u8] {
+        match handle {
+            Handle::Rgba { pixels, .. } => pixels,
+            _ => panic!("identicons are drawn as RGBA"),
+        }
+    }
+
+    #[test]
+    fn same_seed_same_identicon() {
+        let seed = "https://example.com/twtxt.txt";
+        let first = identicon(seed, &Theme::Dark);
+        assert_eq!(identicon(seed, &Theme::Dark).id(), first.id());
+
+        // Drawing it again, such as after it was evicted, gives the same image
+        let hash = Sha256::digest(seed.as_bytes());
+        let redrawn = render(&hash[2..], [255; 4], [0; 4]);
+        assert_eq!(
+            pixels(&redrawn),
+            pixels(&render(&hash[2..], [255; 4], [0; 4]))
+        );
+    }
+
+    #[test]
+    fn different_seeds_different_identicons() {
+        let a = identicon("https://a.example/twtxt.txt", &Theme::Dark);
+        let b = identicon("https://b.example/twtxt.txt", &Theme::Dark);
+        assert_ne!(pixels(&a), pixels(&b));
+        assert_eq!(pixels(&a).len(), SIZE * SIZE * 4);
+    }
+}
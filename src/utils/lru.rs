@@ -0,0 +1,115 @@
+//! A cache that keeps the most recently used values up to a budget, such as a number
+//! of bytes, and forgets the least recently used ones past it.
+
+use std::{collections::HashMap, hash::Hash};
+
+struct Entry<V> {
+    value: V,
+    /// How much of the budget the value takes up.
+    cost: u64,
+    last_used: u64,
+}
+
+pub struct Lru<K, V> {
+    entries: HashMap<K, Entry<V>>,
+    budget: u64,
+    /// The total cost of the values in the cache.
+    cost: u64,
+    /// Incremented on every access, used to find the least recently used value.
+    clock: u64,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Lru<K, V> {
+    pub fn new(budget: u64) -> Self {
+        Self {
+            entries: HashMap::new(),
+            budget,
+            cost: 0,
+            clock: 0,
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(key)?;
+        entry.last_used = self.clock;
+        Some(entry.value.clone())
+    }
+
+    /// Adds `value`, which takes up `cost` of the budget, evicting the least recently
+    /// used values until the cache fits the budget again.
+    pub fn insert(&mut self, key: K, value: V, cost: u64) {
+        self.clock += 1;
+        self.cost += cost;
+        let entry = Entry {
+            value,
+            cost,
+            last_used: self.clock,
+        };
+        if let Some(old) = self.entries.insert(key, entry) {
+            self.cost -= old.cost;
+        }
+
+        // Always keep the newest value, even if it's over budget on its own
+        while self.cost > self.budget && self.entries.len() > 1 {
+            let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&oldest) {
+                self.cost -= entry.cost;
+            }
+        }
+    }
+
+    /// The total cost of the values in the cache.
+    #[cfg(test)]
+    pub fn cost(&self) -> u64 {
+        self.cost
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_the_least_recently_used_values() {
+        let mut lru = Lru::new(3);
+        for key in ["a", "b", "c"] {
+            lru.insert(key, key.to_uppercase(), 1);
+        }
+
+        assert_eq!(lru.get(&"a").as_deref(), Some("A"));
+        lru.insert("d", "D".to_string(), 1);
+        assert_eq!(lru.get(&"b"), None);
+        for key in ["a", "c", "d"] {
+            assert!(lru.get(&key).is_some(), "{} was evicted", key);
+        }
+        assert_eq!(lru.cost(), 3);
+    }
+
+    #[test]
+    fn replacing_a_value_only_counts_its_new_cost() {
+        let mut lru = Lru::new(10);
+        lru.insert("a", 1, 4);
+        lru.insert("a", 2, 6);
+        assert_eq!(lru.get(&"a"), Some(2));
+        assert_eq!(lru.cost(), 6);
+    }
+
+    #[test]
+    fn keeps_the_newest_value_even_over_budget() {
+        let mut lru = Lru::new(10);
+        lru.insert("small", 1, 2);
+        lru.insert("huge", 2, 20);
+
+        assert_eq!(lru.get(&"small"), None);
+        assert_eq!(lru.get(&"huge"), Some(2));
+        assert_eq!(lru.cost(), 20);
+    }
+}
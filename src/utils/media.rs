@@ -0,0 +1,358 @@
+//! Limits and decoding for remote media such as avatars, inline images and link previews.
+//!
+//! Anything linked from a feed is untrusted, so downloads are capped in size and images
+//! are only decoded once their format has been sniffed from the data and their
+//! dimensions checked, which keeps decompression bombs from exhausting memory. Images
+//! are then shrunk to the size they're displayed at, cached on disk and shared in
+//! memory between every component showing them. The thumbnails on disk are rebuilt
+//! once they're a day old, so a changed avatar shows up, and the oldest are removed
+//! once they take up too much space.
+
+mod cache;
+
+use std::{
+    fmt,
+    io::Cursor,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use iced::{
+    Background, Border, Element, Length, Theme,
+    border::Radius,
+    widget::{container, image::Handle, text},
+};
+use image::{
+    DynamicImage, ImageDecoder, ImageError, ImageFormat, ImageReader, Limits, RgbaImage,
+    imageops::FilterType, metadata::Orientation,
+};
+use tracing::{debug, warn};
+
+use crate::utils::{
+    download::download_binary, paths::get_thumbnail_cache_path, styling::secondary_text,
+};
+
+/// The largest media file that will be downloaded.
+pub const MAX_DOWNLOAD_BYTES: u64 = 16 * 1024 * 1024;
+/// The largest width or height of an image that will be decoded.
+pub const MAX_IMAGE_DIMENSION: u32 = 8192;
+/// The most memory a single decoded image may take up.
+pub const MAX_DECODED_BYTES: u64 = 128 * 1024 * 1024;
+
+/// The size of the box avatars are shrunk to fit, twice the largest size they're shown
+/// at so they stay sharp on high DPI screens.
+const AVATAR_THUMBNAIL_SIZE: u32 = 96;
+
+/// How long a thumbnail on disk is used before the original is checked for changes.
+const THUMBNAIL_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+/// How much disk space the thumbnail cache may take up.
+const THUMBNAIL_DISK_BUDGET: u64 = 256 * 1024 * 1024;
+
+/// The image formats we're able to decode.
+pub const SUPPORTED_FORMATS: &[ImageFormat] =
+    &[ImageFormat::Png, ImageFormat::Jpeg, ImageFormat::WebP];
+
+/// Why a piece of media couldn't be loaded.
+#[derive(Debug, Clone)]
+pub enum MediaError {
+    /// The media is larger than we're willing to download or decode.
+    TooLarge(String),
+    /// The data isn't an image in a format we can decode.
+    NotAnImage,
+    /// The media couldn't be downloaded or decoded.
+    Failed(String),
+}
+
+impl fmt::Display for MediaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MediaError::TooLarge(reason) => write!(f, "Too large to preview: {}", reason),
+            MediaError::NotAnImage => write!(f, "Not a supported image"),
+            MediaError::Failed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<String> for MediaError {
+    fn from(e: String) -> Self {
+        MediaError::Failed(e)
+    }
+}
+
+/// A decoded image, ready to be displayed.
+#[derive(Debug, Clone)]
+pub struct DecodedImage {
+    pub handle: Handle,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Returns an error if `len` bytes is more than we're willing to download.
+pub fn check_download_size(len: u64) -> Result<(), MediaError> {
+    if len > MAX_DOWNLOAD_BYTES {
+        Err(MediaError::TooLarge(format!(
+            "{} bytes, the limit is {}",
+            len, MAX_DOWNLOAD_BYTES
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// Downloads an image and shrinks it to fit in a `max_width`x`max_height` box.
+///
+/// Images are shared between everything that shows them: concurrent requests for the
+/// same image are coalesced into one, and recently used images are kept in memory.
+pub async fn load_thumbnail(
+    url: String,
+    max_width: u32,
+    max_height: u32,
+) -> Result<DecodedImage, MediaError> {
+    cache::get_or_load((url.clone(), max_width, max_height), || {
+        fetch_thumbnail(url, max_width, max_height)
+    })
+    .await
+}
+
+/// Loads a thumbnail from the disk cache, or downloads and shrinks the original image.
+///
+/// Decoding and resizing happen on a blocking thread so the UI stays responsive, and
+/// the result is kept in a thumbnail cache on disk so only the small copy is ever
+/// held in memory. Thumbnails older than [`THUMBNAIL_MAX_AGE`] are rebuilt from the
+/// original, which is only downloaded again if the server says it changed. If it
+/// can't be reached, the old thumbnail is used.
+async fn fetch_thumbnail(
+    url: String,
+    max_width: u32,
+    max_height: u32,
+) -> Result<DecodedImage, MediaError> {
+    let cache_path = get_thumbnail_cache_path(&url, max_width, max_height)?;
+
+    let path = cache_path.clone();
+    let cached = run_blocking(move || {
+        let fresh = std::fs::metadata(&path)
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age < THUMBNAIL_MAX_AGE);
+        let data = std::fs::read(&path).ok()?;
+        decode(&data).ok().map(|image| (image, fresh))
+    })
+    .await?;
+
+    let stale = match cached {
+        Some((image, true)) => {
+            debug!("Using cached thumbnail for {}", url);
+            return Ok(into_decoded_image(image.into_rgba8()));
+        }
+        Some((image, false)) => Some(image),
+        None => None,
+    };
+
+    let bytes = match download_binary(url.clone()).await {
+        Ok(bytes) => bytes,
+        Err(e) => match stale {
+            Some(image) => {
+                warn!("Using old thumbnail for {}: {}", url, e);
+                return Ok(into_decoded_image(image.into_rgba8()));
+            }
+            None => return Err(e),
+        },
+    };
+
+    run_blocking(move || {
+        let image = decode(&bytes)?;
+        let image = if image.width() > max_width || image.height() > max_height {
+            image.resize(max_width, max_height, FilterType::Triangle)
+        } else {
+            image
+        };
+
+        let rgba = image.into_rgba8();
+        if let Err(e) = rgba.save_with_format(&cache_path, ImageFormat::Png) {
+            warn!("Failed to cache thumbnail for {}: {}", url, e);
+        }
+        if let Some(dir) = cache_path.parent() {
+            prune_thumbnails(dir, THUMBNAIL_DISK_BUDGET);
+        }
+
+        Ok(into_decoded_image(rgba))
+    })
+    .await?
+}
+
+/// Removes the oldest thumbnails in `dir` until they take up no more than `budget`
+/// bytes.
+fn prune_thumbnails(dir: &Path, budget: u64) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut files: Vec<_> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let meta = entry.metadata().ok().filter(|meta| meta.is_file())?;
+            Some((meta.modified().ok()?, meta.len(), entry.path()))
+        })
+        .collect();
+
+    let mut size: u64 = files.iter().map(|(_, len, _)| len).sum();
+    if size <= budget {
+        return;
+    }
+
+    files.sort_by_key(|(modified, _, _)| *modified);
+    for (_, len, path) in files {
+        if size <= budget {
+            break;
+        }
+        match std::fs::remove_file(&path) {
+            Ok(()) => size -= len,
+            Err(e) => warn!("Failed to remove thumbnail {}: {}", path.display(), e),
+        }
+    }
+}
+
+/// Downloads an avatar and shrinks it to [`AVATAR_THUMBNAIL_SIZE`].
+pub async fn avatar_thumbnail(url: String) -> Result<DecodedImage, MediaError> {
+    load_thumbnail(url, AVATAR_THUMBNAIL_SIZE, AVATAR_THUMBNAIL_SIZE).await
+}
+
+/// Runs a CPU-heavy closure on tokio's blocking thread pool.
+async fn run_blocking<T: Send + 'static>(
+    f: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, MediaError> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| MediaError::Failed(e.to_string()))
+}
+
+/// Hands iced the raw pixels, so it doesn't decode the image again without limits.
+fn into_decoded_image(rgba: RgbaImage) -> DecodedImage {
+    let (width, height) = rgba.dimensions();
+    DecodedImage {
+        handle: Handle::from_rgba(width, height, rgba.into_raw()),
+        width,
+        height,
+    }
+}
+
+/// Decodes an image, enforcing the dimension and memory limits.
+///
+/// The format is sniffed from the data itself rather than trusting the URL or the
+/// `Content-Type` header, so HTML error pages and the like are rejected early. Photos
+/// are turned the way their EXIF orientation says, since that's lost once the pixels
+/// are encoded again.
+pub fn decode(bytes: &[u8]) -> Result<DynamicImage, MediaError> {
+    let format = image::guess_format(bytes).map_err(|_| MediaError::NotAnImage)?;
+    if !SUPPORTED_FORMATS.contains(&format) {
+        return Err(MediaError::NotAnImage);
+    }
+
+    // Only the header is read here, so nothing is allocated for the pixels yet
+    let (width, height) = ImageReader::with_format(Cursor::new(bytes), format)
+        .into_dimensions()
+        .map_err(|e| MediaError::Failed(e.to_string()))?;
+
+    let decoded_size = u64::from(width) * u64::from(height) * 4;
+    if width > MAX_IMAGE_DIMENSION || height > MAX_IMAGE_DIMENSION {
+        return Err(MediaError::TooLarge(format!(
+            "{}x{} pixels, the limit is {}x{}",
+            width, height, MAX_IMAGE_DIMENSION, MAX_IMAGE_DIMENSION
+        )));
+    }
+    if decoded_size > MAX_DECODED_BYTES {
+        return Err(MediaError::TooLarge(format!(
+            "{}x{} pixels would take {} bytes to decode",
+            width, height, decoded_size
+        )));
+    }
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_IMAGE_DIMENSION);
+    limits.max_image_height = Some(MAX_IMAGE_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODED_BYTES);
+
+    let mut reader = ImageReader::with_format(Cursor::new(bytes), format);
+    reader.limits(limits);
+
+    let map_err = |e| match e {
+        ImageError::Limits(e) => MediaError::TooLarge(e.to_string()),
+        e => MediaError::Failed(e.to_string()),
+    };
+    let mut decoder = reader.into_decoder().map_err(map_err)?;
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut image = DynamicImage::from_decoder(decoder).map_err(map_err)?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+/// A box shown in place of media that was too large to preview.
+pub fn too_large_placeholder<'a, Message: 'a>(width: Length) -> Element<'a, Message> {
+    container(
+        text("Too large to preview")
+            .size(12)
+            .style(|theme: &Theme| text::Style {
+                color: Some(secondary_text(theme)),
+            }),
+    )
+    .width(width)
+    .padding(12)
+    .center_x(width)
+    .style(|theme: &Theme| {
+        let ext = theme.extended_palette();
+        container::Style {
+            background: Some(Background::Color(ext.background.strong.color)),
+            border: Border {
+                radius: Radius::from(8.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    })
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+    use crate::utils::test_server::temp_dir;
+
+    fn write_thumbnail(dir: &Path, name: &str, len: usize, age_secs: u64) {
+        let path = dir.join(name);
+        std::fs::write(&path, vec![0; len]).unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(age_secs))
+            .unwrap();
+    }
+
+    #[test]
+    fn prunes_oldest_thumbnails_over_budget() {
+        let dir = temp_dir("prune-thumbnails");
+        write_thumbnail(&dir, "oldest.png", 100, 300);
+        write_thumbnail(&dir, "older.png", 100, 200);
+        write_thumbnail(&dir, "newest.png", 100, 100);
+
+        prune_thumbnails(&dir, 200);
+
+        assert!(!dir.join("oldest.png").exists());
+        assert!(dir.join("older.png").exists());
+        assert!(dir.join("newest.png").exists());
+    }
+
+    #[test]
+    fn keeps_thumbnails_within_budget() {
+        let dir = temp_dir("keep-thumbnails");
+        write_thumbnail(&dir, "a.png", 100, 300);
+        write_thumbnail(&dir, "b.png", 100, 200);
+
+        prune_thumbnails(&dir, 200);
+
+        assert!(dir.join("a.png").exists());
+        assert!(dir.join("b.png").exists());
+    }
+}
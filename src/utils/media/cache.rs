@@ -0,0 +1,194 @@
+//! An in-memory cache of decoded images, shared by every component that shows them.
+//!
+//! A request for an image that's already loading waits for that load instead of
+//! starting another one, and recently used images are kept in memory up to a byte
+//! budget, so components that get rebuilt receive the same [`Handle`] straight away.
+//!
+//! [`Handle`]: iced::widget::image::Handle
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, LazyLock, Mutex, MutexGuard, PoisonError},
+};
+
+use tokio::sync::OnceCell;
+
+use super::{DecodedImage, MediaError};
+use crate::utils::lru::Lru;
+
+/// How much memory the decoded images kept around may take up.
+#[cfg(not(test))]
+const MEMORY_BUDGET: u64 = 128 * 1024 * 1024;
+#[cfg(test)]
+const MEMORY_BUDGET: u64 = 3 * 16 * 16 * 4;
+
+/// An image URL and the size of the box it was shrunk to fit.
+type Key = (String, u32, u32);
+
+/// A load that's in progress, which every request for the same image waits on.
+type InFlight = Arc<OnceCell<Result<DecodedImage, MediaError>>>;
+
+static CACHE: LazyLock<Mutex<MediaCache>> = LazyLock::new(Default::default);
+
+fn lock() -> MutexGuard<'static, MediaCache> {
+    // The cache is always left in a consistent state, so a panic elsewhere is harmless
+    CACHE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+struct MediaCache {
+    loaded: Lru<Key, DecodedImage>,
+    in_flight: HashMap<Key, InFlight>,
+}
+
+impl Default for MediaCache {
+    fn default() -> Self {
+        Self {
+            loaded: Lru::new(MEMORY_BUDGET),
+            in_flight: HashMap::new(),
+        }
+    }
+}
+
+impl MediaCache {
+    fn get(&mut self, key: &Key) -> Option<DecodedImage> {
+        self.loaded.get(key)
+    }
+
+    fn insert(&mut self, key: Key, image: DecodedImage) {
+        let size = image_size(&image);
+        self.loaded.insert(key, image, size);
+    }
+}
+
+fn image_size(image: &DecodedImage) -> u64 {
+    u64::from(image.width) * u64::from(image.height) * 4
+}
+
+/// Returns the cached image for `key`, or loads it with `load`.
+///
+/// If the same image is already being loaded, this waits for that load to finish
+/// instead. Failures aren't cached, so the next request tries again.
+pub async fn get_or_load<F, Fut>(key: Key, load: F) -> Result<DecodedImage, MediaError>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<DecodedImage, MediaError>>,
+{
+    let in_flight = {
+        let mut cache = lock();
+        if let Some(image) = cache.get(&key) {
+            return Ok(image);
+        }
+        cache.in_flight.entry(key.clone()).or_default().clone()
+    };
+
+    let result = in_flight.get_or_init(load).await.clone();
+
+    let mut cache = lock();
+    let is_current = cache
+        .in_flight
+        .get(&key)
+        .is_some_and(|current| Arc::ptr_eq(current, &in_flight));
+    if is_current {
+        cache.in_flight.remove(&key);
+        if let Ok(image) = &result {
+            cache.insert(key, image.clone());
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use iced::{futures::future::join, widget::image::Handle};
+
+    use super::*;
+    use crate::utils::test_server::block_on;
+
+    fn image(size: u32) -> DecodedImage {
+        DecodedImage {
+            handle: Handle::from_rgba(size, size, vec![0; (size * size * 4) as usize]),
+            width: size,
+            height: size,
+        }
+    }
+
+    fn key(url: &str) -> Key {
+        (url.to_string(), 16, 16)
+    }
+
+    /// A loader that counts how often it's called and takes a moment to finish.
+    async fn load(
+        calls: &AtomicUsize,
+        result: Result<DecodedImage, MediaError>,
+    ) -> Result<DecodedImage, MediaError> {
+        calls.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        result
+    }
+
+    #[test]
+    fn coalesces_concurrent_loads() {
+        let calls = AtomicUsize::new(0);
+        let key = key("test://coalesced");
+
+        let (first, second) = block_on(join(
+            get_or_load(key.clone(), || load(&calls, Ok(image(8)))),
+            get_or_load(key.clone(), || load(&calls, Ok(image(8)))),
+        ));
+        assert_eq!(first.unwrap().width, 8);
+        assert_eq!(second.unwrap().width, 8);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Later requests are served from memory
+        block_on(get_or_load(key, || load(&calls, Ok(image(8))))).unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn does_not_cache_failures() {
+        let calls = AtomicUsize::new(0);
+        let key = key("test://failing");
+        let failed = || Err(MediaError::Failed("offline".to_string()));
+
+        let (first, second) = block_on(join(
+            get_or_load(key.clone(), || load(&calls, failed())),
+            get_or_load(key.clone(), || load(&calls, failed())),
+        ));
+        assert!(matches!(first, Err(MediaError::Failed(e)) if e == "offline"));
+        assert!(second.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let retried = block_on(get_or_load(key, || load(&calls, Ok(image(8)))));
+        assert_eq!(retried.unwrap().width, 8);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn evicts_images_by_their_decoded_size() {
+        let mut cache = MediaCache::default();
+        for url in ["a", "b", "c"] {
+            cache.insert(key(url), image(16));
+        }
+        assert_eq!(cache.loaded.cost(), MEMORY_BUDGET);
+
+        cache.get(&key("a")).unwrap();
+        cache.insert(key("d"), image(16));
+        assert!(cache.get(&key("b")).is_none());
+        for url in ["a", "c", "d"] {
+            assert!(cache.get(&key(url)).is_some(), "{} was evicted", url);
+        }
+
+        // Two small images fit in the space of the one they replace
+        cache.insert(key("e"), image(8));
+        cache.insert(key("f"), image(8));
+        assert!(cache.get(&key("a")).is_none());
+        assert_eq!(cache.loaded.cost(), 2 * 16 * 16 * 4 + 2 * 8 * 8 * 4);
+    }
+}
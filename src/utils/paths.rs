@@ -1,7 +1,8 @@
 //! Module for handling file paths used in the twtGUI application.
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+use crate::twtxt::feed_id::FeedId;
 use crate::utils::hash::hash_sha256_str;
 use directories::ProjectDirs;
 
@@ -19,7 +20,10 @@ pub fn cache_root() -> Result<PathBuf, String> {
     Ok(dir.to_path_buf())
 }
 
-pub fn log_root() -> Result<PathBuf, String> {
+/// Returns the root directory used for persistent application data.
+///
+/// Ensures the directory exists and returns an error string if it cannot be created.
+pub fn data_root() -> Result<PathBuf, String> {
     let proj = ProjectDirs::from("com", "taxevaiden", "twtGUI")
         .ok_or("Could not determine project directories")?;
 
@@ -30,17 +34,31 @@ pub fn log_root() -> Result<PathBuf, String> {
     Ok(dir.to_path_buf())
 }
 
-/// Returns the cache path for storing the raw twtxt content for a given URL.
-pub fn get_txt_cache_path(url: &str) -> Result<PathBuf, String> {
-    let hash = hash_sha256_str(url);
+pub fn log_root() -> Result<PathBuf, String> {
+    data_root()
+}
+
+/// Returns the path of the file storing pinned Gemini server certificates.
+pub fn get_gemini_known_hosts_path() -> Result<PathBuf, String> {
+    let mut path = data_root()?;
+    path.push("gemini_known_hosts.json");
+    Ok(path)
+}
+
+/// Returns the cache path for storing the raw twtxt content of a feed.
+pub fn get_txt_cache_path(feed_id: &FeedId) -> Result<PathBuf, String> {
+    let hash = hash_sha256_str(feed_id.as_str());
     let mut path = cache_root()?;
     path.push(format!("{hash}.json"));
     Ok(path)
 }
 
 /// Returns the cache file paths used for binary downloads (data + metadata).
+///
+/// The URL is normalized the same way as a [`FeedId`], so spellings of the same URL
+/// share their cache.
 pub fn get_bin_cache_paths(url: &str) -> Result<(PathBuf, PathBuf), String> {
-    let hash = hash_sha256_str(url);
+    let hash = hash_sha256_str(FeedId::from_url(url).as_str());
     let dir = cache_root()?;
 
     let mut data_path = dir.clone();
@@ -53,9 +71,61 @@ pub fn get_bin_cache_paths(url: &str) -> Result<(PathBuf, PathBuf), String> {
 }
 
 /// Returns the cache path for a parsed twtxt bundle (used to avoid re-parsing when unchanged).
-pub fn get_parsed_cache_path(url: &str) -> Result<PathBuf, String> {
-    let hash = hash_sha256_str(url);
+pub fn get_parsed_cache_path(feed_id: &FeedId) -> Result<PathBuf, String> {
+    let hash = hash_sha256_str(feed_id.as_str());
     let mut path = cache_root()?;
     path.push(format!("{hash}.parsed.json"));
     Ok(path)
 }
+
+/// Returns the cache path for a downscaled copy of an image that fits in a
+/// `width`x`height` box.
+pub fn get_thumbnail_cache_path(url: &str, width: u32, height: u32) -> Result<PathBuf, String> {
+    let hash = hash_sha256_str(url);
+    let mut path = cache_root()?;
+    path.push("thumbnails");
+    std::fs::create_dir_all(&path).map_err(|e| e.to_string())?;
+    path.push(format!("{hash}_{width}x{height}.png"));
+    Ok(path)
+}
+
+/// Returns the cache path for the OpenGraph metadata extracted from a page.
+pub fn get_opengraph_cache_path(url: &str) -> Result<PathBuf, String> {
+    let hash = hash_sha256_str(url);
+    let mut path = cache_root()?;
+    path.push("opengraph");
+    std::fs::create_dir_all(&path).map_err(|e| e.to_string())?;
+    path.push(format!("{hash}.json"));
+    Ok(path)
+}
+
+/// Returns the path the previous version of a rewritten local file is backed up to.
+///
+/// Backups are kept in the app's data directory rather than next to the file, so
+/// they aren't published along with the user's feed.
+pub fn get_backup_path(file: &Path) -> Result<PathBuf, String> {
+    let name = file
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let hash = hash_sha256_str(&file.to_string_lossy());
+    let mut path = data_root()?;
+    path.push("backups");
+    std::fs::create_dir_all(&path).map_err(|e| e.to_string())?;
+    path.push(format!("{}_{name}.bak", &hash[..16]));
+    Ok(path)
+}
+
+/// Returns the path of the file storing the composer's unsent drafts.
+pub fn get_drafts_path() -> Result<PathBuf, String> {
+    let mut path = data_root()?;
+    path.push("drafts.json");
+    Ok(path)
+}
+
+/// Returns the path of the file storing posts scheduled for later.
+pub fn get_schedule_path() -> Result<PathBuf, String> {
+    let mut path = data_root()?;
+    path.push("scheduled.json");
+    Ok(path)
+}
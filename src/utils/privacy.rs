@@ -0,0 +1,98 @@
+//! Module for deciding which remote media may be loaded without asking first.
+
+use std::collections::{HashMap, HashSet};
+
+use reqwest::Url;
+
+use crate::config::{AppConfig, MediaPolicy};
+use crate::twtxt::feed_id::FeedId;
+
+/// Decides whether media referenced by a feed may be downloaded automatically,
+/// based on the user's privacy settings.
+#[derive(Debug, Clone, Default)]
+pub struct MediaFilter {
+    policy: MediaPolicy,
+    overrides: HashMap<FeedId, MediaPolicy>,
+    /// Hosts of the user's own feed and every followed feed.
+    followed_hosts: HashSet<String>,
+}
+
+impl MediaFilter {
+    pub fn new(config: &AppConfig) -> Self {
+        let followed_hosts = config
+            .metadata
+            .follows
+            .iter()
+            .map(|link| link.url.as_str())
+            .chain(config.metadata.urls.iter().map(String::as_str))
+            .filter_map(host_of)
+            .collect();
+
+        Self {
+            policy: config.privacy.media,
+            overrides: config
+                .privacy
+                .media_overrides
+                .iter()
+                .map(|(url, policy)| (FeedId::from_url(url), *policy))
+                .collect(),
+            followed_hosts,
+        }
+    }
+
+    /// Returns the media policy that applies to a feed.
+    ///
+    /// Overrides are looked up by feed rather than by URL, so they also apply to the
+    /// feed's archives and to the feed when it's fetched from another of its URLs.
+    pub fn policy_for(&self, feed: &FeedId) -> MediaPolicy {
+        self.overrides.get(feed).copied().unwrap_or(self.policy)
+    }
+
+    /// Returns whether `media_url`, referenced by `feed`, may be downloaded without
+    /// the user asking for it.
+    pub fn allows(&self, feed: &FeedId, media_url: &str) -> bool {
+        // Local files don't leak anything
+        if media_url.starts_with("file://") {
+            return true;
+        }
+
+        match self.policy_for(feed) {
+            MediaPolicy::LoadAll => true,
+            MediaPolicy::FollowedDomains => {
+                host_of(media_url).is_some_and(|host| self.followed_hosts.contains(&host))
+            }
+            MediaPolicy::ClickToLoad => false,
+        }
+    }
+}
+
+fn host_of(url: &str) -> Option<String> {
+    Url::parse(url)
+        .ok()?
+        .host_str()
+        .map(|host| host.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_apply_to_the_feed_whatever_url_it_came_from() {
+        let mut config = AppConfig::default();
+        config.privacy.media = MediaPolicy::LoadAll;
+        config.privacy.media_overrides.insert(
+            "HTTPS://Example.com:443/twtxt.txt".to_string(),
+            MediaPolicy::ClickToLoad,
+        );
+        let filter = MediaFilter::new(&config);
+
+        let feed = FeedId::from_url("https://example.com/twtxt.txt");
+        assert_eq!(filter.policy_for(&feed), MediaPolicy::ClickToLoad);
+        assert!(!filter.allows(&feed, "https://example.com/avatar.png"));
+        assert!(filter.allows(&feed, "file:///home/me/avatar.png"));
+
+        let other = FeedId::from_url("https://example.org/twtxt.txt");
+        assert!(filter.allows(&other, "https://example.com/avatar.png"));
+    }
+}